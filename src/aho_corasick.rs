@@ -0,0 +1,187 @@
+use std::collections::VecDeque;
+
+use crate::horspool::Horspool;
+use crate::regex_parser::Node;
+use crate::stats::Stats;
+
+/// Aho-Corasick automaton, to search several strings at once in a single pass.
+/// The strings are stored in a trie of their UTF-8 bytes. When the next byte has
+/// no transition, the failure link leads to the state of the longest suffix of
+/// the bytes read that is also a prefix of a string, so no byte is read twice.
+#[derive(Debug, Clone)]
+pub struct AhoCorasick {
+    /// transitions of each state, sorted by byte
+    transitions: Vec<Vec<(u8, usize)>>,
+    failures: Vec<usize>,
+    /// a string ends at the state, or at a state reachable by failure links
+    is_final: Vec<bool>,
+    stats: Stats,
+}
+
+impl AhoCorasick {
+    pub fn new(patterns: &[Vec<u8>]) -> Self {
+        let mut transitions: Vec<Vec<(u8, usize)>> = vec![Vec::new()];
+        let mut is_final = vec![false];
+
+        for pattern in patterns {
+            let mut state = 0;
+            for &byte in pattern {
+                state = match transitions[state].binary_search_by_key(&byte, |(b, _)| *b) {
+                    Ok(i) => transitions[state][i].1,
+                    Err(i) => {
+                        transitions.push(Vec::new());
+                        is_final.push(false);
+                        let next = transitions.len() - 1;
+                        transitions[state].insert(i, (byte, next));
+                        next
+                    }
+                };
+            }
+            is_final[state] = true;
+        }
+
+        // breadth first, so the failure link of a state is computed before
+        // the ones of its children
+        let mut failures = vec![0; transitions.len()];
+        let mut queue = transitions[0]
+            .iter()
+            .map(|(_, next)| *next)
+            .collect::<VecDeque<_>>();
+        while let Some(state) = queue.pop_front() {
+            for i in 0..transitions[state].len() {
+                let (byte, child) = transitions[state][i];
+                let mut fallback = failures[state];
+                let failure = loop {
+                    if let Some(next) = Self::transition(&transitions[fallback], byte) {
+                        break next;
+                    }
+                    if fallback == 0 {
+                        break 0;
+                    }
+                    fallback = failures[fallback];
+                };
+                failures[child] = failure;
+                is_final[child] |= is_final[failure];
+                queue.push_back(child);
+            }
+        }
+
+        Self {
+            transitions,
+            failures,
+            is_final,
+            stats: Stats::default(),
+        }
+    }
+
+    /// Returns None if the pattern is not an alternation of plain strings
+    pub fn from_node(node: &Node) -> Option<Self> {
+        let mut patterns = Vec::new();
+        if Self::collect_alternatives(node, &mut patterns) && patterns.len() > 1 {
+            Some(Self::new(&patterns))
+        } else {
+            None
+        }
+    }
+
+    fn collect_alternatives(node: &Node, patterns: &mut Vec<Vec<u8>>) -> bool {
+        match node {
+            Node::Group { nodes, .. } if matches!(nodes.as_slice(), [Node::Or { .. }]) => {
+                Self::collect_alternatives(&nodes[0], patterns)
+            }
+            Node::Or { nodes } => nodes
+                .iter()
+                .all(|node| Self::collect_alternatives(node, patterns)),
+            _ => {
+                let mut chars = Vec::new();
+                if !Horspool::collect_literals(node, &mut chars) {
+                    return false;
+                }
+                patterns.push(chars.iter().collect::<String>().into_bytes());
+                true
+            }
+        }
+    }
+
+    fn transition(transitions: &[(u8, usize)], byte: u8) -> Option<usize> {
+        transitions
+            .binary_search_by_key(&byte, |(b, _)| *b)
+            .ok()
+            .map(|i| transitions[i].1)
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub fn is_match(&mut self, haystack: &[u8]) -> bool {
+        if self.is_final[0] {
+            return true;
+        }
+        let mut state = 0;
+        for (i, &byte) in haystack.iter().enumerate() {
+            state = loop {
+                if let Some(next) = Self::transition(&self.transitions[state], byte) {
+                    break next;
+                }
+                if state == 0 {
+                    break 0;
+                }
+                state = self.failures[state];
+            };
+            if self.is_final[state] {
+                self.stats.bytes_scanned += i + 1;
+                return true;
+            }
+        }
+        self.stats.bytes_scanned += haystack.len();
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::{regex_lexer::RegexLexer, regex_parser::RegexParser};
+
+    use super::*;
+
+    #[rstest]
+    #[case("ab|cd", true)]
+    #[case("a|b|(cd)", true)]
+    #[case("abc", false)]
+    #[case("a|b.", false)]
+    #[case("(a|b)c", false)]
+    fn test_aho_corasick_support(#[case] pat: &str, #[case] expected: bool) -> anyhow::Result<()> {
+        let lexer = RegexLexer::new(pat);
+        let mut parser = RegexParser::new(lexer)?;
+        let node = parser.build_ast(0)?;
+
+        assert_eq!(AhoCorasick::from_node(&node).is_some(), expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(&["he", "she", "his", "hers"], "ushers", true)]
+    #[case(&["he", "she", "his", "hers"], "ahisa", true)]
+    #[case(&["he", "she", "his", "hers"], "hshis", true)]
+    #[case(&["he", "she", "his", "hers"], "shhi", false)]
+    #[case(&["abcd", "bc"], "abce", true)]
+    #[case(&["abcd", "bcx"], "abcx", true)]
+    #[case(&["été", "hiver"], "un été", true)]
+    #[case(&["a", ""], "", true)]
+    #[case(&["a", "b"], "", false)]
+    fn test_aho_corasick(
+        #[case] patterns: &[&str],
+        #[case] haystack: &str,
+        #[case] expected: bool,
+    ) {
+        let patterns = patterns
+            .iter()
+            .map(|p| p.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let mut aho_corasick = AhoCorasick::new(&patterns);
+        assert_eq!(aho_corasick.is_match(haystack.as_bytes()), expected);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::json;
+
+/// Start of the SARIF document of --format sarif, followed by the results
+pub const SARIF_START: &str = concat!(
+    r#"{"version":"2.1.0","$schema":"https://json.schemastore.org/sarif-2.1.0.json","#,
+    r#""runs":[{"tool":{"driver":{"name":"grep"}},"columnKind":"unicodeCodePoints","results":["#
+);
+
+/// End of the SARIF document, after the results
+pub const SARIF_END: &str = "]}]}\n";
+
+/// Selected line reported to a CI pipeline (--format github or sarif)
+#[derive(Debug, Clone, Copy)]
+pub struct Annotation<'a> {
+    pub file: &'a str,
+    pub line: usize,
+    /// 1-based columns in chars of the match, end excluded
+    pub start_column: usize,
+    pub end_column: usize,
+    pub message: &'a str,
+}
+
+impl Annotation<'_> {
+    /// GitHub Actions workflow command, shown as a warning on the line
+    pub fn github(&self) -> String {
+        format!(
+            "::warning file={},line={},col={},endColumn={}::{}",
+            escape_property(self.file),
+            self.line,
+            self.start_column,
+            self.end_column,
+            escape_data(self.message)
+        )
+    }
+
+    /// SARIF result, to put in the results of SARIF_START
+    pub fn sarif(&self) -> String {
+        format!(
+            r#"{{"level":"warning","message":{{"text":{}}},"locations":[{{"physicalLocation":{{"artifactLocation":{{"uri":{}}},"region":{{"startLine":{},"startColumn":{},"endColumn":{}}}}}}}]}}"#,
+            json::string(self.message),
+            json::string(self.file),
+            self.line,
+            self.start_column,
+            self.end_column
+        )
+    }
+}
+
+/// Message of a workflow command, which ends at the end of the line
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Value of a property of a workflow command, which also ends at a , or ::
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANNOTATION: Annotation = Annotation {
+        file: "src/a,b.rs",
+        line: 3,
+        start_column: 5,
+        end_column: 9,
+        message: "let x = \"100%\";",
+    };
+
+    #[test]
+    fn test_github() {
+        assert_eq!(
+            ANNOTATION.github(),
+            r#"::warning file=src/a%2Cb.rs,line=3,col=5,endColumn=9::let x = "100%25";"#
+        );
+    }
+
+    #[test]
+    fn test_sarif() {
+        assert_eq!(
+            ANNOTATION.sarif(),
+            r#"{"level":"warning","message":{"text":"let x = \"100%\";"},"locations":[{"physicalLocation":{"artifactLocation":{"uri":"src/a,b.rs"},"region":{"startLine":3,"startColumn":5,"endColumn":9}}}]}"#
+        );
+    }
+}
@@ -0,0 +1,578 @@
+//! JSON form of the AST and of a Regex, so that tooling can exchange parsed
+//! patterns. The variants of Node are written as serde does by default: the
+//! name of a unit variant as a string, another variant as an object with its
+//! name as only key, such as {"Literal":"a"} or {"BackRef":{"group":1,...}}
+
+use std::fmt::Write;
+
+use crate::char_class::{CharClass, ClassRange};
+use crate::error::RegexError;
+use crate::regex::Regex;
+use crate::regex_parser::Node;
+
+/// Error of the reading of a Node or a Regex from JSON
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum JsonError {
+    /// the text is not JSON, pos being the byte offset where reading stopped
+    #[error("Invalid JSON at byte {pos}")]
+    Syntax { pos: usize },
+    /// valid JSON that does not describe a node, such as an unknown variant
+    #[error("Invalid node: {reason}")]
+    InvalidNode { reason: String },
+    /// the pattern read for a Regex does not compile
+    #[error(transparent)]
+    Regex(#[from] RegexError),
+}
+
+fn invalid(reason: impl Into<String>) -> JsonError {
+    JsonError::InvalidNode {
+        reason: reason.into(),
+    }
+}
+
+/// Writes the JSON string literal of the text, with its quotes
+fn write_string(json: &mut String, text: &str) {
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+fn write_char(json: &mut String, c: char) {
+    write_string(json, c.encode_utf8(&mut [0; 4]));
+}
+
+fn write_nodes(json: &mut String, nodes: &[Node]) {
+    json.push('[');
+    for (i, node) in nodes.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        node.write_json(json);
+    }
+    json.push(']');
+}
+
+impl Node {
+    /// JSON of the AST, which Node::from_json reads back
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        self.write_json(&mut json);
+        json
+    }
+
+    /// AST written by Node::to_json
+    pub fn from_json(json: &str) -> Result<Node, JsonError> {
+        node(&JsonReader::parse(json)?)
+    }
+
+    fn write_json(&self, json: &mut String) {
+        match self {
+            Node::Or { nodes } => {
+                json.push_str("{\"Or\":{\"nodes\":");
+                write_nodes(json, nodes);
+                json.push_str("}}");
+            }
+            Node::Class(class) => {
+                json.push_str("{\"Class\":{\"ranges\":[");
+                for (i, range) in class.ranges().iter().enumerate() {
+                    if i > 0 {
+                        json.push(',');
+                    }
+                    json.push_str("{\"start\":");
+                    write_char(json, range.start);
+                    json.push_str(",\"end\":");
+                    write_char(json, range.end);
+                    json.push('}');
+                }
+                json.push_str("]}}");
+            }
+            Node::Literal(c) => {
+                json.push_str("{\"Literal\":");
+                write_char(json, *c);
+                json.push('}');
+            }
+            Node::Group { nodes, group_ref } => {
+                json.push_str("{\"Group\":{\"nodes\":");
+                write_nodes(json, nodes);
+                let _ = write!(json, ",\"group_ref\":{group_ref}}}}}");
+            }
+            Node::Quantifier {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                json.push_str("{\"Quantifier\":{\"node\":");
+                node.write_json(json);
+                let max = max.map_or("null".to_string(), |max| max.to_string());
+                let _ = write!(json, ",\"min\":{min},\"max\":{max},\"greedy\":{greedy}}}}}");
+            }
+            Node::Named { name, group } => {
+                json.push_str("{\"Named\":{\"name\":");
+                write_string(json, name);
+                json.push_str(",\"group\":");
+                group.write_json(json);
+                json.push_str("}}");
+            }
+            Node::BackRef { group, ignore_case } => {
+                let _ = write!(
+                    json,
+                    "{{\"BackRef\":{{\"group\":{group},\"ignore_case\":{ignore_case}}}}}"
+                );
+            }
+            Node::LookAround {
+                node,
+                ahead,
+                negated,
+            } => {
+                json.push_str("{\"LookAround\":{\"node\":");
+                node.write_json(json);
+                let _ = write!(json, ",\"ahead\":{ahead},\"negated\":{negated}}}}}");
+            }
+            // the unit variants, whose Debug is their name
+            node => {
+                let _ = write!(json, "\"{node:?}\"");
+            }
+        }
+    }
+}
+
+impl Regex {
+    /// JSON string of the pattern, which Regex::from_json compiles back
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        write_string(&mut json, self.as_str());
+        json
+    }
+
+    /// Regex compiled from a JSON string holding its pattern, as found in a
+    /// configuration file
+    pub fn from_json(json: &str) -> Result<Regex, JsonError> {
+        match JsonReader::parse(json)? {
+            Value::String(pattern) => Ok(Regex::new(&pattern)?),
+            _ => Err(invalid("expected the pattern as a string")),
+        }
+    }
+}
+
+/// JSON value, where a number keeps its text until a field needs it
+#[derive(Debug, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+/// Reads a JSON document into a Value
+struct JsonReader<'a> {
+    json: &'a str,
+    pos: usize,
+}
+
+impl<'a> JsonReader<'a> {
+    /// Value of the whole text, which may be surrounded by whitespace
+    fn parse(json: &'a str) -> Result<Value, JsonError> {
+        let mut reader = Self { json, pos: 0 };
+        let value = reader.value()?;
+        reader.skip_whitespace();
+        if reader.pos < json.len() {
+            return Err(reader.error());
+        }
+        Ok(value)
+    }
+
+    fn error(&self) -> JsonError {
+        JsonError::Syntax { pos: self.pos }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.json[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    /// Consumes c, after whitespace
+    fn expect(&mut self, c: char) -> Result<(), JsonError> {
+        self.skip_whitespace();
+        if self.peek() != Some(c) {
+            return Err(self.error());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Value, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n') => self.keyword("null", Value::Null),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some('-' | '0'..='9') => Ok(self.number()),
+            _ => Err(self.error()),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> Result<Value, JsonError> {
+        if !self.json[self.pos..].starts_with(keyword) {
+            return Err(self.error());
+        }
+        self.pos += keyword.len();
+        Ok(value)
+    }
+
+    /// Text of the number, checked when it is converted
+    fn number(&mut self) -> Value {
+        let start = self.pos;
+        while let Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9') = self.peek() {
+            self.pos += 1;
+        }
+        Value::Number(self.json[start..self.pos].to_string())
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.next().ok_or_else(|| self.error())? {
+                '"' => return Ok(text),
+                '\\' => {
+                    let c = match self.next().ok_or_else(|| self.error())? {
+                        c @ ('"' | '\\' | '/') => c,
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => self.unicode_escape()?,
+                        _ => return Err(self.error()),
+                    };
+                    text.push(c);
+                }
+                c if c < ' ' => return Err(self.error()),
+                c => text.push(c),
+            }
+        }
+    }
+
+    /// Char of \uXXXX, the \u being read, or of a surrogate pair \uXXXX\uXXXX
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error());
+        }
+        if !self.json[self.pos..].starts_with("\\u") {
+            return Err(self.error());
+        }
+        self.pos += 2;
+        let low = self.hex4()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err(self.error());
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+            .ok_or_else(|| self.error())
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self.json.get(self.pos..self.pos + 4).ok_or(self.error())?;
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(self.error());
+        }
+        self.pos += 4;
+        u32::from_str_radix(digits, 16).map_err(|_| self.error())
+    }
+
+    /// Reads the items separated by commas up to close, after the opening char
+    fn items(
+        &mut self,
+        close: char,
+        mut item: impl FnMut(&mut Self) -> Result<(), JsonError>,
+    ) -> Result<(), JsonError> {
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(close) {
+            self.pos += 1;
+            return Ok(());
+        }
+        loop {
+            item(self)?;
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => {}
+                Some(c) if c == close => return Ok(()),
+                _ => return Err(self.error()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, JsonError> {
+        let mut values = Vec::new();
+        self.items(']', |reader| {
+            values.push(reader.value()?);
+            Ok(())
+        })?;
+        Ok(Value::Array(values))
+    }
+
+    fn object(&mut self) -> Result<Value, JsonError> {
+        let mut fields = Vec::new();
+        self.items('}', |reader| {
+            reader.skip_whitespace();
+            let key = reader.string()?;
+            reader.expect(':')?;
+            fields.push((key, reader.value()?));
+            Ok(())
+        })?;
+        Ok(Value::Object(fields))
+    }
+}
+
+/// Value of the field of the object
+fn field<'v>(value: &'v Value, name: &str) -> Result<&'v Value, JsonError> {
+    let Value::Object(fields) = value else {
+        return Err(invalid("expected an object"));
+    };
+    fields
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value)
+        .ok_or_else(|| invalid(format!("missing field {name}")))
+}
+
+fn number(value: &Value) -> Result<usize, JsonError> {
+    match value {
+        Value::Number(text) => text
+            .parse()
+            .map_err(|_| invalid(format!("{text} is not a count"))),
+        _ => Err(invalid("expected a number")),
+    }
+}
+
+fn boolean(value: &Value) -> Result<bool, JsonError> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        _ => Err(invalid("expected a boolean")),
+    }
+}
+
+fn string(value: &Value) -> Result<&str, JsonError> {
+    match value {
+        Value::String(text) => Ok(text),
+        _ => Err(invalid("expected a string")),
+    }
+}
+
+/// Char of a string holding exactly one
+fn single_char(value: &Value) -> Result<char, JsonError> {
+    let text = string(value)?;
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(invalid(format!("{text:?} is not a single char"))),
+    }
+}
+
+fn nodes(value: &Value) -> Result<Vec<Node>, JsonError> {
+    match value {
+        Value::Array(values) => values.iter().map(node).collect(),
+        _ => Err(invalid("expected an array of nodes")),
+    }
+}
+
+fn class(value: &Value) -> Result<CharClass, JsonError> {
+    let Value::Array(values) = field(value, "ranges")? else {
+        return Err(invalid("expected an array of ranges"));
+    };
+    let mut ranges = Vec::with_capacity(values.len());
+    for value in values {
+        let start = single_char(field(value, "start")?)?;
+        let end = single_char(field(value, "end")?)?;
+        if end < start {
+            return Err(invalid(format!("reversed range {start}-{end}")));
+        }
+        ranges.push(ClassRange::new(start, end));
+    }
+    Ok(CharClass::new(ranges))
+}
+
+fn node(value: &Value) -> Result<Node, JsonError> {
+    let (variant, fields) = match value {
+        Value::String(variant) => {
+            return match variant.as_str() {
+                "StartAnchor" => Ok(Node::StartAnchor),
+                "EndAnchor" => Ok(Node::EndAnchor),
+                "NoWordBefore" => Ok(Node::NoWordBefore),
+                "NoWordAfter" => Ok(Node::NoWordAfter),
+                "Digit" => Ok(Node::Digit),
+                "Alphanum" => Ok(Node::Alphanum),
+                "Wildcard" => Ok(Node::Wildcard),
+                _ => Err(invalid(format!("unknown variant {variant}"))),
+            };
+        }
+        Value::Object(fields) if fields.len() == 1 => (fields[0].0.as_str(), &fields[0].1),
+        _ => return Err(invalid("expected a variant")),
+    };
+    match variant {
+        "Or" => Ok(Node::Or {
+            nodes: nodes(field(fields, "nodes")?)?,
+        }),
+        "Class" => Ok(Node::Class(class(fields)?)),
+        "Literal" => Ok(Node::Literal(single_char(fields)?)),
+        "Group" => Ok(Node::Group {
+            nodes: nodes(field(fields, "nodes")?)?,
+            group_ref: number(field(fields, "group_ref")?)?,
+        }),
+        "Quantifier" => {
+            let min = number(field(fields, "min")?)?;
+            let max = match field(fields, "max")? {
+                Value::Null => None,
+                max => Some(number(max)?),
+            };
+            if let Some(max) = max.filter(|&max| max < min) {
+                return Err(invalid(format!("reversed quantifier {{{min},{max}}}")));
+            }
+            Ok(Node::Quantifier {
+                node: Box::new(node(field(fields, "node")?)?),
+                min,
+                max,
+                greedy: boolean(field(fields, "greedy")?)?,
+            })
+        }
+        "Named" => Ok(Node::Named {
+            name: string(field(fields, "name")?)?.to_string(),
+            group: Box::new(node(field(fields, "group")?)?),
+        }),
+        "BackRef" => Ok(Node::BackRef {
+            group: number(field(fields, "group")?)?,
+            ignore_case: boolean(field(fields, "ignore_case")?)?,
+        }),
+        "LookAround" => Ok(Node::LookAround {
+            node: Box::new(node(field(fields, "node")?)?),
+            ahead: boolean(field(fields, "ahead")?)?,
+            negated: boolean(field(fields, "negated")?)?,
+        }),
+        _ => Err(invalid(format!("unknown variant {variant}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+    use crate::regex::RegexBuilder;
+    use crate::regex_lexer::Dialect;
+
+    #[rstest]
+    #[case(Node::Literal('"'), r#"{"Literal":"\""}"#)]
+    #[case(Node::Wildcard, r#""Wildcard""#)]
+    #[case(
+        Node::Class(CharClass::new(vec![ClassRange::new('a', 'c')])),
+        r#"{"Class":{"ranges":[{"start":"a","end":"c"}]}}"#
+    )]
+    #[case(
+        Node::Quantifier { node: Box::new(Node::Digit), min: 1, max: None, greedy: false },
+        r#"{"Quantifier":{"node":"Digit","min":1,"max":null,"greedy":false}}"#
+    )]
+    #[case(
+        Node::BackRef { group: 2, ignore_case: true },
+        r#"{"BackRef":{"group":2,"ignore_case":true}}"#
+    )]
+    fn test_to_json(#[case] node: Node, #[case] expected: &str) {
+        assert_eq!(node.to_json(), expected);
+    }
+
+    #[rstest]
+    #[case("^(a|b\\d)+?c$", Dialect::Perl)]
+    #[case("(?<year>\\d{4})-(?!00)\\d{2,}\\1", Dialect::Perl)]
+    #[case("(?<=\\w)[^\\n\"\\\\é-ü]*.", Dialect::Perl)]
+    #[case("\\(a*\\)\\{1,3\\}\\1", Dialect::Basic)]
+    #[case("", Dialect::Extended)]
+    fn test_json_round_trip(#[case] pattern: &str, #[case] dialect: Dialect) {
+        let ast = RegexBuilder::new(pattern)
+            .dialect(dialect)
+            .build_ast()
+            .unwrap();
+        assert_eq!(Node::from_json(&ast.to_json()), Ok(ast));
+    }
+
+    #[test]
+    fn test_from_json_whitespace_and_escapes() {
+        let json = r#" { "Group" : { "group_ref" : 0 ,
+            "nodes" : [ { "Literal" : "é" }, { "Literal": "😀" } ] } } "#;
+        let expected = Node::Group {
+            nodes: vec![Node::Literal('é'), Node::Literal('😀')],
+            group_ref: 0,
+        };
+        assert_eq!(Node::from_json(json), Ok(expected));
+    }
+
+    #[rstest]
+    #[case(r#"{"Literal":"a""#, JsonError::Syntax { pos: 14 })]
+    #[case(r#""Wildcard" x"#, JsonError::Syntax { pos: 11 })]
+    #[case(r#"[1,]"#, JsonError::Syntax { pos: 3 })]
+    #[case(r#""Star""#, invalid("unknown variant Star"))]
+    #[case(r#"{"Literal":"ab"}"#, invalid("\"ab\" is not a single char"))]
+    #[case(
+        r#"{"BackRef":{"group":-1,"ignore_case":false}}"#,
+        invalid("-1 is not a count")
+    )]
+    #[case(r#"{"Group":{"nodes":[]}}"#, invalid("missing field group_ref"))]
+    #[case(
+        r#"{"Class":{"ranges":[{"start":"z","end":"a"}]}}"#,
+        invalid("reversed range z-a")
+    )]
+    #[case(
+        r#"{"Quantifier":{"node":"Digit","min":2,"max":1,"greedy":true}}"#,
+        invalid("reversed quantifier {2,1}")
+    )]
+    fn test_from_json_error(#[case] json: &str, #[case] expected: JsonError) {
+        assert_eq!(Node::from_json(json), Err(expected));
+    }
+
+    #[test]
+    fn test_regex_json() {
+        let regex = Regex::new("\"(\\w+)\"").unwrap();
+        let json = regex.to_json();
+        assert_eq!(json, r#""\"(\\w+)\"""#);
+        let read = Regex::from_json(&json).unwrap();
+        assert_eq!(read.as_str(), regex.as_str());
+        assert!(read.is_match("say \"hi\""));
+
+        assert_eq!(
+            Regex::from_json(r#""a(""#).unwrap_err(),
+            JsonError::Regex(RegexError::UnbalancedParen { pos: 1 })
+        );
+        assert_eq!(
+            Regex::from_json("1").unwrap_err(),
+            invalid("expected the pattern as a string")
+        );
+    }
+}
@@ -0,0 +1,605 @@
+use std::collections::HashSet;
+
+use crate::char_class::eq_ignore_case;
+use crate::regex_compiler::{Inst, MatchKind, Program};
+use crate::stats::Stats;
+use crate::utf8::{boundary_from, char_at, char_before, positions};
+
+/// Capture slots, see Program. Positions are byte offsets in the text
+type Slots = Vec<Option<usize>>;
+
+/// Work left on the explicit stack, so that long lines do not overflow the
+/// call stack
+#[derive(Debug, Clone)]
+enum Frame {
+    Explore {
+        pc: usize,
+        pos: usize,
+    },
+    /// Puts back the value of the slot once the branch that modified it failed
+    RestoreSlot {
+        slot: usize,
+        value: Option<usize>,
+    },
+}
+
+/// States already explored, which can not lead to a match. Without
+/// backreferences, the outcome only depends on the instruction and the
+/// position, which bounds the running time. Otherwise it also depends on the
+/// captures read by the backreferences. Hashing these states is much slower
+/// than exploring them, so they are only recorded once the run took as many
+/// steps as the positions table would allow
+#[derive(Debug)]
+enum Visited {
+    /// The tables only grow up to the positions reached, on both sides of the
+    /// origin for the lookbehinds, so that a search from an offset does not
+    /// depend on the length of the text
+    Positions {
+        /// states at the positions from origin, at (pos - origin) * nb_insts + pc
+        after: Vec<bool>,
+        /// states at the positions before origin, at
+        /// (origin - 1 - pos) * nb_insts + pc
+        before: Vec<bool>,
+        origin: usize,
+        nb_insts: usize,
+    },
+    States {
+        /// steps left before the states are recorded
+        budget: usize,
+        max_budget: usize,
+        /// slots read by the backreferences
+        read_slots: Vec<usize>,
+        /// instruction, position and values of read_slots, reused between
+        /// lookups so that only new states allocate
+        key: Vec<Option<usize>>,
+        states: HashSet<Vec<Option<usize>>>,
+    },
+}
+
+impl Visited {
+    /// Table for a search from origin, which may reach nb_positions positions
+    fn new(program: &Program, origin: usize, nb_positions: usize) -> Self {
+        let mut read_slots: Vec<usize> = program
+            .insts
+            .iter()
+            .filter_map(|inst| match inst {
+                Inst::BackRef { group, .. } => Some([group * 2, group * 2 + 1]),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        if !read_slots.is_empty() {
+            read_slots.sort_unstable();
+            read_slots.dedup();
+            let max_budget = program.insts.len() * nb_positions;
+            Visited::States {
+                budget: max_budget,
+                max_budget,
+                read_slots,
+                key: Vec::new(),
+                states: HashSet::new(),
+            }
+        } else {
+            Visited::Positions {
+                after: Vec::new(),
+                before: Vec::new(),
+                origin,
+                nb_insts: program.insts.len(),
+            }
+        }
+    }
+
+    /// Returns false if the state was already visited
+    fn insert(&mut self, pc: usize, pos: usize, slots: &[Option<usize>]) -> bool {
+        match self {
+            Visited::Positions {
+                after,
+                before,
+                origin,
+                nb_insts,
+            } => {
+                let (seen, distance) = if pos >= *origin {
+                    (after, pos - *origin)
+                } else {
+                    (before, *origin - 1 - pos)
+                };
+                let index = distance * *nb_insts + pc;
+                if index >= seen.len() {
+                    seen.resize((distance + 1) * *nb_insts, false);
+                }
+                !std::mem::replace(&mut seen[index], true)
+            }
+            Visited::States { budget, .. } if *budget > 0 => {
+                *budget -= 1;
+                true
+            }
+            Visited::States {
+                read_slots,
+                key,
+                states,
+                ..
+            } => {
+                key.clear();
+                key.extend([Some(pc), Some(pos)]);
+                key.extend(
+                    read_slots
+                        .iter()
+                        .map(|&slot| slots.get(slot).copied().flatten()),
+                );
+                !states.contains(key.as_slice()) && states.insert(key.clone())
+            }
+        }
+    }
+
+    /// Forgets the states, for a search from the new origin
+    fn reset(&mut self, new_origin: usize) {
+        match self {
+            Visited::Positions {
+                after,
+                before,
+                origin,
+                ..
+            } => {
+                after.clear();
+                before.clear();
+                *origin = new_origin;
+            }
+            Visited::States {
+                budget,
+                max_budget,
+                states,
+                ..
+            } => {
+                *budget = *max_budget;
+                states.clear();
+            }
+        }
+    }
+}
+
+/// Backtracking engine, used for the patterns the Pike VM can not run:
+/// backreferences and lookarounds (-P). It tries the paths one after the other
+/// in priority order, so the first match found is the one a Perl engine reports.
+/// Unlike the Pike VM, it needs the whole text, typically a line, but it only
+/// decodes the chars it reads, so a search from an offset does not read the
+/// text before it.
+/// Invalid UTF-8 sequences are read as U+FFFD.
+#[derive(Debug, Clone)]
+pub struct Backtracker<'p> {
+    program: &'p Program,
+    /// when searching a set of patterns, the ones that matched
+    matched_patterns: Option<Vec<bool>>,
+    /// end of the text read by the chars and classes, which gives the bytes
+    /// scanned by a search from an offset
+    read_end: usize,
+    stats: Stats,
+}
+
+impl<'p> Backtracker<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        Self {
+            program,
+            matched_patterns: None,
+            read_end: 0,
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Searches a whole text, stopping as soon as a match is found
+    pub fn is_match(&mut self, bytes: &[u8]) -> bool {
+        self.stats.bytes_scanned += bytes.len();
+        let mut visited = Visited::new(self.program, 0, bytes.len() + 1);
+        let mut slots = vec![None; self.program.nb_slots];
+        positions(bytes, 0).any(|start| {
+            slots.fill(None);
+            self.run(bytes, 0, start, &mut slots, None, &mut visited)
+        })
+    }
+
+    /// Successive non overlapping matches in a whole text, as byte offsets
+    pub fn find_all(&mut self, bytes: &[u8]) -> Vec<(usize, usize)> {
+        self.captures_all(bytes)
+            .iter()
+            .filter_map(|slots| Some((slots[0]?, slots[1]?)))
+            .collect()
+    }
+
+    /// Same as find_all, with the slots of each match
+    pub fn captures_all(&mut self, bytes: &[u8]) -> Vec<Slots> {
+        self.stats.bytes_scanned += bytes.len();
+        let mut visited = Visited::new(self.program, 0, bytes.len() + 1);
+        let mut slots = vec![None; self.program.nb_slots];
+        let mut matches = Vec::new();
+        // no match can start there, to avoid reporting twice the same empty match
+        let mut no_start_at = None;
+        let mut start = 0;
+        loop {
+            let found = positions(bytes, start)
+                .filter(|&pos| no_start_at != Some(pos))
+                .any(|pos| {
+                    slots.fill(None);
+                    self.run(bytes, 0, pos, &mut slots, None, &mut visited)
+                });
+            let (true, Some(match_start), Some(match_end)) = (found, slots[0], slots[1]) else {
+                break;
+            };
+            matches.push(slots.clone());
+            no_start_at = (match_start == match_end).then_some(match_end);
+            start = match_end;
+            // the states leading to this match were not failures
+            visited.reset(start);
+        }
+        matches
+    }
+
+    /// Leftmost match starting at or after the byte offset start, or only at
+    /// start if anchored. The text before start is still seen by ^ and the
+    /// lookbehinds, but it is only read by them
+    pub fn captures_at(&mut self, bytes: &[u8], start: usize, anchored: bool) -> Option<Slots> {
+        let start = boundary_from(bytes, start);
+        self.read_end = start;
+        let nb_positions = bytes.len().saturating_sub(start) + 1;
+        let mut visited = Visited::new(self.program, start, nb_positions);
+        let mut slots = vec![None; self.program.nb_slots];
+        let mut run = |pos| {
+            slots.fill(None);
+            self.run(bytes, 0, pos, &mut slots, None, &mut visited)
+        };
+        let found = if anchored {
+            run(start)
+        } else {
+            positions(bytes, start).any(run)
+        };
+        self.stats.bytes_scanned += self.read_end - start;
+        found.then_some(slots)
+    }
+
+    /// Which patterns of a set match a whole text, see Program::new_set
+    pub fn matching_patterns(&mut self, bytes: &[u8]) -> Vec<bool> {
+        self.stats.bytes_scanned += bytes.len();
+        // the states leading to a match lead to the same one from any start,
+        // so they are not explored again
+        let mut visited = Visited::new(self.program, 0, bytes.len() + 1);
+        let mut slots = vec![None; self.program.nb_slots];
+        self.matched_patterns = Some(vec![false; self.program.match_pcs.len()]);
+        for start in positions(bytes, 0) {
+            slots.fill(None);
+            self.run(bytes, 0, start, &mut slots, None, &mut visited);
+            if self
+                .matched_patterns
+                .as_ref()
+                .is_some_and(|matched| matched.iter().all(|&matched| matched))
+            {
+                break;
+            }
+        }
+        self.matched_patterns.take().unwrap_or_default()
+    }
+
+    /// Runs the program from pc at the position. The run succeeds on Match or,
+    /// in the body of a lookaround, on LookEnd at the target position if any.
+    /// On success, the slots are the captures of the match, the longest one
+    /// with MatchKind::LeftmostLongest
+    fn run(
+        &mut self,
+        bytes: &[u8],
+        pc: usize,
+        pos: usize,
+        slots: &mut Slots,
+        target: Option<usize>,
+        visited: &mut Visited,
+    ) -> bool {
+        let program = self.program;
+        let mut longest: Option<Slots> = None;
+        let mut stack = vec![Frame::Explore { pc, pos }];
+        while let Some(frame) = stack.pop() {
+            let (mut pc, mut pos) = match frame {
+                Frame::Explore { pc, pos } => (pc, pos),
+                Frame::RestoreSlot { slot, value } => {
+                    slots[slot] = value;
+                    continue;
+                }
+            };
+            // follows the first branch of each split, the others are explored
+            // if it fails
+            while visited.insert(pc, pos, slots) {
+                self.stats.states_touched += 1;
+                self.stats.backtrack_depth = self.stats.backtrack_depth.max(stack.len());
+                match &program.insts[pc] {
+                    Inst::Char(c) => match self.read(bytes, pos) {
+                        Some((next, len)) if next == *c => pos += len,
+                        _ => break,
+                    },
+                    Inst::Class(class) => match self.read(bytes, pos) {
+                        Some((next, len)) if class.contains(next) => pos += len,
+                        _ => break,
+                    },
+                    Inst::Split(first, second) => {
+                        stack.push(Frame::Explore { pc: *second, pos });
+                        pc = *first;
+                        continue;
+                    }
+                    Inst::Jmp(next) => {
+                        pc = *next;
+                        continue;
+                    }
+                    Inst::Save(slot) => {
+                        stack.push(Frame::RestoreSlot {
+                            slot: *slot,
+                            value: slots[*slot],
+                        });
+                        slots[*slot] = Some(pos);
+                    }
+                    Inst::Assert(assertion) => {
+                        let prev = char_before(bytes, pos).map(|(c, _)| c);
+                        let next = char_at(bytes, pos).map(|(c, _)| c);
+                        if !assertion.holds(prev, next) {
+                            break;
+                        }
+                    }
+                    Inst::BackRef { group, ignore_case } => {
+                        match backref_end(bytes, slots, *group, *ignore_case, pos) {
+                            Some(end) => pos = end,
+                            None => break,
+                        }
+                    }
+                    Inst::Look {
+                        ahead,
+                        negated,
+                        end,
+                    } => {
+                        let mut look_slots = slots.clone();
+                        if self.look(bytes, pc + 1, pos, *ahead, &mut look_slots) == *negated {
+                            break;
+                        }
+                        // the captures of a positive lookaround are kept
+                        for (slot, value) in look_slots.into_iter().enumerate() {
+                            if slots[slot] != value {
+                                stack.push(Frame::RestoreSlot {
+                                    slot,
+                                    value: slots[slot],
+                                });
+                                slots[slot] = value;
+                            }
+                        }
+                        pc = *end;
+                        continue;
+                    }
+                    Inst::LookEnd if target.is_none_or(|target| target == pos) => return true,
+                    Inst::LookEnd => break,
+                    Inst::Match if self.matched_patterns.is_some() => {
+                        // the other paths may reach the Match of other patterns
+                        let pattern = program.pattern_at(pc);
+                        if let (Some(matched), Some(pattern)) =
+                            (&mut self.matched_patterns, pattern)
+                        {
+                            matched[pattern] = true;
+                        }
+                        break;
+                    }
+                    Inst::Match if program.match_kind == MatchKind::LeftmostLongest => {
+                        // the other paths may match a longer text
+                        if longest.as_ref().is_none_or(|longest| longest[1] < slots[1]) {
+                            longest = Some(slots.clone());
+                        }
+                        break;
+                    }
+                    Inst::Match => return true,
+                }
+                pc += 1;
+            }
+        }
+        match longest {
+            Some(longest) => {
+                *slots = longest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Char at the position, see char_at, recording that it was read
+    fn read(&mut self, bytes: &[u8], pos: usize) -> Option<(char, usize)> {
+        let (c, len) = char_at(bytes, pos)?;
+        self.read_end = self.read_end.max(pos + len);
+        Some((c, len))
+    }
+
+    /// Runs the body of a lookaround, starting at pc. A lookbehind matches if
+    /// the body matches a text ending at the position, the closest starts
+    /// being tried first
+    fn look(
+        &mut self,
+        bytes: &[u8],
+        pc: usize,
+        pos: usize,
+        ahead: bool,
+        slots: &mut Slots,
+    ) -> bool {
+        let mut visited = Visited::new(self.program, pos, bytes.len() + 1);
+        if ahead {
+            return self.run(bytes, pc, pos, slots, None, &mut visited);
+        }
+        let mut start = Some(pos);
+        while let Some(from) = start {
+            if self.run(bytes, pc, from, slots, Some(pos), &mut visited) {
+                return true;
+            }
+            start = char_before(bytes, from).map(|(_, len)| from - len);
+        }
+        false
+    }
+}
+
+/// Position after the text captured by the group, if it is found at pos.
+/// A group that did not participate in the match never matches
+fn backref_end(
+    bytes: &[u8],
+    slots: &[Option<usize>],
+    group: usize,
+    ignore_case: bool,
+    pos: usize,
+) -> Option<usize> {
+    let start = (*slots.get(group * 2)?)?;
+    let end = (*slots.get(group * 2 + 1)?)?;
+    if !ignore_case {
+        let captured = &bytes[start..end];
+        return (bytes.get(pos..pos + captured.len())? == captured).then_some(pos + captured.len());
+    }
+    // the chars of both texts may have different lengths
+    let (mut captured_pos, mut pos) = (start, pos);
+    while captured_pos < end {
+        let (a, a_len) = char_at(bytes, captured_pos)?;
+        let (b, b_len) = char_at(bytes, pos)?;
+        if !eq_ignore_case(a, b) {
+            return None;
+        }
+        captured_pos += a_len;
+        pos += b_len;
+    }
+    Some(pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::regex_lexer::{Dialect, RegexLexer};
+    use crate::regex_parser::RegexParser;
+
+    use super::*;
+
+    fn program(pat: &str) -> anyhow::Result<Program> {
+        let lexer = RegexLexer::with_dialect(pat, Dialect::Perl);
+        let node = RegexParser::new(lexer)?.build_ast(0)?;
+        Ok(Program::new(&node))
+    }
+
+    #[rstest]
+    #[case("(a)\\1", "xaay", true)]
+    #[case("(a)\\1", "xaya", false)]
+    #[case("(a|b)\\1", "abba", true)]
+    #[case("\\1(a)", "aa", false)]
+    #[case("^(a*)*\\1b", "aaaa", false)]
+    #[case("^(a*)+\\1b", "aaab", true)]
+    #[case("foo(?=bar)", "foobaz foobar", true)]
+    #[case("foo(?!bar)", "foobar", false)]
+    #[case("(?<=\\$)\\d+", "price: 42", false)]
+    #[case("(?<=\\$)\\d+", "price: $42", true)]
+    #[case("(?<!a)b", "ab", false)]
+    #[case("(a*)*b", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac", false)]
+    #[case("(a*)*\\1b", "aaaaaaaaaaaaaaac", false)]
+    fn test_is_match(
+        #[case] pat: &str,
+        #[case] text: &str,
+        #[case] expected: bool,
+    ) -> anyhow::Result<()> {
+        let program = program(pat)?;
+        let mut backtracker = Backtracker::new(&program);
+        assert_eq!(backtracker.is_match(text.as_bytes()), expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("<.+?>", "<a><b>", vec![(0, 3), (3, 6)])]
+    #[case("<.+>", "<a><b>", vec![(0, 6)])]
+    #[case("a??", "aa", vec![(0, 0), (1, 1), (2, 2)])]
+    #[case("(\\w)\\1", "aabbc", vec![(0, 2), (2, 4)])]
+    #[case("(?<=é)\\w", "éab", vec![(2, 3)])]
+    #[case("(?=(\\w))\\1", "ab", vec![(0, 1), (1, 2)])]
+    fn test_find_all(
+        #[case] pat: &str,
+        #[case] text: &str,
+        #[case] expected: Vec<(usize, usize)>,
+    ) -> anyhow::Result<()> {
+        let program = program(pat)?;
+        let mut backtracker = Backtracker::new(&program);
+        assert_eq!(backtracker.find_all(text.as_bytes()), expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("^a", "aa", 1, false, None)]
+    #[case("(?<=a)b", "ab", 1, false, Some((1, 2)))]
+    #[case("\\d+", "a12b345", 1, true, Some((1, 3)))]
+    #[case("\\d+", "a12b345", 3, false, Some((4, 7)))]
+    #[case("\\d+", "a12b345", 3, true, None)]
+    #[case("b", "éb", 1, true, Some((2, 3)))]
+    fn test_captures_at(
+        #[case] pat: &str,
+        #[case] text: &str,
+        #[case] start: usize,
+        #[case] anchored: bool,
+        #[case] expected: Option<(usize, usize)>,
+    ) -> anyhow::Result<()> {
+        let program = program(pat)?;
+        let mut backtracker = Backtracker::new(&program);
+        let found = backtracker.captures_at(text.as_bytes(), start, anchored);
+        assert_eq!(
+            found.and_then(|slots| Some((slots[0]?, slots[1]?))),
+            expected
+        );
+        Ok(())
+    }
+
+    /// Tokenizing with captures_at reads the same bytes for each block added to
+    /// the text: the text before the offset is not decoded again
+    #[rstest]
+    #[case("\\d+|[a-z]+| ")]
+    #[case("\\d+(?!x)|[a-z]+| ")]
+    fn test_captures_at_scaling(#[case] pat: &str) -> anyhow::Result<()> {
+        let program = program(pat)?;
+        let tokenize = |nb_blocks: usize| {
+            let text = "ab 12 ".repeat(nb_blocks);
+            let mut backtracker = Backtracker::new(&program);
+            let mut pos = 0;
+            while let Some(end) = backtracker
+                .captures_at(text.as_bytes(), pos, true)
+                .and_then(|slots| slots[1])
+            {
+                pos = end;
+            }
+            assert_eq!(pos, text.len());
+            *backtracker.stats()
+        };
+        let [one, two, three] = [100, 200, 300].map(tokenize);
+        assert_eq!(
+            three.bytes_scanned - two.bytes_scanned,
+            two.bytes_scanned - one.bytes_scanned
+        );
+        assert_eq!(
+            three.states_touched - two.states_touched,
+            two.states_touched - one.states_touched
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("(a)\\1", "xaAy", true)]
+    #[case("(é+)-\\1", "éÉ-ÉéÉ", true)]
+    #[case("(ab)\\1", "abAC", false)]
+    fn test_backref_ignore_case(
+        #[case] pat: &str,
+        #[case] text: &str,
+        #[case] expected: bool,
+    ) -> anyhow::Result<()> {
+        let mut parser = RegexParser::new(RegexLexer::with_dialect(pat, Dialect::Perl))?;
+        parser.set_ignore_case(true);
+        let program = Program::new(&parser.build_ast(0)?);
+        let mut backtracker = Backtracker::new(&program);
+        assert_eq!(backtracker.is_match(text.as_bytes()), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_utf8() -> anyhow::Result<()> {
+        let program = program("(.)\\1")?;
+        let mut backtracker = Backtracker::new(&program);
+        assert_eq!(backtracker.find_all(b"a\xff\xffb"), vec![(1, 3)]);
+        Ok(())
+    }
+}
@@ -0,0 +1,328 @@
+use std::fmt;
+
+/// Inclusive range of chars
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+pub struct ClassRange {
+    pub start: char,
+    pub end: char,
+}
+
+impl ClassRange {
+    /// Range of the chars from start to end, which come in this order
+    pub fn new(start: char, end: char) -> Self {
+        debug_assert!(start <= end, "reversed range {start}-{end}");
+        Self { start, end }
+    }
+}
+
+/// Set of chars represented as sorted, non overlapping and non adjacent ranges.
+/// Keeping the ranges normalized allows negation and union in linear time
+/// and containment checks with a binary search.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct CharClass {
+    ranges: Vec<ClassRange>,
+}
+
+/// next valid char, skipping the surrogate gap
+fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        char::MAX => None,
+        c => char::from_u32(c as u32 + 1),
+    }
+}
+
+/// previous valid char, skipping the surrogate gap
+fn prev_char(c: char) -> Option<char> {
+    match c {
+        '\u{E000}' => Some('\u{D7FF}'),
+        '\0' => None,
+        c => char::from_u32(c as u32 - 1),
+    }
+}
+
+/// true if the sorted ranges overlap or are adjacent
+fn touches(first: &ClassRange, second: &ClassRange) -> bool {
+    match next_char(first.end) {
+        Some(c) => second.start <= c,
+        None => true,
+    }
+}
+
+/// Whether b is one of the cases of a, which matches it with -i, see
+/// CharClass::case_fold
+pub fn eq_ignore_case(a: char, b: char) -> bool {
+    a == b || single_char(a.to_lowercase()) == Some(b) || single_char(a.to_uppercase()) == Some(b)
+}
+
+/// the only char of the iterator, None if there are several
+fn single_char(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    let c = chars.next()?;
+    chars.next().is_none().then_some(c)
+}
+
+impl CharClass {
+    pub fn new(ranges: Vec<ClassRange>) -> Self {
+        let mut class = Self { ranges };
+        class.canonicalize();
+        class
+    }
+
+    pub fn single(c: char) -> Self {
+        Self::new(vec![ClassRange::new(c, c)])
+    }
+
+    /// Every char, used for the wildcard
+    pub fn any() -> Self {
+        Self::new(vec![ClassRange::new('\0', char::MAX)])
+    }
+
+    /// \d
+    pub fn digit() -> Self {
+        Self::new(vec![ClassRange::new('0', '9')])
+    }
+
+    /// \w
+    pub fn alphanum() -> Self {
+        Self::new(vec![
+            ClassRange::new('0', '9'),
+            ClassRange::new('A', 'Z'),
+            ClassRange::new('_', '_'),
+            ClassRange::new('a', 'z'),
+        ])
+    }
+
+    /// Class of a bracket expression such as [:alpha:], in the C locale
+    pub fn posix(name: &str) -> Option<Self> {
+        let ranges: &[(char, char)] = match name {
+            "alpha" => &[('A', 'Z'), ('a', 'z')],
+            "digit" => &[('0', '9')],
+            "alnum" => &[('0', '9'), ('A', 'Z'), ('a', 'z')],
+            "upper" => &[('A', 'Z')],
+            "lower" => &[('a', 'z')],
+            "space" => &[('\t', '\r'), (' ', ' ')],
+            "blank" => &[('\t', '\t'), (' ', ' ')],
+            "punct" => &[('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+            "xdigit" => &[('0', '9'), ('A', 'F'), ('a', 'f')],
+            "cntrl" => &[('\0', '\x1f'), ('\x7f', '\x7f')],
+            "print" => &[(' ', '~')],
+            "graph" => &[('!', '~')],
+            _ => return None,
+        };
+        let ranges = ranges
+            .iter()
+            .map(|&(start, end)| ClassRange::new(start, end))
+            .collect();
+        Some(Self::new(ranges))
+    }
+
+    /// Sorts the ranges and merges the ones that overlap or touch
+    fn canonicalize(&mut self) {
+        self.ranges.sort();
+        let mut merged: Vec<ClassRange> = Vec::with_capacity(self.ranges.len());
+        for range in self.ranges.drain(..) {
+            match merged.last_mut() {
+                Some(last) if touches(last, &range) => {
+                    last.end = last.end.max(range.end);
+                }
+                _ => merged.push(range),
+            }
+        }
+        self.ranges = merged;
+    }
+
+    pub fn union(&mut self, other: &CharClass) {
+        self.ranges.extend_from_slice(&other.ranges);
+        self.canonicalize();
+    }
+
+    /// Replaces the class by its complement
+    pub fn negate(&mut self) {
+        let mut negated = Vec::with_capacity(self.ranges.len() + 1);
+        let mut start = Some('\0');
+        for range in &self.ranges {
+            if let (Some(s), Some(end)) = (start, prev_char(range.start)) {
+                if s <= end {
+                    negated.push(ClassRange::new(s, end));
+                }
+            }
+            start = next_char(range.end);
+        }
+        if let Some(s) = start {
+            negated.push(ClassRange::new(s, char::MAX));
+        }
+        self.ranges = negated;
+    }
+
+    /// Adds the other cases of the chars in the class, for case insensitive
+    /// matching. Only the case mappings to a single char are used
+    pub fn case_fold(&mut self) {
+        let mut ranges = self.ranges.clone();
+        for range in &self.ranges {
+            let mut next = Some(range.start);
+            while let Some(c) = next.filter(|&c| c <= range.end) {
+                for other in [single_char(c.to_lowercase()), single_char(c.to_uppercase())]
+                    .into_iter()
+                    .flatten()
+                {
+                    ranges.push(ClassRange::new(other, other));
+                }
+                next = next_char(c);
+            }
+        }
+        *self = Self::new(ranges);
+    }
+
+    /// Same as case_fold, for the ASCII letters only
+    pub fn ascii_case_fold(&mut self) {
+        let mut ranges = self.ranges.clone();
+        for range in &self.ranges {
+            for (from, to) in [('a'..='z', 'A'), ('A'..='Z', 'a')] {
+                let start = range.start.max(*from.start());
+                let end = range.end.min(*from.end());
+                if start <= end {
+                    let shift = |c: char| (c as u8 - *from.start() as u8 + to as u8) as char;
+                    ranges.push(ClassRange::new(shift(start), shift(end)));
+                }
+            }
+        }
+        *self = Self::new(ranges);
+    }
+
+    /// Sorted ranges of the chars of the class
+    pub fn ranges(&self) -> &[ClassRange] {
+        &self.ranges
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges
+            .binary_search_by(|range| {
+                if range.end < c {
+                    std::cmp::Ordering::Less
+                } else if range.start > c {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+}
+
+/// Writes the char as in a bracket expression, escaping the chars special
+/// there and the invisible ones, which are kept as is with {:#}
+fn write_class_char(f: &mut fmt::Formatter<'_>, c: char) -> fmt::Result {
+    match c {
+        '\\' | ']' | '[' | '-' | '^' => write!(f, "\\{c}"),
+        c if f.alternate() => write!(f, "{c}"),
+        c if c.is_control() || c.is_whitespace() && c != ' ' => {
+            write!(f, "{}", c.escape_default())
+        }
+        c => write!(f, "{c}"),
+    }
+}
+
+impl fmt::Display for CharClass {
+    /// Bracket expression of the class, such as [0-9a-f]. A class holding the
+    /// last char, as the negated ones do, is written as the complement of the
+    /// others ([^abc]). With {:#}, it can be parsed back
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (prefix, class) = match self.ranges.last() {
+            Some(range) if range.end == char::MAX => {
+                let mut complement = self.clone();
+                complement.negate();
+                ("[^", complement)
+            }
+            _ => ("[", self.clone()),
+        };
+        write!(f, "{prefix}")?;
+        for range in &class.ranges {
+            write_class_char(f, range.start)?;
+            if range.start != range.end {
+                write!(f, "-")?;
+                write_class_char(f, range.end)?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn ranges(ranges: &[(char, char)]) -> Vec<ClassRange> {
+        ranges.iter().map(|&(s, e)| ClassRange::new(s, e)).collect()
+    }
+
+    #[rstest]
+    #[case(&[('a', 'c'), ('b', 'f')], &[('a', 'f')])]
+    #[case(&[('x', 'z'), ('a', 'c'), ('d', 'd')], &[('a', 'd'), ('x', 'z')])]
+    fn test_canonicalize(#[case] input: &[(char, char)], #[case] expected: &[(char, char)]) {
+        let class = CharClass::new(ranges(input));
+        assert_eq!(class.ranges, ranges(expected));
+    }
+
+    #[rstest]
+    #[case(&[('b', 'y')], &[('\0', 'a'), ('z', char::MAX)])]
+    #[case(&[('\0', 'a')], &[('b', char::MAX)])]
+    #[case(&[('\u{D000}', '\u{D7FF}')], &[('\0', '\u{CFFF}'), ('\u{E000}', char::MAX)])]
+    #[case(&[('\0', char::MAX)], &[])]
+    fn test_negate(#[case] input: &[(char, char)], #[case] expected: &[(char, char)]) {
+        let mut class = CharClass::new(ranges(input));
+        class.negate();
+        assert_eq!(class.ranges, ranges(expected));
+        class.negate();
+        assert_eq!(class, CharClass::new(ranges(input)));
+    }
+
+    #[rstest]
+    #[case(CharClass::alphanum(), "[0-9A-Z_a-z]")]
+    #[case(CharClass::new(ranges(&[('-', '-'), ('\t', '\t')])), "[\\t\\-]")]
+    #[case(CharClass::new(ranges(&[('\0', 'a'), ('c', char::MAX)])), "[^b]")]
+    #[case(CharClass::default(), "[]")]
+    fn test_display(#[case] class: CharClass, #[case] expected: &str) {
+        assert_eq!(class.to_string(), expected);
+    }
+
+    #[test]
+    fn test_union() {
+        let mut class = CharClass::digit();
+        class.union(&CharClass::single('k'));
+        class.union(&CharClass::new(ranges(&[('a', 'j')])));
+        assert_eq!(class.ranges, ranges(&[('0', '9'), ('a', 'k')]));
+    }
+
+    #[rstest]
+    #[case(&[('a', 'c')], &[('A', 'C'), ('a', 'c')])]
+    #[case(&[('0', '9'), ('X', 'x')], &[('0', '9'), ('A', 'z')])]
+    #[case(&[('é', 'é')], &[('É', 'É'), ('é', 'é')])]
+    #[case(&[('İ', 'İ')], &[('İ', 'İ')])]
+    fn test_case_fold(#[case] input: &[(char, char)], #[case] expected: &[(char, char)]) {
+        let mut class = CharClass::new(ranges(input));
+        class.case_fold();
+        assert_eq!(class.ranges, ranges(expected));
+    }
+
+    #[rstest]
+    #[case(&[('a', 'c')], &[('A', 'C'), ('a', 'c')])]
+    #[case(&[('X', 'é')], &[('A', 'é')])]
+    #[case(&[('é', 'é')], &[('é', 'é')])]
+    fn test_ascii_case_fold(#[case] input: &[(char, char)], #[case] expected: &[(char, char)]) {
+        let mut class = CharClass::new(ranges(input));
+        class.ascii_case_fold();
+        assert_eq!(class.ranges, ranges(expected));
+    }
+
+    #[rstest]
+    #[case('a', true)]
+    #[case('5', true)]
+    #[case('_', true)]
+    #[case('-', false)]
+    #[case('é', false)]
+    fn test_contains(#[case] c: char, #[case] expected: bool) {
+        assert_eq!(CharClass::alphanum().contains(c), expected);
+    }
+}
@@ -0,0 +1,119 @@
+use std::io::Write;
+
+/// SGR sequences used to highlight the output, as configured by the
+/// GREP_COLORS environment variable, with the capabilities of GNU grep.
+/// An empty sequence means that the text is not colored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colors {
+    /// ms: matched text in a selected line
+    pub selected_match: String,
+    /// mc: matched text in a context line
+    pub context_match: String,
+    /// fn: file names
+    pub filename: String,
+    /// ln: line numbers
+    pub line_number: String,
+    /// bn: byte offsets
+    pub byte_offset: String,
+    /// se: separators between the fields and between the groups of lines
+    pub separator: String,
+    /// sl: text of the selected lines, outside of the matches
+    pub selected_line: String,
+    /// cx: text of the context lines, outside of the matches
+    pub context_line: String,
+    /// rv: with -v, swap the colors of the selected and context lines
+    pub reverse: bool,
+    /// ne: do not append the sequence that clears the rest of the line
+    pub no_erase: bool,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            selected_match: "01;31".to_string(),
+            context_match: "01;31".to_string(),
+            filename: "35".to_string(),
+            line_number: "32".to_string(),
+            byte_offset: "32".to_string(),
+            separator: "36".to_string(),
+            selected_line: String::new(),
+            context_line: String::new(),
+            reverse: false,
+            no_erase: false,
+        }
+    }
+}
+
+impl Colors {
+    /// Applies a GREP_COLORS value such as `ms=01;32:fn=34:ne` to the default
+    /// colors. Unknown capabilities are ignored
+    pub fn parse(spec: &str) -> Self {
+        let mut colors = Self::default();
+        for capability in spec.split(':') {
+            let (name, value) = capability.split_once('=').unwrap_or((capability, ""));
+            let value = value.to_string();
+            match name {
+                "mt" => {
+                    colors.selected_match = value.clone();
+                    colors.context_match = value;
+                }
+                "ms" => colors.selected_match = value,
+                "mc" => colors.context_match = value,
+                "fn" => colors.filename = value,
+                "ln" => colors.line_number = value,
+                "bn" => colors.byte_offset = value,
+                "se" => colors.separator = value,
+                "sl" => colors.selected_line = value,
+                "cx" => colors.context_line = value,
+                "rv" => colors.reverse = true,
+                "ne" => colors.no_erase = true,
+                _ => {}
+            }
+        }
+        colors
+    }
+
+    /// Writes the text surrounded by the escape sequences of the color
+    pub fn paint(&self, out: &mut impl Write, color: &str, text: &[u8]) -> std::io::Result<()> {
+        if color.is_empty() {
+            return out.write_all(text);
+        }
+        let erase = if self.no_erase { "" } else { "\x1b[K" };
+        write!(out, "\x1b[{color}m{erase}")?;
+        out.write_all(text)?;
+        write!(out, "\x1b[m{erase}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let colors = Colors::parse("mt=01;32:fn=:ln=34:sl=1:rv:xx=1:ne");
+        assert_eq!(
+            colors,
+            Colors {
+                selected_match: "01;32".to_string(),
+                context_match: "01;32".to_string(),
+                filename: String::new(),
+                line_number: "34".to_string(),
+                selected_line: "1".to_string(),
+                reverse: true,
+                no_erase: true,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_paint() -> std::io::Result<()> {
+        let colors = Colors::default();
+        let mut out = Vec::new();
+        colors.paint(&mut out, "01;31", b"abc")?;
+        colors.paint(&mut out, "", b"def")?;
+        assert_eq!(out, b"\x1b[01;31m\x1b[Kabc\x1b[m\x1b[Kdef");
+        Ok(())
+    }
+}
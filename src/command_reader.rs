@@ -0,0 +1,103 @@
+use std::ffi::OsStr;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+/// Decompressors of the files searched with --search-zip, by extension
+const DECOMPRESSORS: [(&str, &str); 4] = [
+    ("gz", "gzip"),
+    ("bz2", "bzip2"),
+    ("xz", "xz"),
+    ("zst", "zstd"),
+];
+
+/// Program that decompresses its standard input to its standard output with
+/// -dc, if the file is compressed
+pub fn decompressor(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?;
+    DECOMPRESSORS
+        .iter()
+        .find(|(known, _)| extension == *known)
+        .map(|(_, program)| *program)
+}
+
+/// Reads the standard output of a command, so that its output is searched
+/// while it is produced. The end of the output is an error if the command fails
+pub struct CommandReader {
+    program: String,
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl CommandReader {
+    pub fn spawn<I, S>(program: &str, args: I, stdin: Stdio) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| io::Error::new(err.kind(), format!("{program}: {err}")))?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+        Ok(Self {
+            program: program.to_string(),
+            child,
+            stdout,
+        })
+    }
+}
+
+impl Read for CommandReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.stdout.read(buf)?;
+        if len == 0 && !buf.is_empty() {
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("{} {status}", self.program)));
+            }
+        }
+        Ok(len)
+    }
+}
+
+impl Drop for CommandReader {
+    /// The search may stop before the end of the output, such as with -l
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("a/b.gz", Some("gzip"))]
+    #[case("b.tar.zst", Some("zstd"))]
+    #[case("b.gzip", None)]
+    #[case("gz", None)]
+    fn test_decompressor(#[case] path: &str, #[case] expected: Option<&str>) {
+        assert_eq!(decompressor(Path::new(path)), expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_reader() -> io::Result<()> {
+        let mut output = String::new();
+        CommandReader::spawn("echo", ["a", "b"], Stdio::null())?.read_to_string(&mut output)?;
+        assert_eq!(output, "a b\n");
+
+        let result =
+            CommandReader::spawn("false", [""; 0], Stdio::null())?.read_to_string(&mut output);
+        assert!(result.is_err());
+        Ok(())
+    }
+}
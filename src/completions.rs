@@ -0,0 +1,212 @@
+use std::io::{self, Write};
+
+use clap::{Arg, Command};
+
+/// Shells for which a completion script is generated (--generate-completions)
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Option of the command line, as seen by the completion scripts
+struct Flag {
+    short: Option<char>,
+    long: Option<String>,
+    help: String,
+    takes_value: bool,
+    /// the values the option accepts, empty when any value is accepted
+    values: Vec<String>,
+}
+
+impl Flag {
+    fn new(arg: &Arg) -> Self {
+        let values = arg
+            .get_possible_values()
+            .iter()
+            .filter(|value| !value.is_hide_set())
+            .map(|value| value.get_name().to_string())
+            .collect();
+        let help = arg.get_help().map(|help| help.to_string());
+        Self {
+            short: arg.get_short(),
+            long: arg.get_long().map(str::to_string),
+            // the first sentence is enough in a menu
+            help: help
+                .as_deref()
+                .unwrap_or_default()
+                .split(". ")
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            takes_value: arg.get_action().takes_values(),
+            values,
+        }
+    }
+
+    /// -s and --long, as typed
+    fn names(&self) -> Vec<String> {
+        let short = self.short.map(|short| format!("-{short}"));
+        let long = self.long.as_ref().map(|long| format!("--{long}"));
+        short.into_iter().chain(long).collect()
+    }
+}
+
+/// Writes the script completing the options of the command, their values
+/// when they are known, and file names otherwise
+pub fn generate(command: &mut Command, shell: Shell, out: &mut impl Write) -> io::Result<()> {
+    command.build();
+    let name = command.get_name().to_string();
+    let flags = command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+        .map(Flag::new)
+        .collect::<Vec<_>>();
+    match shell {
+        Shell::Bash => bash(&name, &flags, out),
+        Shell::Zsh => zsh(&name, &flags, out),
+        Shell::Fish => fish(&name, &flags, out),
+    }
+}
+
+fn bash(name: &str, flags: &[Flag], out: &mut impl Write) -> io::Result<()> {
+    let function = format!("_{}", name.replace('-', "_"));
+    let all_names = flags.iter().flat_map(Flag::names).collect::<Vec<_>>();
+    writeln!(out, "{function}() {{")?;
+    writeln!(out, r#"    local cur="${{COMP_WORDS[COMP_CWORD]}}""#)?;
+    writeln!(out, r#"    local prev="${{COMP_WORDS[COMP_CWORD-1]}}""#)?;
+    writeln!(out, r#"    case "$prev" in"#)?;
+    for flag in flags.iter().filter(|flag| flag.takes_value) {
+        let words = if flag.values.is_empty() {
+            "-f".to_string()
+        } else {
+            format!("-W \"{}\"", flag.values.join(" "))
+        };
+        writeln!(out, "        {})", flag.names().join("|"))?;
+        writeln!(
+            out,
+            r#"            COMPREPLY=($(compgen {words} -- "$cur"))"#
+        )?;
+        writeln!(out, "            return")?;
+        writeln!(out, "            ;;")?;
+    }
+    writeln!(out, "    esac")?;
+    writeln!(out, r#"    if [[ "$cur" == -* ]]; then"#)?;
+    writeln!(
+        out,
+        r#"        COMPREPLY=($(compgen -W "{}" -- "$cur"))"#,
+        all_names.join(" ")
+    )?;
+    writeln!(out, "    else")?;
+    writeln!(out, r#"        COMPREPLY=($(compgen -f -- "$cur"))"#)?;
+    writeln!(out, "    fi")?;
+    writeln!(out, "}}")?;
+    writeln!(out, "complete -o filenames -F {function} {name}")
+}
+
+/// Escapes the text for a single quoted zsh option spec
+fn zsh_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\'' => escaped.push_str(r"'\''"),
+            '[' | ']' | ':' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn zsh(name: &str, flags: &[Flag], out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "#compdef {name}")?;
+    writeln!(out)?;
+    writeln!(out, "_arguments -s \\")?;
+    for flag in flags {
+        let help = zsh_escape(&flag.help);
+        let action = match (flag.takes_value, flag.values.is_empty()) {
+            (false, _) => String::new(),
+            (true, true) => ":value:_files".to_string(),
+            (true, false) => format!(":value:({})", flag.values.join(" ")),
+        };
+        for option in flag.names() {
+            writeln!(out, "    '{option}[{help}]{action}' \\")?;
+        }
+    }
+    writeln!(out, "    '*:file:_files'")
+}
+
+fn fish(name: &str, flags: &[Flag], out: &mut impl Write) -> io::Result<()> {
+    for flag in flags {
+        let mut line = format!("complete -c {name}");
+        if let Some(short) = flag.short {
+            line.push_str(&format!(" -s {short}"));
+        }
+        if let Some(long) = &flag.long {
+            line.push_str(&format!(" -l {long}"));
+        }
+        if flag.takes_value {
+            line.push_str(" -r");
+        }
+        if !flag.values.is_empty() {
+            line.push_str(&format!(" -f -a '{}'", flag.values.join(" ")));
+        }
+        let help = flag.help.replace('\\', r"\\").replace('\'', r"\'");
+        line.push_str(&format!(" -d '{help}'"));
+        writeln!(out, "{line}")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ArgAction;
+    use rstest::rstest;
+
+    use super::*;
+
+    fn command() -> Command {
+        Command::new("grep")
+            .arg(
+                Arg::new("count")
+                    .short('c')
+                    .long("count")
+                    .action(ArgAction::SetTrue)
+                    .help("Count the lines. Really"),
+            )
+            .arg(
+                Arg::new("color")
+                    .long("color")
+                    .value_parser(["auto", "never"])
+                    .help("Highlight [it]"),
+            )
+            .arg(Arg::new("pattern"))
+    }
+
+    #[rstest]
+    #[case(Shell::Bash, r#"COMPREPLY=($(compgen -W "auto never" -- "$cur"))"#)]
+    #[case(
+        Shell::Bash,
+        r#"COMPREPLY=($(compgen -W "-c --count --color -h --help" -- "$cur"))"#
+    )]
+    #[case(Shell::Zsh, r"'--color[Highlight \[it\]]:value:(auto never)' \")]
+    #[case(Shell::Zsh, r"'-c[Count the lines]' \")]
+    #[case(Shell::Fish, "complete -c grep -s c -l count -d 'Count the lines'")]
+    #[case(
+        Shell::Fish,
+        "complete -c grep -l color -r -f -a 'auto never' -d 'Highlight [it]'"
+    )]
+    fn test_generate(#[case] shell: Shell, #[case] expected_line: &str) -> io::Result<()> {
+        let mut out = Vec::new();
+        generate(&mut command(), shell, &mut out)?;
+        let script = String::from_utf8_lossy(&out);
+        assert!(
+            script.lines().any(|line| line.trim() == expected_line),
+            "{script}"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Environment variable giving the path of the configuration file
+const CONFIG_VAR: &str = "GREP_CONFIG_PATH";
+
+/// Configuration file with the default arguments: the file of GREP_CONFIG_PATH,
+/// or else mygrep/config in the configuration directory if it exists. An empty
+/// GREP_CONFIG_PATH disables the configuration
+pub fn path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(CONFIG_VAR) {
+        return (!path.is_empty()).then(|| PathBuf::from(path));
+    }
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_dir.join("mygrep").join("config")).filter(|path| path.is_file())
+}
+
+/// Arguments of the configuration file, one per line, such as --color=auto.
+/// The empty lines and the lines starting with # are ignored
+pub fn parse(content: &str) -> Vec<OsString> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(OsString::from)
+        .collect()
+}
+
+/// Arguments of the configuration file, if there is one
+pub fn args() -> anyhow::Result<Vec<OsString>> {
+    let Some(path) = path() else {
+        return Ok(Vec::new());
+    };
+    let content = std::fs::read_to_string(&path)
+        .map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))?;
+    Ok(parse(&content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let content = "# defaults\n--color=auto\n\n  -n  \n--glob=*.rs # not a comment\n";
+        assert_eq!(
+            parse(content),
+            ["--color=auto", "-n", "--glob=*.rs # not a comment"]
+        );
+    }
+}
@@ -0,0 +1,36 @@
+/// Makes the console of standard output interpret the ANSI escape sequences
+/// of the colors. Returns false if it can not, in which case they would be
+/// printed as is. Only the Windows consoles need it, since Windows 10
+#[cfg(windows)]
+pub fn enable_ansi() -> bool {
+    use std::io;
+    use std::os::windows::io::AsRawHandle;
+
+    let handle = io::stdout().as_raw_handle();
+    let mut mode = 0;
+    // SAFETY: the handle of standard output is valid for the duration of the
+    // calls, and mode is a valid pointer
+    unsafe {
+        sys::GetConsoleMode(handle, &mut mode) != 0
+            && (mode & sys::ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+                || sys::SetConsoleMode(handle, mode | sys::ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0)
+    }
+}
+
+#[cfg(not(windows))]
+pub fn enable_ansi() -> bool {
+    true
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::ffi::c_void;
+
+    pub const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        pub fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+}
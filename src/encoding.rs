@@ -0,0 +1,227 @@
+use std::io::{self, Read};
+
+/// Size of the chunks read from the input
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Encoding of the input, transcoded to UTF-8 before the search (--encoding)
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Encoding {
+    /// UTF-16 or UTF-8 if the input starts with their byte order mark, as is
+    /// otherwise
+    Auto,
+    #[value(name = "utf-8", alias = "utf8")]
+    Utf8,
+    #[value(name = "utf-16le", alias = "utf16le")]
+    Utf16Le,
+    #[value(name = "utf-16be", alias = "utf16be")]
+    Utf16Be,
+    #[value(name = "latin-1", alias = "latin1")]
+    Latin1,
+}
+
+impl Encoding {
+    /// Encoding given by the byte order mark at the start of the input, with
+    /// the length of the mark
+    fn sniff(bytes: &[u8]) -> Option<(Encoding, usize)> {
+        match bytes {
+            [0xef, 0xbb, 0xbf, ..] => Some((Encoding::Utf8, 3)),
+            [0xff, 0xfe, ..] => Some((Encoding::Utf16Le, 2)),
+            [0xfe, 0xff, ..] => Some((Encoding::Utf16Be, 2)),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the input transcoded to UTF-8. The byte order mark is removed, and
+/// the invalid sequences are replaced by U+FFFD, except in UTF-8 where the
+/// input is kept as is
+pub struct Decoder<R> {
+    inner: R,
+    /// None until the byte order mark was checked
+    encoding: Option<Encoding>,
+    requested: Encoding,
+    /// bytes read and not decoded yet, such as half of a UTF-16 code unit
+    pending: Vec<u8>,
+    /// decoded bytes not returned yet
+    decoded: Vec<u8>,
+    /// start of the bytes of decoded not returned yet
+    returned: usize,
+    at_end: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(inner: R, encoding: Encoding) -> Self {
+        Self {
+            inner,
+            encoding: None,
+            requested: encoding,
+            pending: Vec::new(),
+            decoded: Vec::new(),
+            returned: 0,
+            at_end: false,
+        }
+    }
+
+    /// Reads a chunk of the input into pending, sets at_end when there is no
+    /// more input
+    fn fill_pending(&mut self) -> io::Result<()> {
+        let len = self.pending.len();
+        self.pending.resize(len + CHUNK_SIZE, 0);
+        let read = loop {
+            match self.inner.read(&mut self.pending[len..]) {
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        let read = read.inspect_err(|_| self.pending.truncate(len))?;
+        self.pending.truncate(len + read);
+        self.at_end = read == 0;
+        Ok(())
+    }
+
+    /// Decides the encoding once the first bytes are known
+    fn sniff(&mut self) -> io::Result<()> {
+        while self.pending.len() < 3 && !self.at_end {
+            self.fill_pending()?;
+        }
+        let (encoding, bom_len) = match Encoding::sniff(&self.pending) {
+            Some((sniffed, len))
+                if self.requested == Encoding::Auto || sniffed == self.requested =>
+            {
+                (sniffed, len)
+            }
+            // without a byte order mark, auto leaves the input as is
+            _ if self.requested == Encoding::Auto => (Encoding::Utf8, 0),
+            _ => (self.requested, 0),
+        };
+        self.pending.drain(..bom_len);
+        self.encoding = Some(encoding);
+        Ok(())
+    }
+
+    /// Decodes the pending bytes into decoded, keeping an incomplete sequence
+    /// at the end unless the input is over
+    fn decode(&mut self, encoding: Encoding) {
+        self.decoded.clear();
+        self.returned = 0;
+        let consumed = match encoding {
+            Encoding::Auto | Encoding::Utf8 => {
+                self.decoded.extend_from_slice(&self.pending);
+                self.pending.len()
+            }
+            Encoding::Latin1 => {
+                let text = self.pending.iter().map(|&byte| byte as char);
+                self.decoded.extend(text.collect::<String>().bytes());
+                self.pending.len()
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let mut units = self
+                    .pending
+                    .chunks_exact(2)
+                    .map(|unit| match encoding {
+                        Encoding::Utf16Le => u16::from_le_bytes([unit[0], unit[1]]),
+                        _ => u16::from_be_bytes([unit[0], unit[1]]),
+                    })
+                    .collect::<Vec<_>>();
+                // the low surrogate may be in the next chunk
+                if !self.at_end
+                    && units
+                        .last()
+                        .is_some_and(|unit| (0xd800..0xdc00).contains(unit))
+                {
+                    units.pop();
+                }
+                let text = char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect::<String>();
+                self.decoded.extend_from_slice(text.as_bytes());
+                let consumed = text.encode_utf16().count() * 2;
+                if self.at_end && consumed < self.pending.len() {
+                    self.decoded
+                        .extend_from_slice(char::REPLACEMENT_CHARACTER.to_string().as_bytes());
+                    self.pending.len()
+                } else {
+                    consumed
+                }
+            }
+        };
+        self.pending.drain(..consumed);
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let encoding = match self.encoding {
+            Some(encoding) => encoding,
+            None => {
+                self.sniff()?;
+                self.encoding.unwrap_or(Encoding::Utf8)
+            }
+        };
+        while self.returned == self.decoded.len() {
+            if self.pending.is_empty() && self.at_end {
+                return Ok(0);
+            }
+            if !self.at_end {
+                self.fill_pending()?;
+            }
+            self.decode(encoding);
+        }
+        let len = buf.len().min(self.decoded.len() - self.returned);
+        buf[..len].copy_from_slice(&self.decoded[self.returned..self.returned + len]);
+        self.returned += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| {
+                if big_endian {
+                    unit.to_be_bytes()
+                } else {
+                    unit.to_le_bytes()
+                }
+            })
+            .collect()
+    }
+
+    fn decode(bytes: &[u8], encoding: Encoding) -> io::Result<String> {
+        let mut decoded = String::new();
+        Decoder::new(bytes, encoding).read_to_string(&mut decoded)?;
+        Ok(decoded)
+    }
+
+    #[rstest]
+    #[case(utf16("\u{feff}é\n𝄞", false), Encoding::Auto, "é\n𝄞")]
+    #[case(utf16("\u{feff}é\n𝄞", true), Encoding::Auto, "é\n𝄞")]
+    #[case(utf16("é\n𝄞", false), Encoding::Utf16Le, "é\n𝄞")]
+    #[case(utf16("é\n𝄞", true), Encoding::Utf16Be, "é\n𝄞")]
+    #[case(b"\xef\xbb\xbfab".to_vec(), Encoding::Auto, "ab")]
+    #[case(b"ab".to_vec(), Encoding::Auto, "ab")]
+    #[case(b"caf\xe9".to_vec(), Encoding::Latin1, "café")]
+    #[case(b"a\0b".to_vec(), Encoding::Utf16Le, "a\u{fffd}")]
+    #[case(vec![0x3d, 0xd8], Encoding::Utf16Le, "\u{fffd}")]
+    fn test_decoder(
+        #[case] bytes: Vec<u8>,
+        #[case] encoding: Encoding,
+        #[case] expected: &str,
+    ) -> io::Result<()> {
+        assert_eq!(decode(&bytes, encoding)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decoder_chunks() -> io::Result<()> {
+        // the surrogate pairs cross the boundaries of the chunks
+        let text = "a𝄞".repeat(CHUNK_SIZE);
+        assert_eq!(decode(&utf16(&text, false), Encoding::Utf16Le)?, text);
+        Ok(())
+    }
+}
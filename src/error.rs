@@ -0,0 +1,134 @@
+use std::ops::Range;
+
+/// Error of the lexing, parsing or compilation of a pattern. The positions are
+/// indexes of chars in the pattern
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum RegexError {
+    /// a ( without its ), or a ) without its (
+    #[error("Unmatched ( or )")]
+    UnbalancedParen { pos: usize },
+    /// a [ without its ]
+    #[error("Unmatched [")]
+    UnclosedBracket { pos: usize },
+    /// a range such as z-a in brackets, whose end comes before its start
+    #[error("Invalid range end")]
+    InvalidRange { pos: usize },
+    /// [:name:] in brackets, with a name that is not a class
+    #[error("Invalid character class name")]
+    UnknownClass { pos: usize },
+    /// a quantifier with nothing to repeat
+    #[error("Misplaced quantifier")]
+    InvalidQuantifier { pos: usize },
+    /// braces that are not a valid {min,max}
+    #[error("Problem parsing braces")]
+    InvalidRepetition { pos: usize },
+    /// a number too large, in braces or a backreference
+    #[error("Invalid number")]
+    InvalidNumber { pos: usize },
+    #[error("Unknown escape \\{ch}")]
+    UnknownEscape { pos: usize, ch: char },
+    #[error("Trailing backslash")]
+    TrailingBackslash { pos: usize },
+    /// (? followed by something else than :, =, !, <=, <! or <name>
+    #[error("Unknown group construct after (?")]
+    UnknownGroup { pos: usize },
+    #[error("Invalid group name")]
+    InvalidGroupName { pos: usize },
+    #[error("The compiled pattern exceeds the size limit of {limit} instructions")]
+    SizeLimitExceeded { limit: usize },
+}
+
+impl RegexError {
+    /// Chars of the pattern where the error is, if it is in the pattern
+    pub fn span(&self, pattern: &str) -> Option<Range<usize>> {
+        let chars = pattern.chars().collect::<Vec<_>>();
+        // up to the char closing the construct, or to the end of the pattern
+        let up_to = |pos: usize, close: char| {
+            chars[pos..]
+                .iter()
+                .position(|&c| c == close)
+                .map_or(chars.len(), |i| pos + i + 1)
+        };
+        let span = match *self {
+            Self::UnbalancedParen { pos }
+            | Self::InvalidQuantifier { pos }
+            | Self::TrailingBackslash { pos } => pos..pos + 1,
+            Self::UnclosedBracket { pos } => pos..chars.len(),
+            // start, - and end
+            Self::InvalidRange { pos } => pos..pos + 3,
+            Self::UnknownClass { pos } => pos..up_to(pos, ']'),
+            Self::InvalidRepetition { pos } => pos..up_to(pos, '}'),
+            Self::InvalidNumber { pos } => {
+                let nb_digits = chars[pos..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_digit())
+                    .count();
+                pos..pos + nb_digits.max(1)
+            }
+            Self::UnknownEscape { pos, .. } => pos..pos + 2,
+            // (? and the char after it
+            Self::UnknownGroup { pos } => pos..pos + 3,
+            Self::InvalidGroupName { pos } => pos..up_to(pos, '>'),
+            Self::SizeLimitExceeded { .. } => return None,
+        };
+        Some(span.start..span.end.min(chars.len()))
+    }
+
+    /// Message followed by the pattern, with the chars of the error
+    /// underlined with ^~~~
+    pub fn diagnostic(&self, pattern: &str) -> String {
+        let Some(span) = self.span(pattern) else {
+            return self.to_string();
+        };
+        // tabs are kept so that the underline stays aligned
+        let indent = pattern
+            .chars()
+            .take(span.start)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let underline = "~".repeat(span.len().saturating_sub(1));
+        format!("{self}\n  {pattern}\n  {indent}^{underline}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("a(b", RegexError::UnbalancedParen { pos: 1 }, Some(1..2))]
+    #[case("a[bc", RegexError::UnclosedBracket { pos: 1 }, Some(1..4))]
+    #[case("[az-a]", RegexError::InvalidRange { pos: 2 }, Some(2..5))]
+    #[case("[[:foo:]a]", RegexError::UnknownClass { pos: 1 }, Some(1..8))]
+    #[case("a{2x}b", RegexError::InvalidRepetition { pos: 1 }, Some(1..5))]
+    #[case("a{2", RegexError::InvalidRepetition { pos: 1 }, Some(1..3))]
+    #[case("a{3,2}", RegexError::InvalidRepetition { pos: 1 }, Some(1..6))]
+    #[case("a{99999999999999999999}", RegexError::InvalidNumber { pos: 2 }, Some(2..22))]
+    #[case("ab\\q", RegexError::UnknownEscape { pos: 2, ch: 'q' }, Some(2..4))]
+    #[case("(?x)", RegexError::UnknownGroup { pos: 0 }, Some(0..3))]
+    #[case("(?<1a>b)", RegexError::InvalidGroupName { pos: 0 }, Some(0..6))]
+    #[case("a", RegexError::SizeLimitExceeded { limit: 1 }, None)]
+    fn test_span(
+        #[case] pattern: &str,
+        #[case] err: RegexError,
+        #[case] expected: Option<Range<usize>>,
+    ) {
+        assert_eq!(err.span(pattern), expected);
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let err = RegexError::UnknownEscape { pos: 3, ch: 'q' };
+        assert_eq!(
+            err.diagnostic("é\ta\\qb"),
+            "Unknown escape \\q\n  é\ta\\qb\n   \t ^~"
+        );
+        let err = RegexError::SizeLimitExceeded { limit: 1 };
+        assert_eq!(
+            err.diagnostic("a"),
+            "The compiled pattern exceeds the size limit of 1 instructions"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+
+use crate::glob::Glob;
+
+/// Types known without --type-add, with the globs of their file names
+const DEFAULT_TYPES: [(&str, &[&str]); 17] = [
+    ("c", &["*.c", "*.h"]),
+    (
+        "cpp",
+        &["*.cpp", "*.cc", "*.cxx", "*.h", "*.hpp", "*.hh", "*.hxx"],
+    ),
+    ("css", &["*.css", "*.scss"]),
+    ("go", &["*.go"]),
+    ("html", &["*.htm", "*.html"]),
+    ("java", &["*.java"]),
+    ("js", &["*.js", "*.jsx", "*.mjs", "*.cjs"]),
+    ("json", &["*.json"]),
+    ("make", &["Makefile", "makefile", "GNUmakefile", "*.mk"]),
+    ("md", &["*.md", "*.markdown"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.sh", "*.bash", "*.zsh"]),
+    ("toml", &["*.toml"]),
+    ("ts", &["*.ts", "*.tsx", "*.mts", "*.cts"]),
+    ("txt", &["*.txt"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+/// Named sets of globs of file names, selected with -t and excluded with -T.
+/// Displayed as the output of --type-list
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileTypes {
+    /// sorted by name
+    types: Vec<(String, Vec<String>)>,
+}
+
+impl Default for FileTypes {
+    fn default() -> Self {
+        let types = DEFAULT_TYPES
+            .iter()
+            .map(|(name, globs)| {
+                let globs = globs.iter().map(|glob| glob.to_string()).collect();
+                (name.to_string(), globs)
+            })
+            .collect();
+        Self { types }
+    }
+}
+
+impl FileTypes {
+    /// Adds a glob to a type, creating the type if needed, from a NAME:GLOB
+    /// definition (--type-add)
+    pub fn add(&mut self, definition: &str) -> anyhow::Result<()> {
+        let Some((name, glob)) = definition.split_once(':') else {
+            anyhow::bail!("invalid type definition {definition}, expected NAME:GLOB");
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            anyhow::bail!("invalid type name {name}");
+        }
+        match self
+            .types
+            .binary_search_by(|(known, _)| known.as_str().cmp(name))
+        {
+            Ok(index) => self.types[index].1.push(glob.to_string()),
+            Err(index) => {
+                self.types
+                    .insert(index, (name.to_string(), vec![glob.to_string()]));
+            }
+        }
+        Ok(())
+    }
+
+    /// Globs of the file names of the type
+    pub fn globs(&self, name: &str) -> anyhow::Result<Vec<Glob>> {
+        match self.types.iter().find(|(known, _)| known == name) {
+            Some((_, globs)) => Ok(globs.iter().map(|glob| Glob::new(glob)).collect()),
+            None => anyhow::bail!("unrecognized file type: {name}"),
+        }
+    }
+}
+
+impl fmt::Display for FileTypes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, globs) in &self.types {
+            writeln!(f, "{name}: {}", globs.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("rust", "main.rs", true)]
+    #[case("rust", "main.c", false)]
+    #[case("make", "Makefile", true)]
+    #[case("proto", "a.proto", true)]
+    #[case("md", "notes.mdx", true)]
+    fn test_file_types(
+        #[case] name: &str,
+        #[case] file: &str,
+        #[case] expected: bool,
+    ) -> anyhow::Result<()> {
+        let mut types = FileTypes::default();
+        types.add("proto:*.proto")?;
+        types.add("md:*.mdx")?;
+        let globs = types.globs(name)?;
+        assert_eq!(globs.iter().any(|glob| glob.is_match(file)), expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("proto")]
+    #[case(":*.proto")]
+    #[case("a b:*.proto")]
+    fn test_invalid_type(#[case] definition: &str) {
+        assert!(FileTypes::default().add(definition).is_err());
+        assert!(FileTypes::default().globs("unknown").is_err());
+    }
+
+    #[test]
+    fn test_type_list() -> anyhow::Result<()> {
+        let mut types = FileTypes::default();
+        types.add("a:*.a")?;
+        let list = types.to_string();
+        assert!(list.starts_with("a: *.a\nc: *.c, *.h\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+/// Piece of a --format template
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    File,
+    /// 1-based line number
+    Line,
+    /// 1-based byte column of the match
+    Column,
+    /// 0-based byte offset of the match in the input
+    Offset,
+    /// the whole line
+    Text,
+    /// text captured by the group, 0 being the whole match
+    Group(usize),
+}
+
+/// Values of the placeholders for a match
+#[derive(Debug, Clone, Copy)]
+pub struct Fields<'a> {
+    pub file: &'a str,
+    pub line_number: usize,
+    /// 0-based byte offset of the line in the input
+    pub line_offset: usize,
+    pub line: &'a [u8],
+    /// slots of the match in the line, empty for a selected line without
+    /// match, with -v
+    pub slots: &'a [Option<usize>],
+}
+
+/// Template of the output of each match with --format. {file}, {line},
+/// {column}, {offset} and {text} are the file name, line number, column and
+/// byte offset of the match and its line, {match} the matched text, {n} and
+/// {name} the text captured by a group. {{ and }} are braces. Unknown
+/// placeholders are printed as is
+#[derive(Debug, Clone, PartialEq)]
+pub struct Format {
+    parts: Vec<Part>,
+}
+
+impl Format {
+    /// `names` are the named groups of the pattern, with their number
+    pub fn new(template: &str, names: &[(String, usize)]) -> Self {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+        while let Some(brace) = rest.find(['{', '}']) {
+            literal.push_str(&rest[..brace]);
+            let (brace, after) = rest[brace..].split_at(1);
+            rest = after;
+            if let Some(after) = rest.strip_prefix(brace) {
+                // {{ or }}
+                literal.push_str(brace);
+                rest = after;
+                continue;
+            }
+            let placeholder = if brace == "{" {
+                rest.split_once('}')
+            } else {
+                None
+            };
+            let Some((name, after)) = placeholder else {
+                literal.push_str(brace);
+                continue;
+            };
+            let part = match name {
+                "file" => Part::File,
+                "line" => Part::Line,
+                "column" => Part::Column,
+                "offset" => Part::Offset,
+                "text" => Part::Text,
+                "match" => Part::Group(0),
+                _ => match name.parse::<usize>() {
+                    Ok(group) => Part::Group(group),
+                    Err(_) => match names.iter().find(|(group_name, _)| group_name == name) {
+                        Some((_, group)) => Part::Group(*group),
+                        None => {
+                            literal.push('{');
+                            continue;
+                        }
+                    },
+                },
+            };
+            rest = after;
+            if !literal.is_empty() {
+                parts.push(Part::Literal(std::mem::take(&mut literal)));
+            }
+            parts.push(part);
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Self { parts }
+    }
+
+    /// Appends the expansion of the template for a match
+    pub fn expand(&self, fields: &Fields, out: &mut Vec<u8>) {
+        let group = |group: usize| {
+            let start = fields.slots.get(group * 2).copied().flatten()?;
+            let end = fields.slots.get(group * 2 + 1).copied().flatten()?;
+            Some((start, end))
+        };
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => out.extend_from_slice(literal.as_bytes()),
+                Part::File => out.extend_from_slice(fields.file.as_bytes()),
+                Part::Line => out.extend_from_slice(fields.line_number.to_string().as_bytes()),
+                Part::Column => {
+                    let column = group(0).map_or(1, |(start, _)| start + 1);
+                    out.extend_from_slice(column.to_string().as_bytes());
+                }
+                Part::Offset => {
+                    let offset = fields.line_offset + group(0).map_or(0, |(start, _)| start);
+                    out.extend_from_slice(offset.to_string().as_bytes());
+                }
+                Part::Text => out.extend_from_slice(fields.line),
+                Part::Group(group_index) => {
+                    if let Some((start, end)) = group(*group_index) {
+                        out.extend_from_slice(&fields.line[start..end]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("{file}:{line}:{column}: {text}", "f.rs:3:2: xabc")]
+    #[case("{offset} {match} {2}-{1} {last}", "11 abc c-ab c")]
+    #[case("{{{match}}} {unknown} {3} {line", "{abc} {unknown}  {line")]
+    #[case("}{}", "}{}")]
+    fn test_format(#[case] template: &str, #[case] expected: &str) {
+        let names = [("last".to_string(), 2)];
+        let format = Format::new(template, &names);
+        // "abc" matched by (ab)(c) in "xabc"
+        let slots = [Some(1), Some(4), Some(1), Some(3), Some(3), Some(4)];
+        let fields = Fields {
+            file: "f.rs",
+            line_number: 3,
+            line_offset: 10,
+            line: b"xabc",
+            slots: &slots,
+        };
+
+        let mut out = Vec::new();
+        format.expand(&fields, &mut out);
+        assert_eq!(String::from_utf8_lossy(&out), expected);
+    }
+}
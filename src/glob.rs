@@ -0,0 +1,182 @@
+use grep_starter_rust::char_class::{CharClass, ClassRange};
+
+#[derive(Debug, Clone, PartialEq)]
+enum GlobToken {
+    Literal(char),
+    /// ?
+    AnyChar,
+    /// *, does not match /
+    Star,
+    /// **, also matches /
+    DoubleStar,
+    /// **/, empty or any sequence ending with a /
+    AnyDirs,
+    /// [abc], [a-z] or [!abc]
+    Class(CharClass),
+}
+
+/// Shell pattern, as used by --include and --exclude: `*` matches any sequence
+/// of chars except '/', `**` any sequence, `**/` any number of directories,
+/// `?` a single char and `[...]` a char of the set (`[!...]` or `[^...]` for
+/// the complement).
+/// `\` removes the special meaning of the next char. The separator of the
+/// paths matched is '/', on Windows too.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glob {
+    tokens: Vec<GlobToken>,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Self {
+        let chars = pattern.chars().collect::<Vec<_>>();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let token = match chars[i] {
+                '*' if chars[i + 1..].starts_with(&['*', '/']) => {
+                    i += 2;
+                    GlobToken::AnyDirs
+                }
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    i += 1;
+                    GlobToken::DoubleStar
+                }
+                '*' => GlobToken::Star,
+                '?' => GlobToken::AnyChar,
+                '\\' if i + 1 < chars.len() => {
+                    i += 1;
+                    GlobToken::Literal(chars[i])
+                }
+                '[' => match Self::parse_class(&chars[i + 1..]) {
+                    Some((class, len)) => {
+                        i += len;
+                        GlobToken::Class(class)
+                    }
+                    // an unclosed bracket is a literal
+                    None => GlobToken::Literal('['),
+                },
+                c => GlobToken::Literal(c),
+            };
+            tokens.push(token);
+            i += 1;
+        }
+        Self { tokens }
+    }
+
+    /// Parses the set after a [, returns it with the number of chars read,
+    /// including the closing bracket
+    fn parse_class(chars: &[char]) -> Option<(CharClass, usize)> {
+        let mut class = CharClass::default();
+        let negated = matches!(chars.first(), Some('!' | '^'));
+        let mut i = usize::from(negated);
+        let start = i;
+        loop {
+            let c = *chars.get(i)?;
+            // a ] right after the [ is part of the set
+            if c == ']' && i > start {
+                break;
+            }
+            match (chars.get(i + 1), chars.get(i + 2)) {
+                (Some('-'), Some(&end)) if end != ']' => {
+                    // a reversed range matches nothing
+                    if c <= end {
+                        class.union(&CharClass::new(vec![ClassRange::new(c, end)]));
+                    }
+                    i += 3;
+                }
+                _ => {
+                    class.union(&CharClass::single(c));
+                    i += 1;
+                }
+            }
+        }
+        if negated {
+            class.negate();
+        }
+        Some((class, i + 1))
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars = text
+            .chars()
+            .map(|c| if cfg!(windows) && c == '\\' { '/' } else { c })
+            .collect::<Vec<_>>();
+        Self::match_tokens(&self.tokens, &chars)
+    }
+
+    fn match_tokens(tokens: &[GlobToken], chars: &[char]) -> bool {
+        let Some((token, rest)) = tokens.split_first() else {
+            return chars.is_empty();
+        };
+        match token {
+            GlobToken::Star | GlobToken::DoubleStar => {
+                // tries every length for the sequence, shortest first
+                for len in 0..=chars.len() {
+                    if Self::match_tokens(rest, &chars[len..]) {
+                        return true;
+                    }
+                    if len < chars.len() && chars[len] == '/' && *token == GlobToken::Star {
+                        return false;
+                    }
+                }
+                false
+            }
+            GlobToken::AnyDirs => {
+                Self::match_tokens(rest, chars)
+                    || (0..chars.len())
+                        .filter(|&i| chars[i] == '/')
+                        .any(|i| Self::match_tokens(rest, &chars[i + 1..]))
+            }
+            _ => {
+                let Some((&c, chars)) = chars.split_first() else {
+                    return false;
+                };
+                let is_matching = match token {
+                    GlobToken::Literal(literal) => c == *literal,
+                    GlobToken::AnyChar => c != '/',
+                    GlobToken::Class(class) => c != '/' && class.contains(c),
+                    _ => unreachable!(),
+                };
+                is_matching && Self::match_tokens(rest, chars)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("*.rs", "main.rs", true)]
+    #[case("*.rs", "main.rs.bak", false)]
+    #[case("*.rs", "src/main.rs", false)]
+    #[case("**/*.rs", "src/bin/main.rs", true)]
+    #[case("**/main.rs", "main.rs", true)]
+    #[case("**/main.rs", "src/main.rs", true)]
+    #[case("src/**/main.rs", "src/bin/main.rs", true)]
+    #[case("src/**/main.rs", "src/main.rs", true)]
+    #[case("src/**/main.rs", "src/xmain.rs", false)]
+    #[case("?.txt", "a.txt", true)]
+    #[case("?.txt", "ab.txt", false)]
+    #[case("[a-c]*", "beta", true)]
+    #[case("[!a-c]*", "beta", false)]
+    #[case("[]x]", "]", true)]
+    #[case("a[", "a[", true)]
+    #[case("\\*", "*", true)]
+    #[case("\\*", "a", false)]
+    #[case("*.min.js", "app.min.js", true)]
+    #[case("", "", true)]
+    fn test_glob(#[case] pattern: &str, #[case] text: &str, #[case] expected: bool) {
+        assert_eq!(Glob::new(pattern).is_match(text), expected);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_glob_backslash_separators() {
+        assert!(Glob::new("src/**/*.rs").is_match("src\\bin\\main.rs"));
+        assert!(!Glob::new("*.rs").is_match("src\\main.rs"));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::regex_parser::Node;
+use crate::stats::Stats;
+
+/// Boyer-Moore-Horspool search for patterns that are a plain string.
+/// The needle is compared from its end and, on mismatch, the window is shifted
+/// according to the byte aligned with the last byte of the needle, which allows
+/// to skip most of the haystack for long needles.
+/// It searches the UTF-8 bytes of the haystack, without decoding them: UTF-8 is
+/// self-synchronizing, so the bytes of the needle only match at a char boundary
+#[derive(Debug, Clone)]
+pub struct Horspool {
+    /// UTF-8 encoding of the needle
+    needle: Vec<u8>,
+    /// shift for each byte, boxed to keep the engines small
+    shifts: Box<[usize; 256]>,
+    stats: Stats,
+}
+
+impl Horspool {
+    pub fn new(needle: Vec<char>) -> Self {
+        let needle = needle.into_iter().collect::<String>().into_bytes();
+        let len = needle.len();
+        let mut shifts = Box::new([len.max(1); 256]);
+
+        // the last byte is excluded: it would give a shift of 0
+        for (i, &byte) in needle.iter().enumerate().take(len.saturating_sub(1)) {
+            shifts[byte as usize] = len - 1 - i;
+        }
+
+        Self {
+            needle,
+            shifts,
+            stats: Stats::default(),
+        }
+    }
+
+    /// Returns None if the pattern is not made only of literals. A U+FFFD in
+    /// the pattern is left to the engines, which read the invalid UTF-8
+    /// sequences of the haystack as U+FFFD
+    pub fn from_node(node: &Node) -> Option<Self> {
+        let mut needle = Vec::new();
+        if Self::collect_literals(node, &mut needle)
+            && !needle.is_empty()
+            && !needle.contains(&char::REPLACEMENT_CHARACTER)
+        {
+            Some(Self::new(needle))
+        } else {
+            None
+        }
+    }
+
+    /// Appends the chars of the pattern to the needle, returns false if it is
+    /// not made only of literals
+    pub fn collect_literals(node: &Node, needle: &mut Vec<char>) -> bool {
+        match node {
+            Node::Literal(c) => {
+                needle.push(*c);
+                true
+            }
+            Node::Group { nodes, .. } => nodes.iter().all(|n| Self::collect_literals(n, needle)),
+            _ => false,
+        }
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Byte offset of the first occurence of the needle in the haystack
+    pub fn find(&mut self, haystack: &[u8]) -> Option<usize> {
+        let len = self.needle.len();
+        if len == 0 {
+            return Some(0);
+        }
+
+        let mut start = 0;
+        let mut found = None;
+        while start + len <= haystack.len() {
+            let window = &haystack[start..start + len];
+            if window
+                .iter()
+                .rev()
+                .zip(self.needle.iter().rev())
+                .all(|(a, b)| a == b)
+            {
+                found = Some(start);
+                break;
+            }
+            let shift = self.shifts[window[len - 1] as usize];
+            self.stats.prefilter_skips += shift - 1;
+            start += shift;
+        }
+
+        let end = found.map_or(haystack.len(), |start| start + len);
+        self.stats.bytes_scanned += end;
+        found
+    }
+
+    pub fn is_match(&mut self, haystack: &[u8]) -> bool {
+        self.find(haystack).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::{regex_lexer::RegexLexer, regex_parser::RegexParser};
+
+    use super::*;
+
+    #[rstest]
+    #[case("abc", true)]
+    #[case("a(bc)", true)]
+    #[case("a\\.b", true)]
+    #[case("a.b", false)]
+    #[case("^abc", false)]
+    #[case("ab?", false)]
+    fn test_horspool_support(#[case] pat: &str, #[case] expected: bool) -> anyhow::Result<()> {
+        let lexer = RegexLexer::new(pat);
+        let mut parser = RegexParser::new(lexer)?;
+        let node = parser.build_ast(0)?;
+
+        assert_eq!(Horspool::from_node(&node).is_some(), expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("abc", "abc", Some(0))]
+    #[case("abc", "xxabcxx", Some(2))]
+    #[case("abc", "ababab", None)]
+    #[case("needle", "haystack with a needle in it", Some(16))]
+    #[case("aab", "aaaaaab", Some(4))]
+    #[case("été", "un été chaud", Some(3))]
+    #[case("é", "\u{e8}\u{c9}é", Some(4))]
+    #[case("x", "", None)]
+    #[case("", "abc", Some(0))]
+    fn test_horspool_find(
+        #[case] needle: &str,
+        #[case] haystack: &str,
+        #[case] expected: Option<usize>,
+    ) {
+        let mut horspool = Horspool::new(needle.chars().collect());
+        assert_eq!(horspool.find(haystack.as_bytes()), expected);
+    }
+
+    #[test]
+    fn test_horspool_stats() {
+        let mut horspool = Horspool::new("needle".chars().collect());
+        assert_eq!(horspool.find(b"a haystack with a needle"), Some(18));
+        // windows start at 0, 6, 12 and 18
+        assert_eq!(horspool.stats().prefilter_skips, 3 * 5);
+        assert_eq!(horspool.stats().bytes_scanned, 24);
+    }
+}
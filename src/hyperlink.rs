@@ -0,0 +1,123 @@
+use std::fmt::Write;
+use std::path::Path;
+
+/// Ends the hyperlink started by start
+pub const END: &[u8] = b"\x1b]8;;\x1b\\";
+
+/// Templates of the aliases accepted by --hyperlink-format
+const ALIASES: [(&str, &str); 8] = [
+    ("default", "file://{host}{path}"),
+    ("file", "file://{host}{path}"),
+    ("kitty", "file://{host}{path}#{line}"),
+    ("vscode", "vscode://file{path}:{line}:{column}"),
+    (
+        "vscode-insiders",
+        "vscode-insiders://file{path}:{line}:{column}",
+    ),
+    ("cursor", "cursor://file{path}:{line}:{column}"),
+    (
+        "macvim",
+        "mvim://open?url=file://{path}&line={line}&column={column}",
+    ),
+    (
+        "textmate",
+        "txmt://open?url=file://{path}&line={line}&column={column}",
+    ),
+];
+
+/// URL of the hyperlinks on the file names (--hyperlink-format), written as
+/// OSC 8 escape sequences, which the terminals supporting them make clickable
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyperlinkFormat {
+    /// with the host already replaced
+    template: String,
+}
+
+impl HyperlinkFormat {
+    /// Parses an alias such as vscode, or a template where {path} is the
+    /// absolute path of the file, {host} the host name, {line} and {column}
+    /// the position of the line. Returns None for none
+    pub fn new(format: &str) -> anyhow::Result<Option<Self>> {
+        if format == "none" || format.is_empty() {
+            return Ok(None);
+        }
+        let template = ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == format)
+            .map_or(format, |(_, template)| template);
+        if !template.contains("{path}") {
+            anyhow::bail!("the hyperlink format must contain {{path}}: {format}");
+        }
+        Ok(Some(Self {
+            template: template.replace("{host}", &host_name()),
+        }))
+    }
+
+    /// URL of the file at the position, 1-based
+    pub fn url(&self, path: &Path, line: usize, column: usize) -> String {
+        self.template
+            .replace("{line}", &line.to_string())
+            .replace("{column}", &column.to_string())
+            .replace("{path}", &encode_path(path))
+    }
+}
+
+/// Starts a hyperlink to the URL, on the text written until END
+pub fn start(url: &str) -> String {
+    format!("\x1b]8;;{url}\x1b\\")
+}
+
+fn host_name() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|host| host.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Path percent-encoded for a URL, starting with a /
+fn encode_path(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut encoded = String::new();
+    if !path.starts_with('/') {
+        // C:/dir on Windows
+        encoded.push('/');
+    }
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~:".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("vscode", "vscode://file/src/a%20b.rs:3:7")]
+    #[case("kitty", "file://HOST/src/a%20b.rs#3")]
+    #[case("x://{path}?l={line}", "x:///src/a%20b.rs?l=3")]
+    fn test_url(#[case] format: &str, #[case] expected: &str) -> anyhow::Result<()> {
+        let Some(format) = HyperlinkFormat::new(format)? else {
+            anyhow::bail!("no hyperlink format");
+        };
+        let url = format.url(Path::new("/src/a b.rs"), 3, 7);
+        assert_eq!(url, expected.replace("HOST", &host_name()));
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("none", true)]
+    #[case("", true)]
+    #[case("file://{line}", false)]
+    fn test_no_url(#[case] format: &str, #[case] is_ok: bool) {
+        let format = HyperlinkFormat::new(format);
+        assert_eq!(format.is_ok_and(|format| format.is_none()), is_ok);
+    }
+}
@@ -0,0 +1,195 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::glob::Glob;
+
+/// Ignore files read in each directory. The rules of .ignore take precedence
+/// over the ones of .gitignore
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// One line of an ignore file
+#[derive(Debug)]
+struct IgnoreRule {
+    glob: Glob,
+    /// !pattern, the path is not ignored
+    whitelist: bool,
+    /// pattern/, only matches directories
+    dir_only: bool,
+    /// a pattern with a / is relative to the directory of the ignore file,
+    /// otherwise it matches the base name at any depth
+    anchored: bool,
+}
+
+impl IgnoreRule {
+    /// Returns None for empty lines and comments
+    fn parse(line: &str) -> Option<Self> {
+        let mut line = line.trim_end_matches('\r');
+        // trailing spaces are ignored unless escaped with a \
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line = &line[..line.len() - 1];
+        }
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let whitelist = line.starts_with('!');
+        if whitelist {
+            line = &line[1..];
+        }
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        (!line.is_empty()).then(|| Self {
+            glob: Glob::new(line),
+            whitelist,
+            dir_only,
+            anchored,
+        })
+    }
+}
+
+/// Rules of the ignore files of a directory, followed while walking its tree.
+/// As in git, the rules of the deepest directory win, and in a file, the last
+/// matching rule wins
+#[derive(Debug)]
+pub struct Ignore {
+    parent: Option<Rc<Ignore>>,
+    dir: PathBuf,
+    rules: Vec<IgnoreRule>,
+}
+
+impl Ignore {
+    fn new(parent: Option<Rc<Ignore>>, dir: &Path, content: &str) -> Self {
+        Self {
+            parent,
+            dir: dir.to_path_buf(),
+            rules: content.lines().filter_map(IgnoreRule::parse).collect(),
+        }
+    }
+
+    /// Rules of the global git excludes file followed by the extra rules,
+    /// such as the ones of --ignore-file, applied from the root of the walk
+    pub fn global(root: &Path, extra: &str) -> Rc<Self> {
+        let mut content = global_excludes_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        content.push('\n');
+        content.push_str(extra);
+        Rc::new(Self::new(None, root, &content))
+    }
+
+    /// Rules for the files of the directory, its ignore files added to the
+    /// ones of its parents
+    pub fn child(self: &Rc<Self>, dir: &Path) -> io::Result<Rc<Self>> {
+        let mut content = String::new();
+        for name in IGNORE_FILES {
+            match fs::read_to_string(dir.join(name)) {
+                Ok(file) => {
+                    content.push_str(&file);
+                    content.push('\n');
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if content.is_empty() {
+            return Ok(Rc::clone(self));
+        }
+        Ok(Rc::new(Self::new(Some(Rc::clone(self)), dir, &content)))
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let relative = path.strip_prefix(&self.dir).unwrap_or(path);
+        let relative = relative.to_string_lossy();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let rule = self.rules.iter().rev().find(|rule| {
+            (is_dir || !rule.dir_only)
+                && rule
+                    .glob
+                    .is_match(if rule.anchored { &relative } else { &name })
+        });
+        match (rule, &self.parent) {
+            (Some(rule), _) => !rule.whitelist,
+            (None, Some(parent)) => parent.is_ignored(path, is_dir),
+            (None, None) => false,
+        }
+    }
+}
+
+/// core.excludesFile of ~/.gitconfig, $XDG_CONFIG_HOME/git/ignore by default
+fn global_excludes_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let configured = home.as_ref().and_then(|home| {
+        let config = fs::read_to_string(home.join(".gitconfig")).ok()?;
+        let mut section = String::new();
+        config.lines().find_map(|line| {
+            let line = line.trim();
+            if line.starts_with('[') {
+                section = line.trim_matches(['[', ']']).trim().to_lowercase();
+                return None;
+            }
+            let (key, value) = line.split_once('=')?;
+            if section != "core" || !key.trim().eq_ignore_ascii_case("excludesfile") {
+                return None;
+            }
+            let value = value.trim().trim_matches('"');
+            Some(match value.strip_prefix("~/") {
+                Some(rest) => home.join(rest),
+                None => PathBuf::from(value),
+            })
+        })
+    });
+    configured.or_else(|| {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(".config")))?;
+        Some(config_dir.join("git/ignore"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("*.log", "a/b.log", false, true)]
+    #[case("*.log", "a/b.txt", false, false)]
+    #[case("target/", "target", true, true)]
+    #[case("target/", "target", false, false)]
+    #[case("/build", "build", true, true)]
+    #[case("/build", "a/build", true, false)]
+    #[case("doc/*.md", "doc/a.md", false, true)]
+    #[case("doc/*.md", "a/doc/a.md", false, false)]
+    #[case("**/doc", "a/doc", true, true)]
+    #[case("*.log\n!keep.log", "keep.log", false, false)]
+    #[case("!keep.log\n*.log", "keep.log", false, true)]
+    #[case("# *.log\n\n", "a.log", false, false)]
+    #[case("\\#a", "#a", false, true)]
+    #[case("a.log   ", "a.log", false, true)]
+    fn test_ignore(
+        #[case] content: &str,
+        #[case] path: &str,
+        #[case] is_dir: bool,
+        #[case] expected: bool,
+    ) {
+        let root = Path::new("root");
+        let ignore = Ignore::new(None, root, content);
+        assert_eq!(ignore.is_ignored(&root.join(path), is_dir), expected);
+    }
+
+    #[test]
+    fn test_nested_ignore() {
+        let root = Path::new("root");
+        let parent = Rc::new(Ignore::new(None, root, "*.log\n/a.txt"));
+        let child = Ignore::new(Some(parent), &root.join("sub"), "!keep.log\n");
+
+        assert!(child.is_ignored(&root.join("sub/b.log"), false));
+        assert!(!child.is_ignored(&root.join("sub/keep.log"), false));
+        assert!(!child.is_ignored(&root.join("sub/a.txt"), false));
+        assert!(child.is_ignored(&root.join("a.txt"), false));
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt::Write;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// JSON string literal of the text, with its quotes
+pub fn string(text: &str) -> String {
+    let mut literal = String::with_capacity(text.len() + 2);
+    literal.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(literal, "\\u{:04x}", c as u32);
+            }
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Object holding arbitrary data, as in ripgrep: {"text":...} when it is valid
+/// UTF-8, {"bytes":...} with the base64 of the data otherwise
+pub fn data(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => format!("{{\"text\":{}}}", string(text)),
+        Err(_) => format!("{{\"bytes\":\"{}\"}}", base64(bytes)),
+    }
+}
+
+/// Standard base64, with padding
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0, |group, (i, &byte)| group | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0x3f;
+                encoded.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("ab", r#""ab""#)]
+    #[case("a\"b\\c", r#""a\"b\\c""#)]
+    #[case("a\nb\u{1}", r#""a\nb\u0001""#)]
+    #[case("été", r#""été""#)]
+    fn test_string(#[case] text: &str, #[case] expected: &str) {
+        assert_eq!(string(text), expected);
+    }
+
+    #[rstest]
+    #[case(b"ab", r#"{"text":"ab"}"#)]
+    #[case(b"\xff", r#"{"bytes":"/w=="}"#)]
+    #[case(b"\xffab", r#"{"bytes":"/2Fi"}"#)]
+    #[case(b"\xffabc", r#"{"bytes":"/2FiYw=="}"#)]
+    fn test_data(#[case] bytes: &[u8], #[case] expected: &str) {
+        assert_eq!(data(bytes), expected);
+    }
+}
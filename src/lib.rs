@@ -0,0 +1,36 @@
+//! Regular expression engine of the grep command, usable on its own through
+//! [`Regex`]. The modules of the engine are public for the command, which
+//! searches with them directly. The AST of a pattern, given by
+//! [`RegexBuilder::build_ast`], can be analyzed with a [`Visitor`]
+//! and exchanged as JSON with [`Node::to_json`] and [`Node::from_json`]
+
+pub mod aho_corasick;
+mod ast_json;
+pub mod backtrack;
+pub mod char_class;
+mod error;
+pub mod horspool;
+mod regex;
+pub mod regex_compiler;
+pub mod regex_lexer;
+pub mod regex_matcher;
+pub mod regex_parser;
+mod regex_set;
+pub mod replace;
+pub mod shift_or;
+pub mod sparse_set;
+pub mod stats;
+mod utf8;
+mod visitor;
+
+pub use crate::ast_json::JsonError;
+pub use crate::error::RegexError;
+pub use crate::regex::{
+    CaptureMatches, Captures, Match, Matches, Regex, RegexBuilder, Split, SplitN,
+};
+pub use crate::regex_compiler::MatchKind;
+pub use crate::regex_lexer::Dialect;
+pub use crate::regex_parser::Node;
+pub use crate::regex_set::{RegexSet, SetMatches};
+pub use crate::replace::{NoExpand, Replacer};
+pub use crate::visitor::{walk, Visitor};
@@ -1,62 +1,1635 @@
-mod regex_lexer;
-mod regex_matcher;
-mod regex_parser;
+mod annotation;
+mod colors;
+mod command_reader;
+mod completions;
+mod config;
+mod console;
+mod encoding;
+mod file_types;
+mod format;
+mod glob;
+mod hyperlink;
+mod ignore;
+mod json;
+mod man;
+mod mmap;
+mod output;
+mod progress;
+mod searcher;
+mod walk;
+mod watch;
 
-use clap::Parser;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, ValueEnum};
 use clap_stdin::FileOrStdin;
-use regex_lexer::RegexLexer;
+use grep_starter_rust::regex_lexer::{Dialect, RegexLexer, RegexToken};
+use grep_starter_rust::regex_parser::Node;
+use grep_starter_rust::MatchKind;
+use grep_starter_rust::RegexBuilder;
+
+use crate::colors::Colors;
+use crate::command_reader::{decompressor, CommandReader};
+use crate::completions::Shell;
+use crate::encoding::{Decoder, Encoding};
+use crate::file_types::FileTypes;
+use crate::glob::Glob;
+use crate::hyperlink::HyperlinkFormat;
+use crate::mmap::{should_map, Mmap};
+use crate::output::Output;
+use crate::progress::Progress;
+use crate::searcher::{BinaryFiles, OutputMode, SearchOptions, Searcher};
+use crate::walk::{parse_size, relative_name, FileFilter, SortBy, Walk, WalkOptions};
+use crate::watch::{Change, Watch};
+
+/// When to highlight the output
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum ColorChoice {
+    /// only when writing to a terminal, and NO_COLOR is not set
+    Auto,
+    Always,
+    Never,
+}
 
-use crate::regex_matcher::Matcher;
-use crate::regex_parser::RegexParser;
+/// What to do with the directories given on the command line (-d)
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Directories {
+    /// read them as files, which reports an error
+    Read,
+    /// ignore them silently
+    Skip,
+    /// search their files, as -r
+    Recurse,
+}
 
 #[derive(Parser)]
 #[command(
     version,
     about = "Custom grep",
-    long_about = "Search for patterns in a file"
+    long_about = "Search for patterns in a file",
+    // -h is --no-filename, as in GNU grep
+    disable_help_flag = true,
+    // the command line overrides the configuration file
+    args_override_self = true
 )]
 struct Cli {
+    #[arg(long, action = ArgAction::Help, help = "Print help")]
+    help: Option<bool>,
     #[arg(
         short('E'),
         long,
-        help = "Interpret patterns as extended regular expression",
-        // required = true
+        overrides_with_all = ["fixed_strings", "basic_regexp", "perl_regexp"],
+        help = "Interpret patterns as extended regular expressions"
     )]
     extended_regexp: bool,
-    #[arg(help = "One or more patterns separated by newline characters")]
-    pattern: String,
     #[arg(
-        help = "A file of - stands for standard input. In this version, there is no recursive search, so no files also means standard input",
-        default_value = "-"
+        short('F'),
+        long,
+        overrides_with_all = ["extended_regexp", "basic_regexp", "perl_regexp"],
+        help = "Interpret patterns as fixed strings, not regular expressions"
+    )]
+    fixed_strings: bool,
+    #[arg(
+        short('G'),
+        long,
+        overrides_with_all = ["extended_regexp", "fixed_strings", "perl_regexp"],
+        help = "Interpret patterns as basic regular expressions, the default"
+    )]
+    basic_regexp: bool,
+    #[arg(
+        short('P'),
+        long,
+        overrides_with_all = ["extended_regexp", "fixed_strings", "basic_regexp"],
+        help = "Interpret patterns as Perl regular expressions, with lookarounds and lazy quantifiers"
+    )]
+    perl_regexp: bool,
+    #[arg(
+        short,
+        long,
+        overrides_with = "smart_case",
+        help = "Ignore case distinctions in patterns and data"
+    )]
+    ignore_case: bool,
+    #[arg(
+        short('S'),
+        long,
+        overrides_with = "ignore_case",
+        help = "Ignore case distinctions if the pattern has no uppercase letter"
+    )]
+    smart_case: bool,
+    #[arg(
+        short('w'),
+        long,
+        help = "Select only the matches that are not part of a bigger word"
+    )]
+    word_regexp: bool,
+    #[arg(
+        short('x'),
+        long,
+        help = "Select only the matches that exactly match the whole line"
+    )]
+    line_regexp: bool,
+    #[arg(short('v'), long, help = "Select non-matching lines")]
+    invert_match: bool,
+    #[arg(short, long, help = "Print only a count of selected lines per file")]
+    count: bool,
+    #[arg(
+        long,
+        help = "Print only a count of the matches per file, which can be several per line. Overrides -c"
+    )]
+    count_matches: bool,
+    #[arg(
+        long,
+        help = "Print only the number of selected lines matched by each pattern given with -e or -f, per file"
+    )]
+    count_per_pattern: bool,
+    #[arg(
+        short('l'),
+        long,
+        overrides_with = "files_without_match",
+        help = "Print only names of files with selected lines"
+    )]
+    files_with_matches: bool,
+    #[arg(
+        short('L'),
+        long,
+        overrides_with = "files_with_matches",
+        help = "Print only names of files with no selected lines"
+    )]
+    files_without_match: bool,
+    #[arg(
+        short('q'),
+        long,
+        alias = "silent",
+        help = "Suppress all normal output, exit as soon as a line is selected"
+    )]
+    quiet: bool,
+    #[arg(
+        short('s'),
+        long,
+        help = "Suppress error messages about nonexistent or unreadable files"
+    )]
+    no_messages: bool,
+    #[arg(
+        short('m'),
+        long,
+        value_name = "NUM",
+        help = "Stop reading a file after NUM selected lines"
+    )]
+    max_count: Option<usize>,
+    #[arg(
+        short('A'),
+        long,
+        value_name = "NUM",
+        help = "Print NUM lines of trailing context"
+    )]
+    after_context: Option<usize>,
+    #[arg(
+        short('B'),
+        long,
+        value_name = "NUM",
+        help = "Print NUM lines of leading context"
+    )]
+    before_context: Option<usize>,
+    #[arg(
+        short('C'),
+        long,
+        value_name = "NUM",
+        help = "Print NUM lines of output context"
+    )]
+    context: Option<usize>,
+    #[arg(
+        long,
+        visible_alias = "context-separator",
+        value_name = "SEP",
+        overrides_with = "no_group_separator",
+        help = "Print SEP between the groups of context lines, instead of --"
+    )]
+    group_separator: Option<String>,
+    #[arg(
+        long,
+        visible_alias = "no-context-separator",
+        overrides_with = "group_separator",
+        help = "Print nothing between the groups of context lines"
+    )]
+    no_group_separator: bool,
+    #[arg(
+        short('o'),
+        long,
+        help = "Print only the matched parts of a matching line, one per line"
+    )]
+    only_matching: bool,
+    #[arg(
+        long,
+        requires = "only_matching",
+        help = "With -o, print each distinct match once per file, with its number of occurrences if -c is given"
+    )]
+    unique: bool,
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        help = "Print the matches replaced by the template, where $1 or ${name} is the text of a group ($0 the whole match)"
+    )]
+    replace: Option<String>,
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        help = "Print the template for each match instead of the selected lines. {file}, {line}, {column}, {offset}, {text} and {match} are the file name, line number, column, byte offset, line and text of the match, {1} or {name} the text of a group. {{ and }} are braces. github prints the selected lines as GitHub Actions annotations, sarif as a SARIF document"
+    )]
+    format: Option<String>,
+    #[arg(
+        long,
+        help = "Print the 1-based column of the first match of each line, or of each match with -o. Implies -n"
+    )]
+    column: bool,
+    #[arg(
+        long,
+        help = "Print file:line:column:line once per match, for the quickfix list of Vim"
+    )]
+    vimgrep: bool,
+    #[arg(
+        long,
+        overrides_with = "no_heading",
+        help = "Print the name of each file once, above its lines. The default when the output is a terminal"
+    )]
+    heading: bool,
+    #[arg(
+        long,
+        overrides_with = "heading",
+        help = "Prefix each line with the name of its file"
+    )]
+    no_heading: bool,
+    #[arg(short('n'), long, help = "Print line number with output lines")]
+    line_number: bool,
+    #[arg(
+        short('b'),
+        long,
+        help = "Print the 0-based byte offset of each output line, or of each match with -o"
+    )]
+    byte_offset: bool,
+    #[arg(
+        short('H'),
+        long,
+        overrides_with = "no_filename",
+        help = "Print the file name for each match, the default when there are several files"
+    )]
+    with_filename: bool,
+    #[arg(
+        short('h'),
+        long,
+        overrides_with = "with_filename",
+        help = "Suppress the file name prefix on output"
+    )]
+    no_filename: bool,
+    #[arg(
+        short('Z'),
+        long,
+        help = "Follow the file names in the output by a NUL byte instead of the character after them, for xargs -0"
+    )]
+    null: bool,
+    #[arg(
+        long,
+        help = "Align the line numbers and byte offsets, and put a tab before the content of the lines. -T is --type-not"
+    )]
+    initial_tab: bool,
+    #[arg(
+        short('M'),
+        long,
+        value_name = "NUM",
+        help = "Replace the lines longer than NUM bytes by an omission marker"
+    )]
+    max_columns: Option<usize>,
+    #[arg(
+        long,
+        requires = "max_columns",
+        help = "Print the NUM bytes of the long lines around their first match, between omission markers"
+    )]
+    max_columns_preview: bool,
+    #[arg(
+        short('e'),
+        long,
+        value_name = "PATTERNS",
+        help = "Use PATTERNS for matching, can be given several times"
+    )]
+    regexp: Vec<String>,
+    #[arg(
+        short('f'),
+        long = "file",
+        value_name = "FILE",
+        help = "Take patterns from FILE, one per line. - stands for standard input"
+    )]
+    pattern_files: Vec<String>,
+    #[arg(
+        required_unless_present_any = [
+            "regexp",
+            "pattern_files",
+            "list_files",
+            "type_list",
+            "generate_completions",
+            "generate_man",
+            "interactive",
+        ],
+        help = "One or more patterns separated by newline characters. With -e or -f, it is the first file"
+    )]
+    pattern: Option<String>,
+    #[arg(
+        help = "Files to search, - stands for standard input. No files means the working directory with -r, standard input otherwise"
+    )]
+    files: Vec<FileOrStdin<String>>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Search the files listed in FILE, one per line, after the FILES. - reads the list from standard input"
+    )]
+    files_from: Vec<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Same as --files-from, with the names separated by NUL bytes, as printed by find -print0"
+    )]
+    files_from0: Vec<String>,
+    #[arg(
+        long,
+        value_name = "WHEN",
+        default_value = "auto",
+        default_missing_value = "auto",
+        num_args = 0..=1,
+        require_equals = true,
+        help = "Highlight the matches, the file names and line numbers. The colors are taken from GREP_COLORS. auto does not highlight when NO_COLOR is set"
+    )]
+    color: ColorChoice,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "none",
+        help = "Make the file names hyperlinks when the output is colored: none, default, file, kitty, vscode, vscode-insiders, cursor, macvim, textmate, or a template where {path} is the absolute path of the file, {host} the host name, {line} and {column} the position"
+    )]
+    hyperlink_format: String,
+    #[arg(
+        short('z'),
+        long,
+        help = "Lines are terminated by a NUL byte instead of a newline"
+    )]
+    null_data: bool,
+    #[arg(
+        short('U'),
+        long,
+        help = "Let the matches span several lines, as with \\n in the pattern. ^ and $ match at each line, . never matches a newline"
+    )]
+    multiline: bool,
+    #[arg(
+        long,
+        overrides_with = "no_crlf",
+        help = "Treat \\r\\n as a line terminator: $ matches before the \\r, which is not printed. The default on Windows"
+    )]
+    crlf: bool,
+    #[arg(
+        long,
+        overrides_with = "crlf",
+        help = "Treat only \\n as a line terminator"
+    )]
+    no_crlf: bool,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Name of standard input in the output, instead of (standard input)"
+    )]
+    label: Option<String>,
+    #[arg(
+        long,
+        value_name = "TYPE",
+        default_value = "binary",
+        help = "How to search the files with a NUL byte"
+    )]
+    binary_files: BinaryFiles,
+    #[arg(
+        short('a'),
+        long,
+        help = "Search the binary files as text, same as --binary-files=text"
+    )]
+    text: bool,
+    #[arg(
+        short('r'),
+        long,
+        help = "Search the files of the directories, recursively"
+    )]
+    recursive: bool,
+    #[arg(
+        short('d'),
+        long,
+        value_enum,
+        value_name = "ACTION",
+        default_value = "read",
+        help = "What to do with the directories given on the command line: read them as files, which fails, skip them, or recurse into them as -r"
+    )]
+    directories: Directories,
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Search only the files whose base name matches GLOB"
+    )]
+    include: Vec<String>,
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Skip the files whose base name matches GLOB. With --include, the last matching glob wins"
+    )]
+    exclude: Vec<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Search only the files whose base name matches a glob of FILE, one per line. The lines starting with # are comments"
+    )]
+    include_from: Vec<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Skip the files whose base name matches a glob of FILE, one per line. The lines starting with # are comments"
+    )]
+    exclude_from: Vec<String>,
+    #[arg(
+        long,
+        value_name = "GLOB",
+        help = "Do not walk the directories whose base name matches GLOB"
+    )]
+    exclude_dir: Vec<String>,
+    #[arg(
+        long,
+        help = "Search the files ignored by the .gitignore and .ignore files, by the global git excludes and by --ignore-file"
+    )]
+    no_ignore: bool,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Skip the files ignored by the rules of PATH, with the syntax of .gitignore, relative to the searched directories. Can be given several times"
+    )]
+    ignore_file: Vec<String>,
+    #[arg(
+        short('t'),
+        long = "type",
+        value_name = "TYPE",
+        help = "Search only the files of TYPE, such as rust or py, can be given several times. See --type-list"
+    )]
+    file_type: Vec<String>,
+    #[arg(
+        short('T'),
+        long,
+        value_name = "TYPE",
+        help = "Skip the files of TYPE, can be given several times"
+    )]
+    type_not: Vec<String>,
+    #[arg(
+        long,
+        value_name = "NAME:GLOB",
+        help = "Add GLOB to the file names of the type NAME, which is created if needed"
+    )]
+    type_add: Vec<String>,
+    #[arg(long, help = "Print the file types with their globs, and exit")]
+    type_list: bool,
+    #[arg(
+        long,
+        help = "Follow the symbolic links found in the directories, skipping the ones that loop"
+    )]
+    follow: bool,
+    #[arg(
+        long,
+        value_name = "NUM",
+        help = "Descend at most NUM levels below the directories, 1 to search only their files"
+    )]
+    max_depth: Option<usize>,
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        help = "Skip the files larger than SIZE during -r, such as 500K or 10M"
+    )]
+    max_filesize: Option<u64>,
+    #[arg(
+        long,
+        help = "Search the hidden files and directories during -r. The files given on the command line are always searched"
+    )]
+    hidden: bool,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "KEY",
+        default_value = "path",
+        help = "Order of the files searched during -r"
+    )]
+    sort: SortBy,
+    #[arg(
+        long,
+        help = "Search the content of the .gz, .bz2, .xz and .zst files, decompressed by gzip, bzip2, xz or zstd"
+    )]
+    search_zip: bool,
+    #[arg(
+        long,
+        value_name = "COMMAND",
+        help = "Search the output of COMMAND run on each file, given its path as argument and its content as input, such as pdftotext"
+    )]
+    pre: Option<String>,
+    #[arg(
+        long,
+        value_name = "GLOB",
+        requires = "pre",
+        help = "Run the --pre command only on the files whose base name matches GLOB, can be given several times"
+    )]
+    pre_glob: Vec<String>,
+    #[arg(
+        long,
+        value_enum,
+        help = "Transcode the input to UTF-8 before searching it. auto detects UTF-16 and UTF-8 with their byte order mark"
+    )]
+    encoding: Option<Encoding>,
+    #[arg(
+        long,
+        overrides_with = "no_mmap",
+        help = "Read the regular files through a memory map. By default, only the large files are mapped"
+    )]
+    mmap: bool,
+    #[arg(long, overrides_with = "mmap", help = "Never use memory maps")]
+    no_mmap: bool,
+    #[arg(
+        long,
+        help = "Print the results as JSON lines, one object per event, in the format of ripgrep"
+    )]
+    json: bool,
+    #[arg(
+        long,
+        overrides_with = "block_buffered",
+        help = "Flush the output after each line, at the cost of throughput. The default on a terminal"
+    )]
+    line_buffered: bool,
+    #[arg(
+        long,
+        overrides_with = "line_buffered",
+        help = "Flush the output when the buffer is full, even on a terminal"
+    )]
+    block_buffered: bool,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write the results to PATH instead of standard output. PATH is replaced once the search is over, and left as is if it fails"
+    )]
+    output: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Print all the lines, highlighting the matches, as a colorizing filter"
+    )]
+    passthru: bool,
+    #[arg(
+        long = "files",
+        help = "Print the files that would be searched, after the filters, without searching them. Implies -r and takes no pattern"
+    )]
+    list_files: bool,
+    #[arg(
+        short('j'),
+        long,
+        value_name = "NUM",
+        help = "Search NUM files at a time, the number of CPUs by default. Standard input is always searched alone"
+    )]
+    threads: Option<usize>,
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SHELL",
+        hide = true,
+        help = "Print the completion script of the shell, and exit"
+    )]
+    generate_completions: Option<Shell>,
+    #[arg(long, hide = true, help = "Print the man page, in roff, and exit")]
+    generate_man: bool,
+    #[arg(
+        long,
+        help = "Ignore the configuration file, given by GREP_CONFIG_PATH or else ~/.config/mygrep/config, which holds default arguments, one per line"
+    )]
+    no_config: bool,
+    #[arg(
+        long,
+        help = "Read the files once, then search them for each pattern typed on standard input, as a regex playground"
+    )]
+    interactive: bool,
+    #[arg(
+        long,
+        help = "Print the tokens of each pattern, one per line, and exit without searching"
+    )]
+    dump_tokens: bool,
+    #[arg(
+        long,
+        help = "Print the syntax tree of the pattern, as an indented tree, and exit without searching"
+    )]
+    dump_ast: bool,
+    #[arg(
+        long,
+        help = "Print a summary of the search: files, matched lines, bytes, elapsed time and counters of the engines"
+    )]
+    stats: bool,
+    #[arg(
+        long,
+        help = "Show the number of files searched and of matched lines, and the last file searched, on a line of standard error when it is a terminal"
     )]
-    file: FileOrStdin<String>,
+    progress: bool,
+    #[arg(
+        long,
+        help = "Keep running after the search, and search the files again as they change. As with tail -f, only the lines appended to a file are searched"
+    )]
+    watch: bool,
+    #[arg(
+        long,
+        help = "Print on standard error the time taken to compile the pattern, then to read and search each file, with its throughput"
+    )]
+    timing: bool,
+}
+
+/// is_terminal tells whether the output is a terminal, for auto
+fn use_colors(choice: ColorChoice, is_terminal: bool) -> bool {
+    match choice {
+        ColorChoice::Always => {
+            console::enable_ansi();
+            true
+        }
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            // https://no-color.org
+            let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+            // the Windows consoles do not set TERM
+            let is_dumb = std::env::var("TERM").map_or(!cfg!(windows), |term| term == "dumb");
+            // a console unable to show the colors would print the escapes
+            is_terminal && !no_color && !is_dumb && console::enable_ansi()
+        }
+    }
+}
+
+/// How the content of the inputs is read
+struct ReadOptions {
+    /// the compressed files are read through their decompressor
+    search_zip: bool,
+    /// the input is transcoded from this encoding
+    encoding: Option<Encoding>,
+    /// whether the regular files are memory mapped, None to decide from their
+    /// size
+    mmap: Option<bool>,
+    /// command whose output is searched instead of the files, given their path
+    pre: Option<String>,
+    /// with pre, the globs of the base names of the files it applies to, all
+    /// the files if there is none
+    pre_globs: Vec<Glob>,
+}
+
+impl ReadOptions {
+    /// Command run on the file by --pre, if it applies to it
+    fn preprocessor(&self, path: &Path) -> Option<&str> {
+        let pre = self.pre.as_deref()?;
+        let name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy();
+        let is_selected =
+            self.pre_globs.is_empty() || self.pre_globs.iter().any(|glob| glob.is_match(&name));
+        is_selected.then_some(pre)
+    }
+}
+
+/// A file to search
+enum Input {
+    /// with the name in the output
+    Stdin(String),
+    /// path to open, with the name in the output
+    File(PathBuf, String),
+}
+
+impl Input {
+    fn name(&self) -> &str {
+        match self {
+            Input::Stdin(name) | Input::File(_, name) => name,
+        }
+    }
+
+    /// The input is read line by line, so stdin is searched while it is read
+    fn open(&self, options: &ReadOptions) -> io::Result<Box<dyn BufRead>> {
+        let reader: Box<dyn Read> = match self {
+            Input::Stdin(_) => Box::new(io::stdin()),
+            Input::File(path, _) => {
+                let file = File::open(path)?;
+                // the file is read as is when it can not be mapped
+                let map = || {
+                    should_map(&file, options.mmap)
+                        .then(|| Mmap::map(&file).ok())
+                        .flatten()
+                };
+                if let Some(pre) = options.preprocessor(path) {
+                    // as in ripgrep, the command gets the path and the content
+                    Box::new(CommandReader::spawn(pre, [path], file.into())?)
+                } else if let Some(program) = decompressor(path).filter(|_| options.search_zip) {
+                    Box::new(CommandReader::spawn(program, ["-dc"], file.into())?)
+                } else if let Some(map) = map() {
+                    Box::new(io::Cursor::new(map))
+                } else {
+                    Box::new(file)
+                }
+            }
+        };
+        Ok(match options.encoding {
+            Some(encoding) => Box::new(BufReader::new(Decoder::new(reader, encoding))),
+            None => Box::new(BufReader::new(reader)),
+        })
+    }
+}
+
+/// Files to search for one of the files given on the command line, the files
+/// of the tree for a directory with -r. When the directory is implicit, the
+/// names are relative to it
+fn expand_input<'a>(
+    file: &FileOrStdin<String>,
+    stdin_label: &str,
+    directories: Directories,
+    is_implicit: bool,
+    walk_options: &'a WalkOptions,
+) -> Box<dyn Iterator<Item = anyhow::Result<Input>> + 'a> {
+    if file.is_stdin() {
+        let input = Input::Stdin(stdin_label.to_string());
+        return Box::new(std::iter::once(Ok(input)));
+    }
+    let path = PathBuf::from(file.filename());
+    if directories == Directories::Skip && path.is_dir() {
+        return Box::new(std::iter::empty());
+    }
+    if directories == Directories::Recurse && path.is_dir() {
+        if !walk_options.filter.is_dir_included(&path) {
+            return Box::new(std::iter::empty());
+        }
+        let root = path.clone();
+        let walk: Box<dyn Iterator<Item = _>> = match walk_options.sort {
+            SortBy::Path => Box::new(Walk::new(&path, walk_options)),
+            _ => Box::new(Walk::new(&path, walk_options).sorted().into_iter()),
+        };
+        Box::new(walk.map(move |entry| {
+            let path = entry?;
+            let name = if is_implicit {
+                relative_name(&path, &root)
+            } else {
+                path.as_os_str()
+            };
+            let name = name.to_string_lossy().into_owned();
+            Ok(Input::File(path, name))
+        }))
+    } else if walk_options.filter.is_included(&path) {
+        let name = file.filename().to_string();
+        Box::new(std::iter::once(Ok(Input::File(path, name))))
+    } else {
+        Box::new(std::iter::empty())
+    }
 }
 
-fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
+/// Interval between two checks of the files with --watch
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Searches the files again as they change (--watch), until the output is
+/// closed. The lines appended to a file are searched alone, and the files
+/// created in the directories are searched whole
+fn watch<I>(
+    searcher: &mut Searcher,
+    inputs: impl Fn() -> I,
+    read_options: &ReadOptions,
+    out: &mut impl Write,
+    no_messages: bool,
+) -> anyhow::Result<()>
+where
+    I: Iterator<Item = anyhow::Result<Input>>,
+{
+    let mut watch = Watch::default();
+    for input in inputs() {
+        if let Ok(Input::File(path, _)) = input {
+            // a file that can not be read yet is searched once it can
+            let _ = watch.add(&path);
+        }
+    }
+    loop {
+        out.flush()?;
+        thread::sleep(WATCH_INTERVAL);
+        for input in inputs().flatten() {
+            let Input::File(path, name) = &input else {
+                continue;
+            };
+            let result = match watch.poll(path) {
+                Ok(Some(Change::Rewritten)) => search_input(searcher, &input, read_options, out),
+                Ok(Some(Change::Appended {
+                    content,
+                    nb_lines,
+                    offset,
+                })) => searcher
+                    .search_from(name, &content[..], out, nb_lines, offset)
+                    .map_err(|err| with_name(name, err)),
+                // a deleted file is searched again if it is created again
+                Ok(None) | Err(_) => continue,
+            };
+            if let Err(err) = result {
+                if is_broken_pipe(&err) {
+                    return Err(err);
+                }
+                if !no_messages {
+                    out.flush()?;
+                    eprintln!("grep: {err}");
+                }
+            }
+        }
+    }
+}
 
-    let content = cli.file.contents()?;
+/// Input searched, given to the callback of the search loops
+struct Searched {
+    /// None if the input could not be listed
+    name: Option<String>,
+    /// number of selected lines
+    result: anyhow::Result<usize>,
+    /// time spent opening, reading and searching the input
+    elapsed: Duration,
+    bytes_searched: usize,
+}
 
-    // By default, clap exits with status code 2 when we don't pass the required
-    // arguments. To exit with status code 1, we need to handle it manually.
-    if !cli.extended_regexp {
-        println!("Expected first argument to be '-E'");
-        std::process::exit(1);
+impl Searched {
+    fn new(
+        searcher: &mut Searcher,
+        input: anyhow::Result<Input>,
+        read_options: &ReadOptions,
+        out: &mut impl Write,
+    ) -> Self {
+        let start = Instant::now();
+        let bytes_before = searcher.stats().bytes_searched;
+        let name = input.as_ref().ok().map(|input| input.name().to_string());
+        // an unreadable file does not prevent searching the others
+        let result = input.and_then(|input| search_input(searcher, &input, read_options, out));
+        Self {
+            name,
+            result,
+            elapsed: start.elapsed(),
+            bytes_searched: searcher.stats().bytes_searched - bytes_before,
+        }
     }
+}
 
-    let pat = cli.pattern;
-    let chars = content.chars().collect::<Vec<_>>();
+/// Searches one of the inputs. Returns the number of selected lines
+fn search_input(
+    searcher: &mut Searcher,
+    input: &Input,
+    read_options: &ReadOptions,
+    out: &mut impl Write,
+) -> anyhow::Result<usize> {
+    let name = input.name();
+    let reader = input
+        .open(read_options)
+        .map_err(|err| with_name(name, err.into()))?;
+    searcher
+        .search(name, reader, out)
+        .map_err(|err| with_name(name, err))
+}
 
-    let lexer = RegexLexer::new(&pat);
-    let mut parser = RegexParser::new(lexer)?;
+/// Error of the input, prefixed by its name. A closed output is left as is,
+/// see is_broken_pipe
+fn with_name(name: &str, err: anyhow::Error) -> anyhow::Error {
+    if is_broken_pipe(&err) {
+        err
+    } else {
+        anyhow::anyhow!("{name}: {err}")
+    }
+}
 
-    let node = parser.build_ast(0)?;
-    let mut matcher = Matcher::new(chars.len());
-    let is_match = matcher.matches(&node, &chars);
+/// Whether the output was closed by its reader, as with grep ... | head. As
+/// GNU grep, the search then stops without a message
+fn is_broken_pipe(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe)
+    })
+}
 
-    if is_match {
+/// Prints the name of each input instead of searching it (--files). As with a
+/// search, the exit status is 0 when a file is printed
+fn list_files(
+    inputs: impl Iterator<Item = anyhow::Result<Input>>,
+    null: bool,
+    no_messages: bool,
+) -> anyhow::Result<ExitCode> {
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut is_listed = false;
+    let mut has_error = false;
+    for input in inputs {
+        match input {
+            Ok(input) => {
+                is_listed = true;
+                out.write_all(input.name().as_bytes())?;
+                out.write_all(if null { b"\0" } else { b"\n" })?;
+            }
+            Err(err) if is_broken_pipe(&err) => return Err(err),
+            Err(err) => {
+                has_error = true;
+                if !no_messages {
+                    out.flush()?;
+                    eprintln!("grep: {err}");
+                }
+            }
+        }
+    }
+    out.flush()?;
+    Ok(if has_error {
+        ExitCode::from(2)
+    } else if is_listed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// Searches the inputs on several threads. Each input is searched into a
+/// buffer, and the buffers are written in the order of the inputs, so that the
+/// output of each file stays contiguous. on_result is called with each input
+/// searched in order, and stops the search by returning false
+fn search_parallel<I>(
+    searcher: &mut Searcher,
+    inputs: impl FnOnce() -> I + Send,
+    read_options: &ReadOptions,
+    threads: usize,
+    out: &mut impl Write,
+    mut on_result: impl FnMut(Searched, &mut dyn Write) -> anyhow::Result<bool>,
+) -> anyhow::Result<()>
+where
+    I: Iterator<Item = anyhow::Result<Input>>,
+{
+    let (job_sender, job_receiver) = mpsc::sync_channel(threads);
+    // dropped with the last worker, which stops the walk
+    let job_receiver = Arc::new(Mutex::new(job_receiver));
+    let (result_sender, result_receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            for job in inputs().enumerate() {
+                if job_sender.send(job).is_err() {
+                    break;
+                }
+            }
+        });
+        let workers = (0..threads)
+            .map(|_| {
+                let mut worker = searcher.clone();
+                let job_receiver = Arc::clone(&job_receiver);
+                let result_sender = result_sender.clone();
+                scope.spawn(move || {
+                    loop {
+                        let job = job_receiver
+                            .lock()
+                            .map_err(|_| ())
+                            .and_then(|jobs| jobs.recv().map_err(|_| ()));
+                        let Ok((index, input)) = job else {
+                            break;
+                        };
+                        // the empty lines between the headings are written below
+                        worker.reset_headings();
+                        let mut buffer = Vec::new();
+                        let searched = Searched::new(&mut worker, input, read_options, &mut buffer);
+                        let heading = worker.printed_heading();
+                        if result_sender
+                            .send((index, buffer, heading, searched))
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                    worker.stats()
+                })
+            })
+            .collect::<Vec<_>>();
+        drop((job_receiver, result_sender));
+        // the workers stop when the results are no longer received
+        let result_receiver = result_receiver;
+
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        let mut any_heading = false;
+        'results: for (index, buffer, heading, searched) in result_receiver.iter() {
+            pending.insert(index, (buffer, heading, searched));
+            while let Some((buffer, heading, searched)) = pending.remove(&next) {
+                next += 1;
+                if heading && any_heading {
+                    out.write_all(searcher.heading_separator())?;
+                }
+                any_heading |= heading;
+                out.write_all(&buffer)?;
+                if !on_result(searched, out)? {
+                    break 'results;
+                }
+            }
+        }
+        drop(result_receiver);
+        for worker in workers {
+            let stats = worker
+                .join()
+                .map_err(|_| anyhow::anyhow!("a search thread panicked"))?;
+            searcher.merge_stats(&stats);
+        }
         Ok(())
+    })
+}
+
+/// --include and --exclude globs, including the ones of --include-from and
+/// --exclude-from files, in the order of the command line, --exclude-dir globs
+/// and the -t and -T types
+fn build_filter(
+    cli: &Cli,
+    matches: &clap::ArgMatches,
+    types: &FileTypes,
+) -> anyhow::Result<FileFilter> {
+    let mut rules = Vec::new();
+    for (id, globs, include) in [
+        ("include", &cli.include, true),
+        ("exclude", &cli.exclude, false),
+    ] {
+        let indices = matches.indices_of(id).into_iter().flatten();
+        rules.extend(
+            indices
+                .zip(globs)
+                .map(|(index, glob)| (index, glob.clone(), include)),
+        );
+    }
+    for (id, paths, include) in [
+        ("include_from", &cli.include_from, true),
+        ("exclude_from", &cli.exclude_from, false),
+    ] {
+        let indices = matches.indices_of(id).into_iter().flatten();
+        for (index, path) in indices.zip(paths) {
+            let globs = read_glob_file(path)?;
+            rules.extend(globs.into_iter().map(|glob| (index, glob, include)));
+        }
+    }
+    // the sort is stable, so the globs of a file keep their order
+    rules.sort_by_key(|(index, _, _)| *index);
+
+    let mut filter = FileFilter::default();
+    for (_, glob, include) in rules {
+        filter.add(Glob::new(&glob), include);
+    }
+    for glob in &cli.exclude_dir {
+        filter.exclude_dir(Glob::new(glob));
+    }
+    for (names, include) in [(&cli.file_type, true), (&cli.type_not, false)] {
+        for name in names {
+            filter.add_type(types.globs(name)?, include);
+        }
+    }
+    Ok(filter)
+}
+
+/// Patterns of a -f file, one per line. The empty lines at the end are ignored
+fn read_pattern_file(path: &str) -> anyhow::Result<Vec<String>> {
+    let content = if path == "-" {
+        io::read_to_string(io::stdin())?
+    } else {
+        std::fs::read_to_string(path).map_err(|err| anyhow::anyhow!("{path}: {err}"))?
+    };
+    let mut patterns = content.split('\n').map(String::from).collect::<Vec<_>>();
+    while patterns.last().is_some_and(|pattern| pattern.is_empty()) {
+        patterns.pop();
+    }
+    Ok(patterns)
+}
+
+/// Globs of an --include-from or --exclude-from file, one per line. The empty
+/// lines and the lines starting with # are ignored
+fn read_glob_file(path: &str) -> anyhow::Result<Vec<String>> {
+    let content = std::fs::read_to_string(path).map_err(|err| anyhow::anyhow!("{path}: {err}"))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Names of a --files-from file, or of standard input for -, separated by the
+/// byte. The empty names are ignored
+fn read_file_list(path: &str, separator: u8) -> anyhow::Result<Vec<String>> {
+    let content = if path == "-" {
+        let mut content = Vec::new();
+        io::stdin().lock().read_to_end(&mut content)?;
+        content
+    } else {
+        std::fs::read(path).map_err(|err| anyhow::anyhow!("{path}: {err}"))?
+    };
+    Ok(content
+        .split(|&byte| byte == separator)
+        .map(|name| name.strip_suffix(b"\r").unwrap_or(name))
+        .filter(|name| !name.is_empty())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect())
+}
+
+/// Rules of the --ignore-file files, put together
+fn read_ignore_files(paths: &[String]) -> anyhow::Result<String> {
+    let mut rules = String::new();
+    for path in paths {
+        let content =
+            std::fs::read_to_string(path).map_err(|err| anyhow::anyhow!("{path}: {err}"))?;
+        rules.push_str(&content);
+        rules.push('\n');
+    }
+    Ok(rules)
+}
+
+/// Reads the inputs once, then searches them for each pattern read from
+/// standard input (--interactive), until the end of the input
+fn interactive(
+    cli: &Cli,
+    inputs: impl Iterator<Item = anyhow::Result<Input>>,
+    read_options: &ReadOptions,
+    options: SearchOptions,
+) -> anyhow::Result<ExitCode> {
+    let mut haystacks = Vec::new();
+    for input in inputs {
+        let input = input?;
+        if let Input::Stdin(_) = input {
+            anyhow::bail!(
+                "--interactive reads the patterns from standard input, give files to search"
+            );
+        }
+        let mut content = Vec::new();
+        input
+            .open(read_options)
+            .and_then(|mut reader| reader.read_to_end(&mut content))
+            .map_err(|err| anyhow::anyhow!("{}: {err}", input.name()))?;
+        haystacks.push((input.name().to_string(), content));
+    }
+
+    let mut out = io::stdout().lock();
+    let mut line = String::new();
+    loop {
+        write!(out, "pattern> ")?;
+        out.flush()?;
+        line.clear();
+        if io::stdin().read_line(&mut line)? == 0 {
+            writeln!(out)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        let pattern = line.trim_end_matches(['\n', '\r']);
+        if pattern.is_empty() {
+            continue;
+        }
+        let node = match build_pattern(&[pattern], cli) {
+            Ok(node) => node,
+            Err(err) => {
+                writeln!(out, "error: {err}")?;
+                continue;
+            }
+        };
+        let mut searcher = match Searcher::new(&node, options.clone()) {
+            Ok(searcher) => searcher,
+            Err(err) => {
+                writeln!(out, "error: {err}")?;
+                continue;
+            }
+        };
+        let mut nb_selected = 0;
+        for (name, content) in &haystacks {
+            nb_selected += searcher.search(name, &content[..], &mut out)?;
+        }
+        writeln!(out, "{nb_selected} selected lines")?;
+    }
+}
+
+/// Whether the value of --format selects the CI annotations rather than
+/// being a template
+fn is_annotation_format(format: &str) -> bool {
+    format == "github" || format == "sarif"
+}
+
+/// Syntax of the patterns given on the command line, when they are not fixed
+/// strings
+fn dialect(cli: &Cli) -> Dialect {
+    if cli.perl_regexp {
+        Dialect::Perl
+    } else if cli.extended_regexp {
+        Dialect::Extended
+    } else {
+        Dialect::Basic
+    }
+}
+
+/// Writes the tokens of each pattern, one per line, with an empty line
+/// between the patterns (--dump-tokens). The fixed strings are only literals
+fn dump_tokens(patterns: &[&str], cli: &Cli, out: &mut impl Write) -> anyhow::Result<()> {
+    for (i, pattern) in patterns.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        if cli.fixed_strings {
+            for c in pattern.chars() {
+                writeln!(out, "{}", RegexToken::Literal(c))?;
+            }
+            continue;
+        }
+        let mut lexer = RegexLexer::with_dialect(pattern, dialect(cli));
+        loop {
+            let token = lexer.next_token()?;
+            if token == RegexToken::Eof {
+                break;
+            }
+            writeln!(out, "{token}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Pattern matching any of the patterns given on the command line
+fn build_pattern(patterns: &[&str], cli: &Cli) -> anyhow::Result<Node> {
+    let node = if cli.fixed_strings {
+        let has_uppercase = patterns
+            .iter()
+            .flat_map(|pattern| pattern.chars())
+            .any(char::is_uppercase);
+        Node::fixed_strings(
+            patterns,
+            cli.ignore_case || (cli.smart_case && !has_uppercase),
+        )
+    } else {
+        let nodes = patterns
+            .iter()
+            .map(|pattern| {
+                RegexBuilder::new(pattern)
+                    .dialect(dialect(cli))
+                    .case_insensitive(cli.ignore_case)
+                    .smart_case(cli.smart_case)
+                    .build_ast()
+                    .map_err(|err| anyhow::anyhow!(err.diagnostic(pattern)))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Node::alternatives(nodes)
+    };
+    // as in GNU grep, -x takes precedence over -w
+    Ok(if cli.line_regexp {
+        node.line_bounded()
+    } else if cli.word_regexp {
+        node.word_bounded()
+    } else {
+        node
+    })
+}
+
+/// Searches the files given on the command line. As in GNU grep, the exit
+/// status is 0 when a line is selected and 1 otherwise. The errors exit with 2
+fn main() -> ExitCode {
+    match run() {
+        Ok(exit_code) => exit_code,
+        Err(err) if is_broken_pipe(&err) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("grep: {err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run() -> anyhow::Result<ExitCode> {
+    // the arguments of the command line come last, to override the ones of the
+    // configuration file
+    let mut args = std::env::args_os().collect::<Vec<_>>();
+    if !args.iter().any(|arg| arg == "--no-config") {
+        // after the name of the program
+        let at = args.len().min(1);
+        args.splice(at..at, config::args()?);
+    }
+    // the matches give the positions of --include and --exclude
+    let matches = Cli::command().get_matches_from(args);
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+
+    let mut file_types = FileTypes::default();
+    for definition in &cli.type_add {
+        file_types.add(definition)?;
+    }
+    if cli.type_list {
+        print!("{file_types}");
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(shell) = cli.generate_completions {
+        completions::generate(&mut Cli::command(), shell, &mut io::stdout().lock())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if cli.generate_man {
+        man::generate(&mut Cli::command(), &mut io::stdout().lock())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    // as in ripgrep, --files lists the files of the working directory
+    if cli.directories == Directories::Recurse || cli.list_files {
+        cli.recursive = true;
+    }
+    // with -e or -f, there is no pattern argument
+    let has_pattern_options = !cli.regexp.is_empty() || !cli.pattern_files.is_empty();
+    let mut pattern_args = std::mem::take(&mut cli.regexp);
+    for path in &cli.pattern_files {
+        pattern_args.extend(read_pattern_file(path)?);
+    }
+    match cli.pattern.take() {
+        // with --files, there is no pattern at all
+        Some(pattern) if has_pattern_options || cli.list_files || cli.interactive => {
+            cli.files.insert(0, pattern.parse()?)
+        }
+        Some(pattern) => pattern_args.push(pattern),
+        None => {}
+    }
+    // an empty list searches nothing
+    let has_file_lists = !cli.files_from.is_empty() || !cli.files_from0.is_empty();
+    for (paths, separator) in [(&cli.files_from, b'\n'), (&cli.files_from0, b'\0')] {
+        for path in paths {
+            for name in read_file_list(path, separator)? {
+                cli.files.push(name.parse()?);
+            }
+        }
+    }
+    // as in GNU grep, the names of the files of the working directory are
+    // printed without the ./ prefix
+    let is_implicit_dir = cli.files.is_empty() && cli.recursive && !has_file_lists;
+    if cli.files.is_empty() && !has_file_lists {
+        cli.files
+            .push(if cli.recursive { "." } else { "-" }.parse()?);
+    }
+    let walk_options = WalkOptions {
+        filter: build_filter(&cli, &matches, &file_types)?,
+        use_ignore_files: !cli.no_ignore,
+        ignore_rules: read_ignore_files(&cli.ignore_file)?,
+        follow_links: cli.follow,
+        max_depth: cli.max_depth,
+        hidden: cli.hidden,
+        max_filesize: cli.max_filesize,
+        sort: cli.sort,
+    };
+    // as GNU grep does by default
+    let stdin_label = cli.label.as_deref().unwrap_or("(standard input)");
+    let inputs = || {
+        cli.files.iter().flat_map(|file| {
+            expand_input(
+                file,
+                stdin_label,
+                if cli.recursive {
+                    Directories::Recurse
+                } else {
+                    cli.directories
+                },
+                is_implicit_dir,
+                &walk_options,
+            )
+        })
+    };
+    if cli.list_files {
+        return list_files(inputs(), cli.null, cli.no_messages);
+    }
+
+    let patterns = pattern_args
+        .iter()
+        .flat_map(|patterns| patterns.split('\n'))
+        .collect::<Vec<_>>();
+
+    // with the creation of the searcher, for --timing
+    let compile_start = Instant::now();
+    let node = build_pattern(&patterns, &cli)?;
+    let mut compile_time = compile_start.elapsed();
+
+    if cli.dump_tokens || cli.dump_ast {
+        let mut out = io::stdout().lock();
+        if cli.dump_tokens {
+            dump_tokens(&patterns, &cli, &mut out)?;
+        }
+        if cli.dump_ast {
+            if cli.dump_tokens {
+                writeln!(out)?;
+            }
+            write!(out, "{}", node.tree())?;
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // a file written with --output is not read on a terminal
+    let is_terminal = cli.output.is_none() && io::stdout().is_terminal();
+    let colors = use_colors(cli.color, is_terminal).then(|| {
+        std::env::var("GREP_COLORS").map_or_else(|_| Colors::default(), |spec| Colors::parse(&spec))
+    });
+    let options = SearchOptions {
+        invert_match: cli.invert_match,
+        output_mode: if cli.quiet {
+            OutputMode::Quiet
+        } else if cli.json {
+            OutputMode::Json
+        } else if cli.format.as_deref() == Some("github") {
+            OutputMode::Github
+        } else if cli.format.as_deref() == Some("sarif") {
+            OutputMode::Sarif
+        } else if cli.files_without_match {
+            OutputMode::FilesWithoutMatch
+        } else if cli.files_with_matches {
+            OutputMode::FilesWithMatches
+        } else if cli.count_per_pattern {
+            OutputMode::CountPerPattern
+        } else if cli.count_matches {
+            OutputMode::CountMatches
+        } else if cli.count && !cli.unique {
+            OutputMode::Count
+        } else {
+            OutputMode::Lines
+        },
+        max_count: cli.max_count,
+        // -A and -B take precedence over -C
+        before_context: cli.before_context.or(cli.context).unwrap_or(0),
+        after_context: cli.after_context.or(cli.context).unwrap_or(0),
+        group_separator: cli.group_separator.clone(),
+        no_group_separator: cli.no_group_separator,
+        only_matching: cli.only_matching,
+        unique: cli.unique,
+        unique_count: cli.unique && cli.count,
+        // as in ripgrep, a column is printed after the line number
+        line_number: cli.line_number || cli.column || cli.vimgrep || cli.interactive,
+        byte_offset: cli.byte_offset,
+        column: cli.column || cli.vimgrep,
+        vimgrep: cli.vimgrep,
+        // as in ripgrep, the lines are grouped by file for a human reader, and
+        // --vimgrep needs a file name on each line
+        heading: !cli.vimgrep && (cli.heading || (!cli.no_heading && is_terminal)),
+        with_filename: !cli.no_filename
+            && (cli.with_filename || cli.vimgrep || cli.recursive || cli.files.len() > 1),
+        // as in ripgrep, the terminals showing the colors can show the links
+        hyperlink: HyperlinkFormat::new(&cli.hyperlink_format)?.filter(|_| colors.is_some()),
+        colors,
+        null: cli.null,
+        initial_tab: cli.initial_tab,
+        max_columns: cli.max_columns,
+        max_columns_preview: cli.max_columns_preview,
+        null_data: cli.null_data,
+        crlf: cli.crlf || (!cli.no_crlf && cfg!(windows)),
+        binary_files: if cli.text {
+            BinaryFiles::Text
+        } else {
+            cli.binary_files
+        },
+        // a human reads the lines as they are found, a pipe reads blocks
+        line_buffered: cli.line_buffered || (is_terminal && !cli.block_buffered),
+        passthru: cli.passthru,
+        replace: cli.replace.clone(),
+        format: cli
+            .format
+            .clone()
+            .filter(|format| !is_annotation_format(format)),
+        multiline: cli.multiline,
+        // as in GNU grep, the basic and extended expressions report the
+        // longest match, as POSIX requires
+        match_kind: if dialect(&cli) == Dialect::Perl {
+            MatchKind::LeftmostFirst
+        } else {
+            MatchKind::LeftmostLongest
+        },
+    };
+    let read_options = ReadOptions {
+        search_zip: cli.search_zip,
+        encoding: cli.encoding,
+        mmap: (cli.mmap || cli.no_mmap).then_some(cli.mmap),
+        pre: cli.pre.clone(),
+        pre_globs: cli.pre_glob.iter().map(|glob| Glob::new(glob)).collect(),
+    };
+    if cli.interactive {
+        return interactive(&cli, inputs(), &read_options, options);
+    }
+    let sarif = options.output_mode == OutputMode::Sarif;
+    let compile_start = Instant::now();
+    let mut searcher = Searcher::new(&node, options)?;
+    if cli.count_per_pattern {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| Ok((pattern.to_string(), build_pattern(&[pattern], &cli)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        searcher.set_patterns(patterns)?;
+    }
+    compile_time += compile_start.elapsed();
+    if cli.timing {
+        eprintln!(
+            "timing: pattern compilation: {:.6}s",
+            compile_time.as_secs_f64()
+        );
+    }
+
+    let mut progress = Progress::default();
+    let show_progress = cli.progress && io::stderr().is_terminal();
+    let mut out = BufWriter::new(progress.clear_before(Output::new(cli.output.as_deref())?));
+    if sarif {
+        out.write_all(annotation::SARIF_START.as_bytes())?;
+    }
+    let start = Instant::now();
+    let mut is_match = false;
+    let mut has_error = false;
+    // returns whether the search goes on
+    let mut on_result = |searched: Searched, out: &mut dyn Write| {
+        let name = searched.name.as_deref().unwrap_or_default();
+        match searched.result {
+            Ok(nb_selected) => {
+                is_match |= nb_selected > 0;
+                if cli.timing {
+                    out.flush()?;
+                    progress.clear();
+                    let secs = searched.elapsed.as_secs_f64();
+                    let throughput = searched.bytes_searched as f64 / secs.max(1e-9) / 1e6;
+                    eprintln!(
+                        "timing: {name}: {secs:.6}s, {} bytes, {throughput:.1} MB/s",
+                        searched.bytes_searched
+                    );
+                }
+                if show_progress {
+                    // the results of the file are printed before the line
+                    out.flush()?;
+                    progress.update(name, nb_selected);
+                }
+            }
+            Err(err) if is_broken_pipe(&err) => return Err(err),
+            Err(err) => {
+                has_error = true;
+                if !cli.no_messages {
+                    out.flush()?;
+                    progress.clear();
+                    eprintln!("grep: {err}");
+                }
+            }
+        }
+        // the exit status is known, the other files do not matter
+        anyhow::Ok(!(is_match && cli.quiet))
+    };
+    let threads = cli
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get));
+    // standard input is read as it comes, by a single thread
+    if threads <= 1 || cli.files.iter().any(|file| file.is_stdin()) {
+        for input in inputs() {
+            let searched = Searched::new(&mut searcher, input, &read_options, &mut out);
+            if !on_result(searched, &mut out)? {
+                break;
+            }
+        }
     } else {
-        anyhow::bail!("Error matching pattern")
+        search_parallel(
+            &mut searcher,
+            inputs,
+            &read_options,
+            threads,
+            &mut out,
+            on_result,
+        )?;
+    }
+    if cli.watch {
+        watch(
+            &mut searcher,
+            inputs,
+            &read_options,
+            &mut out,
+            cli.no_messages,
+        )?;
+    }
+    if sarif {
+        out.write_all(annotation::SARIF_END.as_bytes())?;
     }
+    let stats = searcher.stats();
+    let elapsed = start.elapsed();
+    if cli.json && !cli.quiet {
+        writeln!(
+            out,
+            r#"{{"type":"summary","data":{{"elapsed_total":{{"secs":{},"nanos":{},"human":"{:.6}s"}},"stats":{{"searches":{},"searches_with_match":{},"matched_lines":{},"bytes_searched":{}}}}}}}"#,
+            elapsed.as_secs(),
+            elapsed.subsec_nanos(),
+            elapsed.as_secs_f64(),
+            stats.files_searched,
+            stats.files_with_matches,
+            stats.matched_lines,
+            stats.bytes_searched
+        )?;
+    }
+    progress.clear();
+    out.into_inner()
+        .map_err(|err| err.into_error())?
+        .into_inner()
+        .finish()
+        .map_err(|err| match &cli.output {
+            Some(path) => anyhow::anyhow!("{}: {err}", path.display()),
+            None => err.into(),
+        })?;
+
+    if cli.stats {
+        println!("{stats}");
+        println!("{:.6}s elapsed", elapsed.as_secs_f64());
+    }
+
+    // as in GNU grep, the errors are reported by the exit status, even with -s,
+    // unless -q found a match
+    Ok(if has_error && !(cli.quiet && is_match) {
+        ExitCode::from(2)
+    } else if is_match {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
 }
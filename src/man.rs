@@ -0,0 +1,188 @@
+use std::io::{self, Write};
+
+use clap::{Arg, ArgAction, Command};
+
+/// Escapes the text for roff: backslashes, dashes, and the dots and quotes
+/// that would start a request at the beginning of a line
+fn escape(text: &str) -> String {
+    let escaped = text.replace('\\', r"\e").replace('-', r"\-");
+    match escaped.chars().next() {
+        Some('.' | '\'') => format!(r"\&{escaped}"),
+        _ => escaped,
+    }
+}
+
+fn bold(text: &str) -> String {
+    format!(r"\fB{}\fR", escape(text))
+}
+
+fn italic(text: &str) -> String {
+    format!(r"\fI{}\fR", escape(text))
+}
+
+/// Name of the value of the argument, as in the help
+fn value_name(arg: &Arg) -> String {
+    match arg.get_value_names() {
+        Some([name, ..]) => name.to_string(),
+        _ => arg.get_id().as_str().to_uppercase(),
+    }
+}
+
+/// Description of the argument, with its accepted values
+fn write_item(out: &mut impl Write, tag: &str, arg: &Arg) -> io::Result<()> {
+    writeln!(out, ".TP")?;
+    writeln!(out, "{tag}")?;
+    let help = arg.get_help().map(|help| help.to_string());
+    writeln!(out, "{}", escape(help.as_deref().unwrap_or_default()))?;
+    let values = arg
+        .get_possible_values()
+        .into_iter()
+        .filter(|value| !value.is_hide_set())
+        .collect::<Vec<_>>();
+    // the booleans accept true and false, which is not worth documenting
+    if !values.is_empty() && arg.get_action().takes_values() {
+        writeln!(out, ".RS")?;
+        for value in values {
+            writeln!(out, ".TP")?;
+            writeln!(out, "{}", bold(value.get_name()))?;
+            if let Some(help) = value.get_help() {
+                writeln!(out, "{}", escape(&help.to_string()))?;
+            }
+        }
+        writeln!(out, ".RE")?;
+    }
+    Ok(())
+}
+
+/// Writes the man page of the command, in roff: its synopsis, its description,
+/// and each of its options and arguments
+pub fn generate(command: &mut Command, out: &mut impl Write) -> io::Result<()> {
+    command.build();
+    let name = command.get_name().to_string();
+    let version = command.get_version().unwrap_or_default();
+    writeln!(
+        out,
+        r#".TH {} 1 "" "{} {}""#,
+        escape(&name.to_uppercase()),
+        escape(&name),
+        escape(version)
+    )?;
+
+    writeln!(out, ".SH NAME")?;
+    let about = command.get_about().map(|about| about.to_string());
+    writeln!(
+        out,
+        r"{} \- {}",
+        escape(&name),
+        escape(about.as_deref().unwrap_or_default())
+    )?;
+
+    let positionals = command
+        .get_positionals()
+        .filter(|arg| !arg.is_hide_set())
+        .collect::<Vec<_>>();
+    writeln!(out, ".SH SYNOPSIS")?;
+    let mut synopsis = format!(r"{} [{}]", bold(&name), italic("OPTIONS"));
+    for arg in &positionals {
+        synopsis.push_str(&format!(" [{}]", italic(&value_name(arg))));
+        let is_repeated = matches!(arg.get_action(), ArgAction::Append)
+            || arg
+                .get_num_args()
+                .is_some_and(|range| range.max_values() > 1);
+        if is_repeated {
+            synopsis.push_str("...");
+        }
+    }
+    writeln!(out, "{synopsis}")?;
+
+    let description = command
+        .get_long_about()
+        .or(command.get_about())
+        .map(|about| about.to_string());
+    if let Some(description) = description {
+        writeln!(out, ".SH DESCRIPTION")?;
+        writeln!(out, "{}", escape(&description))?;
+    }
+
+    writeln!(out, ".SH OPTIONS")?;
+    let options = command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set());
+    for arg in options {
+        let mut tag = [
+            arg.get_short().map(|short| bold(&format!("-{short}"))),
+            arg.get_long().map(|long| bold(&format!("--{long}"))),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", ");
+        if arg.get_action().takes_values() {
+            tag.push_str(&format!("={}", italic(&value_name(arg))));
+        }
+        write_item(out, &tag, arg)?;
+    }
+
+    if !positionals.is_empty() {
+        writeln!(out, ".SH ARGUMENTS")?;
+        for arg in positionals {
+            write_item(out, &italic(&value_name(arg)), arg)?;
+        }
+    }
+    writeln!(out, ".SH EXIT STATUS")?;
+    writeln!(
+        out,
+        "0 if a line is selected, 1 if none is, 2 if an error occurred."
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("a-b", r"a\-b")]
+    #[case(r"\d", r"\ed")]
+    #[case(".x", r"\&.x")]
+    fn test_escape(#[case] text: &str, #[case] expected: &str) {
+        assert_eq!(escape(text), expected);
+    }
+
+    #[test]
+    fn test_generate() -> io::Result<()> {
+        let mut command = Command::new("grep")
+            .version("1.0")
+            .about("Search")
+            .arg(
+                Arg::new("max_count")
+                    .short('m')
+                    .long("max-count")
+                    .value_name("NUM")
+                    .help("Stop after NUM lines"),
+            )
+            .arg(
+                Arg::new("color")
+                    .long("color")
+                    .value_parser(["auto", "never"])
+                    .help("Highlight"),
+            )
+            .arg(Arg::new("files").action(ArgAction::Append));
+        let mut out = Vec::new();
+        generate(&mut command, &mut out)?;
+        let page = String::from_utf8_lossy(&out);
+
+        for line in [
+            r#".TH GREP 1 "" "grep 1.0""#,
+            r"grep \- Search",
+            r"\fBgrep\fR [\fIOPTIONS\fR] [\fIFILES\fR]...",
+            r"\fB\-m\fR, \fB\-\-max\-count\fR=\fINUM\fR",
+            r"\fB\-\-color\fR=\fICOLOR\fR",
+            r"\fBnever\fR",
+        ] {
+            assert!(page.lines().any(|page_line| page_line == line), "{page}");
+        }
+        Ok(())
+    }
+}
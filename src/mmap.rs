@@ -0,0 +1,148 @@
+use std::fs::File;
+use std::io;
+
+/// Files at least this large are memory mapped when --mmap is not given: for
+/// smaller files, setting up the map costs more than reading them
+const AUTO_THRESHOLD: u64 = 1 << 20;
+
+/// Whether to memory map the file. None is the automatic choice: large regular
+/// files, on the platforms where mapping is faster than reading. macOS is left
+/// out, as ripgrep does
+pub fn should_map(file: &File, mmap: Option<bool>) -> bool {
+    let Ok(metadata) = file.metadata() else {
+        return false;
+    };
+    let is_supported = cfg!(unix) && metadata.is_file();
+    match mmap {
+        Some(mmap) => mmap && is_supported,
+        None => is_supported && !cfg!(target_os = "macos") && metadata.len() >= AUTO_THRESHOLD,
+    }
+}
+
+/// Read-only view of the whole content of a file, paged in by the kernel when
+/// it is accessed. As with any memory map, a file truncated by another process
+/// while it is searched makes the process crash
+#[derive(Debug)]
+pub struct Mmap {
+    ptr: *const u8,
+    len: usize,
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::{c_int, c_long, c_void};
+
+    pub const PROT_READ: c_int = 1;
+    pub const MAP_PRIVATE: c_int = 2;
+
+    extern "C" {
+        pub fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: c_long,
+        ) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+impl Mmap {
+    #[cfg(unix)]
+    pub fn map(file: &File) -> io::Result<Self> {
+        use std::os::fd::AsRawFd;
+
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::other("file too large to be mapped"))?;
+        if len == 0 {
+            // mapping nothing is an error
+            return Ok(Self {
+                ptr: std::ptr::null(),
+                len,
+            });
+        }
+        // SAFETY: the arguments describe a read-only private map of the whole
+        // file, whose descriptor is valid for the duration of the call
+        let ptr = unsafe {
+            sys::mmap(
+                std::ptr::null_mut(),
+                len,
+                sys::PROT_READ,
+                sys::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        // MAP_FAILED
+        if ptr as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            ptr: ptr as *const u8,
+            len,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn map(_file: &File) -> io::Result<Self> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+impl AsRef<[u8]> for Mmap {
+    fn as_ref(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // SAFETY: the map is readable for len bytes until it is dropped
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if self.len > 0 {
+            // SAFETY: the map was created by mmap with this length, and the
+            // slices borrowed from it do not outlive it
+            unsafe {
+                sys::munmap(self.ptr as *mut _, self.len);
+            }
+        }
+    }
+}
+
+// SAFETY: the map is read-only, so it can be read from any thread
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mmap() -> io::Result<()> {
+        let path = std::env::temp_dir().join(format!("grep-mmap-{}", std::process::id()));
+        fs::write(&path, "ab\ncd\n")?;
+        let map = Mmap::map(&File::open(&path)?).map(|map| map.as_ref().to_vec());
+        fs::write(&path, "")?;
+        let empty = Mmap::map(&File::open(&path)?).map(|map| map.as_ref().to_vec());
+        fs::remove_file(&path)?;
+
+        assert_eq!(map?, b"ab\ncd\n");
+        assert_eq!(empty?, b"");
+        Ok(())
+    }
+
+    #[test]
+    fn test_should_map() -> io::Result<()> {
+        let file = File::open(std::env::current_exe()?)?;
+        assert!(!should_map(&file, Some(false)));
+        assert_eq!(should_map(&file, Some(true)), cfg!(unix));
+        Ok(())
+    }
+}
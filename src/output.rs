@@ -0,0 +1,140 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// File written under a temporary name next to its path, then renamed to its
+/// path once complete. Readers of the path see either its previous content or
+/// the whole output, and nothing is left behind if the search fails
+#[derive(Debug)]
+pub struct AtomicFile {
+    file: File,
+    path: PathBuf,
+    temp_path: PathBuf,
+    committed: bool,
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file name"))?;
+        let mut temp_name = OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = path.with_file_name(temp_name);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            temp_path,
+            committed: false,
+        })
+    }
+
+    /// Renames the file to its path, replacing the file there
+    pub fn commit(mut self) -> io::Result<()> {
+        self.file.flush()?;
+        fs::rename(&self.temp_path, &self.path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Where the results of the search are written: standard output, or the file
+/// of --output
+#[derive(Debug)]
+pub enum Output {
+    Stdout(io::StdoutLock<'static>),
+    File(AtomicFile),
+}
+
+impl Output {
+    pub fn new(path: Option<&Path>) -> anyhow::Result<Self> {
+        Ok(match path {
+            Some(path) => Output::File(
+                AtomicFile::create(path)
+                    .map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))?,
+            ),
+            None => Output::Stdout(io::stdout().lock()),
+        })
+    }
+
+    /// Flushes the output, and puts the file of --output in place
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Output::Stdout(mut stdout) => stdout.flush(),
+            Output::File(file) => file.commit(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout(stdout) => stdout.write(buf),
+            Output::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout(stdout) => stdout.flush(),
+            Output::File(file) => file.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomic_file() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("grep-output-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("out");
+        fs::write(&path, "old")?;
+
+        let mut file = AtomicFile::create(&path)?;
+        file.write_all(b"new")?;
+        let before_commit = fs::read_to_string(&path)?;
+        file.commit()?;
+        let after_commit = fs::read_to_string(&path)?;
+
+        // dropped without commit
+        let mut file = AtomicFile::create(&path)?;
+        file.write_all(b"failed")?;
+        drop(file);
+        let after_drop = fs::read_to_string(&path)?;
+        let nb_files = fs::read_dir(&dir)?.count();
+        fs::remove_dir_all(&dir)?;
+
+        assert_eq!(before_commit, "old");
+        assert_eq!(after_commit, "new");
+        assert_eq!(after_drop, "new");
+        assert_eq!(nb_files, 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use std::cell::Cell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Minimum time between two redraws of the line
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Erases the line of the cursor
+const CLEAR_LINE: &str = "\r\x1b[K";
+
+/// Progress line of --progress on standard error: the number of files searched
+/// and of matched lines so far, and the last file searched
+#[derive(Debug, Default)]
+pub struct Progress {
+    nb_files: usize,
+    nb_matched_lines: usize,
+    last_draw: Option<Instant>,
+    /// whether the line is on the terminal, shared with the ClearProgress
+    /// writing the results
+    shown: Rc<Cell<bool>>,
+}
+
+impl Progress {
+    /// Wraps the output of the results, so that the line is erased before
+    /// they are printed
+    pub fn clear_before<W: Write>(&self, out: W) -> ClearProgress<W> {
+        ClearProgress {
+            inner: out,
+            shown: Rc::clone(&self.shown),
+        }
+    }
+
+    /// Counts a searched file, and redraws the line if it was not redrawn
+    /// recently
+    pub fn update(&mut self, name: &str, nb_matched_lines: usize) {
+        self.nb_files += 1;
+        self.nb_matched_lines += nb_matched_lines;
+        if self
+            .last_draw
+            .is_some_and(|last_draw| last_draw.elapsed() < REDRAW_INTERVAL)
+        {
+            return;
+        }
+        self.last_draw = Some(Instant::now());
+        let width = std::env::var("COLUMNS")
+            .ok()
+            .and_then(|columns| columns.parse().ok())
+            .unwrap_or(80);
+        let line = line(self.nb_files, self.nb_matched_lines, name, width);
+        eprint!("{CLEAR_LINE}{line}");
+        self.shown.set(true);
+    }
+
+    /// Erases the line, once the search is over
+    pub fn clear(&self) {
+        if self.shown.replace(false) {
+            eprint!("{CLEAR_LINE}");
+        }
+    }
+}
+
+/// Writer erasing the progress line before writing
+#[derive(Debug)]
+pub struct ClearProgress<W> {
+    inner: W,
+    shown: Rc<Cell<bool>>,
+}
+
+impl<W> ClearProgress<W> {
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for ClearProgress<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.shown.replace(false) {
+            eprint!("{CLEAR_LINE}");
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Text of the progress line, cut to fit in the width of the terminal
+fn line(nb_files: usize, nb_matched_lines: usize, name: &str, width: usize) -> String {
+    let line = format!("{nb_files} files, {nb_matched_lines} matched lines: {name}");
+    // the cursor stays on the last column, which would wrap the line
+    line.chars().take(width.saturating_sub(1)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(80, "3 files, 12 matched lines: src/main.rs")]
+    #[case(31, "3 files, 12 matched lines: src")]
+    fn test_line(#[case] width: usize, #[case] expected: &str) {
+        assert_eq!(line(3, 12, "src/main.rs", width), expected);
+    }
+}
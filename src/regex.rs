@@ -0,0 +1,840 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::{Index, Range};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::backtrack::Backtracker;
+use crate::error::RegexError;
+use crate::regex_compiler::{CompileOptions, MatchKind, Program, DEFAULT_SIZE_LIMIT};
+use crate::regex_lexer::{Dialect, RegexLexer};
+use crate::regex_matcher::PartialMatcher;
+use crate::regex_parser::{Node, RegexParser, META_CHARS};
+use crate::replace::{Replacer, Template};
+
+/// Options of the compilation of a Regex, unless changed by RegexBuilder
+pub(crate) const DEFAULT_OPTIONS: CompileOptions = CompileOptions {
+    multiline: false,
+    dot_matches_new_line: false,
+    match_kind: MatchKind::LeftmostFirst,
+    size_limit: Some(DEFAULT_SIZE_LIMIT),
+};
+
+/// Compiled extended regular expression, with the Perl extensions (non
+/// capturing groups, lookarounds, lazy quantifiers). The compiled program is
+/// immutable and shared by the clones, each search allocating its own
+/// engine, so a regex can be used from several threads at once
+#[derive(Debug, Clone)]
+pub struct Regex {
+    pattern: Arc<str>,
+    program: Arc<Program>,
+    /// named groups, with their number, shared with the Captures
+    names: Arc<[(String, usize)]>,
+    anchored: bool,
+}
+
+impl Regex {
+    /// Regex with the default options, see RegexBuilder
+    pub fn new(pattern: &str) -> Result<Self, RegexError> {
+        RegexBuilder::new(pattern).build()
+    }
+
+    /// Escapes the metacharacters of the text, so that the pattern matches it
+    /// literally
+    pub fn escape(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            if META_CHARS.contains(c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
+
+    /// Pattern the regex was compiled from
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        if self.anchored {
+            return self.captures_at(haystack, 0).is_some();
+        }
+        if self.program.needs_backtracking() {
+            Backtracker::new(&self.program).is_match(haystack.as_bytes())
+        } else {
+            PartialMatcher::new(&self.program).is_match(haystack.as_bytes())
+        }
+    }
+
+    /// Leftmost match in the haystack
+    pub fn find<'h>(&self, haystack: &'h str) -> Option<Match<'h>> {
+        self.captures(haystack)?.get(0)
+    }
+
+    /// Leftmost match starting at or after the byte offset start. Unlike a
+    /// search in &haystack[start..], ^ and the lookbehinds see the text before
+    /// start
+    pub fn find_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Match<'h>> {
+        self.captures_at(haystack, start)?.get(0)
+    }
+
+    /// Groups of the leftmost match starting at or after the byte offset
+    /// start, see find_at
+    pub fn captures_at<'h>(&self, haystack: &'h str, start: usize) -> Option<Captures<'h>> {
+        let slots = self.search_at(haystack, start)?;
+        Some(Captures {
+            haystack,
+            slots,
+            names: Arc::clone(&self.names),
+        })
+    }
+
+    /// Successive non overlapping matches in the haystack
+    pub fn find_iter<'h>(&self, haystack: &'h str) -> Matches<'h> {
+        Matches {
+            captures: self.captures_iter(haystack),
+        }
+    }
+
+    /// Groups of the successive non overlapping matches in the haystack
+    pub fn captures_iter<'h>(&self, haystack: &'h str) -> CaptureMatches<'h> {
+        CaptureMatches {
+            searches: Searches::new(self, haystack),
+        }
+    }
+
+    /// Texts between the matches. An empty match splits between two chars, and
+    /// a match at an edge of the haystack gives an empty text there
+    pub fn split<'h>(&self, haystack: &'h str) -> Split<'h> {
+        Split {
+            searches: Searches::new(self, haystack),
+            last_end: Some(0),
+        }
+    }
+
+    /// Same as split, in at most limit texts, the last one being the rest of
+    /// the haystack
+    pub fn splitn<'h>(&self, haystack: &'h str, limit: usize) -> SplitN<'h> {
+        SplitN {
+            split: self.split(haystack),
+            limit,
+        }
+    }
+
+    /// Groups of the leftmost match in the haystack
+    pub fn captures<'h>(&self, haystack: &'h str) -> Option<Captures<'h>> {
+        let slots = self.search_at(haystack, 0)?;
+        Some(Captures {
+            haystack,
+            slots,
+            names: Arc::clone(&self.names),
+        })
+    }
+
+    /// Haystack with its leftmost match replaced
+    pub fn replace<'h>(&self, haystack: &'h str, replacer: impl Replacer) -> Cow<'h, str> {
+        self.replacen(haystack, 1, replacer)
+    }
+
+    /// Haystack with all its matches replaced
+    pub fn replace_all<'h>(&self, haystack: &'h str, replacer: impl Replacer) -> Cow<'h, str> {
+        self.replacen(haystack, 0, replacer)
+    }
+
+    /// Haystack with its first limit matches replaced, all of them if limit
+    /// is 0. The haystack is borrowed if nothing matches
+    pub fn replacen<'h>(
+        &self,
+        haystack: &'h str,
+        limit: usize,
+        mut replacer: impl Replacer,
+    ) -> Cow<'h, str> {
+        let limit = if limit > 0 { limit } else { usize::MAX };
+        let mut matches = self.captures_iter(haystack).take(limit).peekable();
+        if matches.peek().is_none() {
+            return Cow::Borrowed(haystack);
+        }
+        let mut replaced = String::with_capacity(haystack.len());
+        let mut last_end = 0;
+        for caps in matches {
+            let Some(found) = caps.get(0) else {
+                continue;
+            };
+            replaced.push_str(&haystack[last_end..found.start()]);
+            replacer.replace_append(&caps, &mut replaced);
+            last_end = found.end();
+        }
+        replaced.push_str(&haystack[last_end..]);
+        Cow::Owned(replaced)
+    }
+
+    /// Number of groups, including the whole match
+    pub fn captures_len(&self) -> usize {
+        self.program.nb_slots / 2
+    }
+
+    /// Slots of the leftmost match starting at or after the byte offset start,
+    /// only at start when anchored. The engines do not decode the haystack
+    /// before start, they only look at it for the assertions and the
+    /// lookbehinds, so that searching from successive offsets stays linear
+    fn search_at(&self, haystack: &str, start: usize) -> Option<Vec<Option<usize>>> {
+        let bytes = haystack.as_bytes();
+        if self.program.needs_backtracking() {
+            Backtracker::new(&self.program).captures_at(bytes, start, self.anchored)
+        } else {
+            PartialMatcher::new(&self.program).captures_at(bytes, start, self.anchored)
+        }
+    }
+}
+
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+/// Same as Regex::new, so that a regex can be read from a string, as in a
+/// configuration file. With Display, it round-trips through its pattern
+impl FromStr for Regex {
+    type Err = RegexError;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        Regex::new(pattern)
+    }
+}
+
+/// Compiles a Regex with options. By default, the pattern is a Perl regular
+/// expression matched case sensitively, where ^ and $ only match at the edges
+/// of the haystack and . does not match a newline
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    pattern: String,
+    dialect: Dialect,
+    case_insensitive: bool,
+    smart_case: bool,
+    unicode: bool,
+    anchored: bool,
+    /// None for the one of the dialect
+    match_kind: Option<MatchKind>,
+    options: CompileOptions,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            dialect: Dialect::Perl,
+            case_insensitive: false,
+            smart_case: false,
+            unicode: true,
+            anchored: false,
+            match_kind: None,
+            options: DEFAULT_OPTIONS,
+        }
+    }
+
+    /// Syntax of the pattern. Unless match_kind is called, the basic and
+    /// extended expressions report the longest match, as POSIX requires
+    pub fn dialect(&mut self, dialect: Dialect) -> &mut Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Letters match both their lower and upper case
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut Self {
+        self.case_insensitive = yes;
+        self
+    }
+
+    /// Case insensitive if the pattern has no uppercase literal
+    pub fn smart_case(&mut self, yes: bool) -> &mut Self {
+        self.smart_case = yes;
+        self
+    }
+
+    /// Whether case insensitivity applies beyond the ASCII letters
+    pub fn unicode(&mut self, yes: bool) -> &mut Self {
+        self.unicode = yes;
+        self
+    }
+
+    /// Matches only start at the start of the search, or where the previous
+    /// match ends, as a tokenizer needs
+    pub fn anchored(&mut self, yes: bool) -> &mut Self {
+        self.anchored = yes;
+        self
+    }
+
+    /// ^ and $ match at the start and end of the lines
+    pub fn multiline(&mut self, yes: bool) -> &mut Self {
+        self.options.multiline = yes;
+        self
+    }
+
+    /// . matches a newline
+    pub fn dot_matches_new_line(&mut self, yes: bool) -> &mut Self {
+        self.options.dot_matches_new_line = yes;
+        self
+    }
+
+    /// Maximum number of instructions of the compiled pattern
+    pub fn size_limit(&mut self, size_limit: usize) -> &mut Self {
+        self.options.size_limit = Some(size_limit);
+        self
+    }
+
+    pub fn match_kind(&mut self, match_kind: MatchKind) -> &mut Self {
+        self.match_kind = Some(match_kind);
+        self
+    }
+
+    /// Parses the pattern, without compiling it
+    pub fn build_ast(&self) -> Result<Node, RegexError> {
+        let parse = |case_insensitive| {
+            let lexer = RegexLexer::with_dialect(&self.pattern, self.dialect);
+            let mut parser = RegexParser::new(lexer)?;
+            parser.set_ignore_case(case_insensitive);
+            parser.set_unicode(self.unicode);
+            let node = parser.build_ast(0)?;
+            Ok::<_, RegexError>((node, parser.has_uppercase()))
+        };
+        let (node, has_uppercase) = parse(self.case_insensitive)?;
+        if self.smart_case && !self.case_insensitive && !has_uppercase {
+            return Ok(parse(true)?.0);
+        }
+        Ok(node)
+    }
+
+    pub fn build(&self) -> Result<Regex, RegexError> {
+        let node = self.build_ast()?;
+        let match_kind = self.match_kind.unwrap_or(match self.dialect {
+            Dialect::Perl => MatchKind::LeftmostFirst,
+            Dialect::Basic | Dialect::Extended => MatchKind::LeftmostLongest,
+        });
+        let options = CompileOptions {
+            match_kind,
+            ..self.options
+        };
+        let program = Program::with_options(&node, options)?;
+        Ok(Regex {
+            pattern: self.pattern.as_str().into(),
+            names: program.names.clone().into(),
+            program: Arc::new(program),
+            anchored: self.anchored,
+        })
+    }
+}
+
+/// Part of a haystack matched by a regex, as byte offsets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'h> {
+    haystack: &'h str,
+    start: usize,
+    end: usize,
+}
+
+impl<'h> Match<'h> {
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn as_str(&self) -> &'h str {
+        &self.haystack[self.range()]
+    }
+}
+
+/// Iterator over the matches of a regex, see Regex::find_iter
+#[derive(Debug)]
+pub struct Matches<'h> {
+    captures: CaptureMatches<'h>,
+}
+
+impl<'h> Iterator for Matches<'h> {
+    type Item = Match<'h>;
+
+    fn next(&mut self) -> Option<Match<'h>> {
+        self.captures.next()?.get(0)
+    }
+}
+
+/// Iterator over the groups of the matches of a regex, see
+/// Regex::captures_iter
+#[derive(Debug)]
+pub struct CaptureMatches<'h> {
+    searches: Searches<'h>,
+}
+
+impl<'h> Iterator for CaptureMatches<'h> {
+    type Item = Captures<'h>;
+
+    fn next(&mut self) -> Option<Captures<'h>> {
+        Some(Captures {
+            haystack: self.searches.haystack,
+            slots: self.searches.next_slots()?,
+            names: Arc::clone(&self.searches.regex.names),
+        })
+    }
+}
+
+/// Successive non overlapping matches of a regex, each one searched when the
+/// iterator using them asks for it
+#[derive(Debug)]
+struct Searches<'h> {
+    regex: Regex,
+    haystack: &'h str,
+    /// where the next search starts, None once no match is left
+    start: Option<usize>,
+    /// no match can start there, to avoid reporting twice the same empty match
+    no_start_at: Option<usize>,
+}
+
+impl<'h> Searches<'h> {
+    fn new(regex: &Regex, haystack: &'h str) -> Self {
+        Self {
+            regex: regex.clone(),
+            haystack,
+            start: Some(0),
+            no_start_at: None,
+        }
+    }
+
+    /// Slots of the next match, see Program
+    fn next_slots(&mut self) -> Option<Vec<Option<usize>>> {
+        let mut start = self.start.take()?;
+        if self.no_start_at == Some(start) {
+            // anchored, the next match would have to start there
+            if self.regex.anchored {
+                return None;
+            }
+            start += self.haystack[start..].chars().next()?.len_utf8();
+        }
+        let slots = self.regex.search_at(self.haystack, start)?;
+        let (match_start, match_end) = (slots[0]?, slots[1]?);
+        self.start = Some(match_end);
+        self.no_start_at = (match_start == match_end).then_some(match_end);
+        Some(slots)
+    }
+}
+
+/// Iterator over the texts between the matches of a regex, see Regex::split
+#[derive(Debug)]
+pub struct Split<'h> {
+    searches: Searches<'h>,
+    /// end of the last match, None once the rest of the haystack was given
+    last_end: Option<usize>,
+}
+
+impl<'h> Iterator for Split<'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<&'h str> {
+        let haystack = self.searches.haystack;
+        let last_end = self.last_end?;
+        match self.searches.next_slots() {
+            Some(slots) => {
+                // the whole match is group 0
+                let (start, end) = (slots[0]?, slots[1]?);
+                self.last_end = Some(end);
+                Some(&haystack[last_end..start])
+            }
+            None => {
+                self.last_end = None;
+                Some(&haystack[last_end..])
+            }
+        }
+    }
+}
+
+/// Iterator over a limited number of texts, see Regex::splitn
+#[derive(Debug)]
+pub struct SplitN<'h> {
+    split: Split<'h>,
+    /// number of texts left
+    limit: usize,
+}
+
+impl<'h> Iterator for SplitN<'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<&'h str> {
+        match self.limit {
+            0 => None,
+            1 => {
+                self.limit = 0;
+                let last_end = self.split.last_end.take()?;
+                Some(&self.split.searches.haystack[last_end..])
+            }
+            _ => {
+                self.limit -= 1;
+                self.split.next()
+            }
+        }
+    }
+}
+
+/// Texts matched by the groups of a regex, the whole match being group 0
+#[derive(Debug, Clone)]
+pub struct Captures<'h> {
+    haystack: &'h str,
+    /// group n is at slots 2n and 2n+1, see Program
+    slots: Vec<Option<usize>>,
+    names: Arc<[(String, usize)]>,
+}
+
+impl<'h> Captures<'h> {
+    /// Text matched by the group, None if it did not participate in the match
+    pub fn get(&self, group: usize) -> Option<Match<'h>> {
+        let start = (*self.slots.get(group * 2)?)?;
+        let end = (*self.slots.get(group * 2 + 1)?)?;
+        Some(Match {
+            haystack: self.haystack,
+            start,
+            end,
+        })
+    }
+
+    /// Text matched by the named group
+    pub fn name(&self, name: &str) -> Option<Match<'h>> {
+        let (_, group) = self
+            .names
+            .iter()
+            .find(|(group_name, _)| group_name == name)?;
+        self.get(*group)
+    }
+
+    /// Number of groups, including the ones that did not participate
+    pub fn len(&self) -> usize {
+        self.slots.len() / 2
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Appends the template to dst, where $n and ${n} are replaced by the
+    /// text of group n, $name and ${name} by the one of a named group, and $$
+    /// is a $
+    pub fn expand(&self, template: &str, dst: &mut String) {
+        let mut expanded = Vec::new();
+        Template::new(template, &self.names).expand(
+            self.haystack.as_bytes(),
+            &self.slots,
+            &mut expanded,
+        );
+        // the groups start and end at char boundaries
+        dst.push_str(&String::from_utf8_lossy(&expanded));
+    }
+
+    /// Texts of the groups, in order
+    pub fn iter(&self) -> impl Iterator<Item = Option<Match<'h>>> + '_ {
+        (0..self.len()).map(|group| self.get(group))
+    }
+}
+
+impl Index<usize> for Captures<'_> {
+    type Output = str;
+
+    /// Panics if the group did not participate in the match
+    fn index(&self, group: usize) -> &str {
+        self.get(group)
+            .unwrap_or_else(|| panic!("no match for group {group}"))
+            .as_str()
+    }
+}
+
+impl Index<&str> for Captures<'_> {
+    type Output = str;
+
+    /// Panics if the group did not participate in the match
+    fn index(&self, name: &str) -> &str {
+        self.name(name)
+            .unwrap_or_else(|| panic!("no match for group {name}"))
+            .as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::replace::NoExpand;
+
+    use super::*;
+
+    #[rstest]
+    #[case("a+b", "xaab", true)]
+    #[case("^ab$", "xab", false)]
+    #[case("(a)\\1", "aa", true)]
+    #[case("a(?=b)", "ac", false)]
+    fn test_is_match(
+        #[case] pattern: &str,
+        #[case] haystack: &str,
+        #[case] expected: bool,
+    ) -> anyhow::Result<()> {
+        assert_eq!(Regex::new(pattern)?.is_match(haystack), expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("b+", "abbcb", Some((1, 3, "bb")))]
+    #[case("é|c", "aéc", Some((1, 3, "é")))]
+    #[case("(\\w)\\1", "abccd", Some((2, 4, "cc")))]
+    #[case("z", "abc", None)]
+    fn test_find(
+        #[case] pattern: &str,
+        #[case] haystack: &str,
+        #[case] expected: Option<(usize, usize, &str)>,
+    ) -> anyhow::Result<()> {
+        let found = Regex::new(pattern)?.find(haystack);
+        let found = found.map(|found| (found.start(), found.end(), found.as_str()));
+        assert_eq!(found, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("AB", |_: &mut RegexBuilder| {}, None)]
+    #[case("AB", |builder: &mut RegexBuilder| { builder.case_insensitive(true); }, Some("Ab"))]
+    #[case("ab", |builder: &mut RegexBuilder| { builder.smart_case(true); }, Some("Ab"))]
+    #[case("aB", |builder: &mut RegexBuilder| { builder.smart_case(true); }, None)]
+    #[case("É", |builder: &mut RegexBuilder| { builder.case_insensitive(true); }, Some("é"))]
+    #[case("É", |builder: &mut RegexBuilder| { builder.case_insensitive(true).unicode(false); }, None)]
+    #[case("^b$", |_: &mut RegexBuilder| {}, None)]
+    #[case("^b$", |builder: &mut RegexBuilder| { builder.multiline(true); }, Some("b"))]
+    #[case("^a.b", |_: &mut RegexBuilder| {}, None)]
+    #[case("^a.b", |builder: &mut RegexBuilder| { builder.dot_matches_new_line(true); }, Some("a\nb"))]
+    #[case("Ab|Abé", |_: &mut RegexBuilder| {}, Some("Ab"))]
+    #[case("Ab|Abé", |builder: &mut RegexBuilder| { builder.match_kind(MatchKind::LeftmostLongest); }, Some("Abé"))]
+    #[case("(Ab|Abé)(?!x)", |_: &mut RegexBuilder| {}, Some("Ab"))]
+    #[case("(Ab|Abé)(?!x)", |builder: &mut RegexBuilder| { builder.match_kind(MatchKind::LeftmostLongest); }, Some("Abé"))]
+    fn test_builder(
+        #[case] pattern: &str,
+        #[case] configure: fn(&mut RegexBuilder),
+        #[case] expected: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let mut builder = RegexBuilder::new(pattern);
+        configure(&mut builder);
+        let regex = builder.build()?;
+        let haystack = "a\nb\nAbé\nabb";
+        assert_eq!(regex.find(haystack).map(|found| found.as_str()), expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(Dialect::Perl, None, "a")]
+    #[case(Dialect::Extended, None, "ab")]
+    #[case(Dialect::Basic, None, "ab")]
+    #[case(Dialect::Extended, Some(MatchKind::LeftmostFirst), "a")]
+    fn test_dialect_match_kind(
+        #[case] dialect: Dialect,
+        #[case] match_kind: Option<MatchKind>,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let pattern = if dialect == Dialect::Basic {
+            "a\\|ab"
+        } else {
+            "a|ab"
+        };
+        let mut builder = RegexBuilder::new(pattern);
+        builder.dialect(dialect);
+        if let Some(match_kind) = match_kind {
+            builder.match_kind(match_kind);
+        }
+        let regex = builder.build()?;
+        assert_eq!(
+            regex.find("xab").map(|found| found.as_str()),
+            Some(expected)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_captures() -> anyhow::Result<()> {
+        let regex = Regex::new("(?<year>\\d{4})-(\\d\\d)(x)?")?;
+        let Some(caps) = regex.captures("on 2024-05-17") else {
+            anyhow::bail!("no match");
+        };
+        assert_eq!(&caps[0], "2024-05");
+        assert_eq!(&caps["year"], "2024");
+        assert_eq!(caps.get(2).map(|group| group.range()), Some(8..10));
+        assert_eq!(caps.get(3), None);
+        assert_eq!(caps.name("month"), None);
+        assert_eq!(caps.len(), regex.captures_len());
+        assert_eq!(caps.len(), 4);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(0, "[b]a[b]c[b]")]
+    #[case(1, "[b]abcb")]
+    #[case(2, "[b]a[b]cb")]
+    fn test_replacen(#[case] limit: usize, #[case] expected: &str) -> anyhow::Result<()> {
+        let regex = Regex::new("(?<letter>b)")?;
+        assert_eq!(regex.replacen("babcb", limit, "[${letter}]"), expected);
+        assert_eq!(regex.replacen("babcb", limit, "[$1]".to_string()), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace() -> anyhow::Result<()> {
+        let regex = Regex::new("(\\w+)=(\\w+)")?;
+        assert_eq!(regex.replace_all("a=1 b=2", "$2=$1"), "1=a 2=b");
+        let upper = |caps: &Captures| caps[1].to_uppercase();
+        assert_eq!(regex.replace_all("a=1 b=2", upper), "A B");
+        assert_eq!(regex.replace("a=1 b=2", NoExpand("$0")), "$0 b=2");
+        assert!(matches!(regex.replace("none", "x"), Cow::Borrowed("none")));
+        // the empty matches are replaced too
+        assert_eq!(Regex::new("x*")?.replace_all("ab", "-"), "-a-b-");
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(",", "a,b,,c", vec!["a", "b", "", "c"])]
+    #[case(",+", ",a,,b,", vec!["", "a", "b", ""])]
+    #[case("", "aé", vec!["", "a", "é", ""])]
+    #[case(",", "", vec![""])]
+    fn test_split(
+        #[case] pattern: &str,
+        #[case] haystack: &str,
+        #[case] expected: Vec<&str>,
+    ) -> anyhow::Result<()> {
+        let regex = Regex::new(pattern)?;
+        assert_eq!(regex.split(haystack).collect::<Vec<_>>(), expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(0, vec![])]
+    #[case(1, vec!["a,b,c"])]
+    #[case(2, vec!["a", "b,c"])]
+    #[case(5, vec!["a", "b", "c"])]
+    fn test_splitn(#[case] limit: usize, #[case] expected: Vec<&str>) -> anyhow::Result<()> {
+        let regex = Regex::new(",")?;
+        assert_eq!(regex.splitn("a,b,c", limit).collect::<Vec<_>>(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_iter() -> anyhow::Result<()> {
+        let regex = Regex::new("\\d+")?;
+        let found = regex.find_iter("a1b22c333").map(|found| found.as_str());
+        assert_eq!(found.collect::<Vec<_>>(), ["1", "22", "333"]);
+        // after an empty match, the next one starts at the next char at least
+        let starts = Regex::new("x*|é")?
+            .find_iter("aéx")
+            .map(|found| found.start());
+        assert_eq!(starts.collect::<Vec<_>>(), [0, 1, 3, 4]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_captures_iter() -> anyhow::Result<()> {
+        let regex = Regex::new("(?<key>\\w+)=(\\w*)")?;
+        let pairs = regex
+            .captures_iter("a=1, bc=, d=23")
+            .map(|caps| (caps["key"].to_string(), caps[2].to_string()))
+            .collect::<Vec<_>>();
+        let expected = [("a", "1"), ("bc", ""), ("d", "23")]
+            .map(|(key, value)| (key.to_string(), value.to_string()));
+        assert_eq!(pairs, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_at() -> anyhow::Result<()> {
+        let regex = Regex::new("(?<!\\d)\\d")?;
+        assert_eq!(regex.find_at("12 3", 1).map(|found| found.start()), Some(3));
+        let regex = Regex::new("^a")?;
+        assert_eq!(regex.find_at("aa", 1), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_anchored() -> anyhow::Result<()> {
+        let regex = RegexBuilder::new("\\d+|[a-z]+| ").anchored(true).build()?;
+        let mut tokens = Vec::new();
+        let mut pos = 0;
+        while let Some(token) = regex.find_at("ab 12c", pos) {
+            tokens.push(token.as_str());
+            pos = token.end();
+        }
+        assert_eq!(tokens, ["ab", " ", "12", "c"]);
+        assert_eq!(
+            regex.find_at("ab !c", 2).map(|found| found.as_str()),
+            Some(" ")
+        );
+        assert_eq!(regex.find_at("ab !c", 3), None);
+
+        let found = regex.find_iter("ab1!c").map(|found| found.as_str());
+        assert_eq!(found.collect::<Vec<_>>(), ["ab", "1"]);
+        assert!(!regex.is_match("!ab"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_threads() -> anyhow::Result<()> {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Regex>();
+
+        let regex = Regex::new("(\\w)\\1")?;
+        let clone = regex.clone();
+        assert!(Arc::ptr_eq(&regex.program, &clone.program));
+        let found = std::thread::scope(|scope| {
+            let handles = ["aab", "abb", "abc"].map(|haystack| {
+                let regex = &regex;
+                scope.spawn(move || regex.find(haystack).map(|found| found.start()))
+            });
+            handles.map(|handle| handle.join().unwrap())
+        });
+        assert_eq!(found, [Some(0), Some(1), None]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_size_limit() {
+        assert!(RegexBuilder::new("a{10}{10}")
+            .size_limit(50)
+            .build()
+            .is_err());
+        assert!(RegexBuilder::new("a{10}").size_limit(50).build().is_ok());
+    }
+
+    #[test]
+    fn test_new_error() {
+        assert!(Regex::new("[a").is_err());
+    }
+
+    #[rstest]
+    #[case("a.b", "a\\.b")]
+    #[case("1+1=2?", "1\\+1=2\\?")]
+    #[case("(x)[y]{z}|^$\\*", "\\(x\\)\\[y\\]\\{z\\}\\|\\^\\$\\\\\\*")]
+    #[case("é-#", "é-#")]
+    fn test_escape(#[case] text: &str, #[case] expected: &str) -> anyhow::Result<()> {
+        let escaped = Regex::escape(text);
+        assert_eq!(escaped, expected);
+        let regex = Regex::new(&format!("^{escaped}$"))?;
+        assert!(regex.is_match(text));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_str() -> anyhow::Result<()> {
+        let regex = "a(b+)".parse::<Regex>()?;
+        assert!(regex.is_match("xabb"));
+        assert_eq!(regex.to_string().parse::<Regex>()?.as_str(), "a(b+)");
+        assert_eq!(
+            "a)".parse::<Regex>().err(),
+            Some(RegexError::UnbalancedParen { pos: 1 })
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,440 @@
+use crate::char_class::CharClass;
+use crate::error::RegexError;
+use crate::regex_parser::Node;
+
+/// Zero width conditions checked at the current position
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Assertion {
+    /// ^
+    StartText,
+    /// $
+    EndText,
+    /// the previous char is not a word char
+    NoWordBefore,
+    /// the next char is not a word char
+    NoWordAfter,
+    /// ^ in multiline mode, at the start of the text or after a newline
+    StartLine,
+    /// $ in multiline mode, at the end of the text or before a newline
+    EndLine,
+}
+
+/// Letters, digits and underscore, in any script
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+impl Assertion {
+    /// `prev` and `next` are the chars around the position, None at the edges of
+    /// the text
+    pub fn holds(&self, prev: Option<char>, next: Option<char>) -> bool {
+        match self {
+            Assertion::StartText => prev.is_none(),
+            Assertion::EndText => next.is_none(),
+            Assertion::NoWordBefore => !prev.is_some_and(is_word_char),
+            Assertion::NoWordAfter => !next.is_some_and(is_word_char),
+            Assertion::StartLine => prev.is_none_or(|c| c == '\n'),
+            Assertion::EndLine => next.is_none_or(|c| c == '\n'),
+        }
+    }
+}
+
+/// Instruction of the compiled program, executed by the Pike VM, or by the
+/// backtracker for the ones it does not support
+#[derive(Debug, PartialEq, Clone)]
+pub enum Inst {
+    /// Consumes the given char
+    Char(char),
+    /// Consumes any char of the class
+    Class(CharClass),
+    /// Continues at both targets, the first one has priority
+    Split(usize, usize),
+    /// Continues at the target
+    Jmp(usize),
+    /// Records the current position in the capture slot
+    Save(usize),
+    /// Continues only if the assertion holds
+    Assert(Assertion),
+    /// Consumes the text captured by the group, in any case if ignore_case
+    BackRef { group: usize, ignore_case: bool },
+    /// Runs the lookaround whose body starts at the next instruction, and
+    /// continues at `end` if it matched (or not, if negated)
+    Look {
+        ahead: bool,
+        negated: bool,
+        end: usize,
+    },
+    /// End of the body of a lookaround
+    LookEnd,
+    /// The pattern matched
+    Match,
+}
+
+/// Which match is reported among the ones starting at the leftmost position
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum MatchKind {
+    /// the first one in the order of preference of the alternations and
+    /// quantifiers, as in Perl
+    #[default]
+    LeftmostFirst,
+    /// the longest one, as in POSIX
+    LeftmostLongest,
+}
+
+/// Default maximum number of instructions of a compiled pattern, which large
+/// repetitions such as a{1000}{1000} quickly reach
+pub const DEFAULT_SIZE_LIMIT: usize = 1 << 20;
+
+/// How a pattern is compiled, see RegexBuilder
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompileOptions {
+    /// ^ and $ match around the newlines rather than at the edges of the text
+    pub multiline: bool,
+    /// . matches a newline
+    pub dot_matches_new_line: bool,
+    pub match_kind: MatchKind,
+    /// maximum number of instructions of the program
+    pub size_limit: Option<usize>,
+}
+
+impl Default for CompileOptions {
+    /// Options of a pattern searched in a line
+    fn default() -> Self {
+        Self {
+            multiline: false,
+            dot_matches_new_line: true,
+            match_kind: MatchKind::default(),
+            size_limit: None,
+        }
+    }
+}
+
+/// Pattern compiled to a list of instructions (Thompson construction).
+/// Group n records its start and end positions in slots 2n and 2n+1,
+/// the whole pattern being group 0.
+/// A program may search a set of patterns, each one ending with its own Match
+#[derive(Debug, PartialEq, Clone)]
+pub struct Program {
+    pub insts: Vec<Inst>,
+    pub nb_slots: usize,
+    /// named groups, with their number
+    pub names: Vec<(String, usize)>,
+    pub match_kind: MatchKind,
+    /// position of the Match of each pattern
+    pub match_pcs: Vec<usize>,
+}
+
+impl Program {
+    pub fn new(node: &Node) -> Self {
+        Self::compile(std::slice::from_ref(node), CompileOptions::default())
+    }
+
+    /// Program searching all the patterns at once, to know which of them
+    /// match. Without patterns, nothing matches
+    pub fn new_set(nodes: &[Node]) -> Self {
+        Self::compile(nodes, CompileOptions::default())
+    }
+
+    /// Program searching a whole text rather than a line (-U): ^ and $ match
+    /// around the newlines, and . does not match a newline
+    pub fn new_multiline(node: &Node) -> Self {
+        Self::compile(
+            std::slice::from_ref(node),
+            CompileOptions {
+                multiline: true,
+                dot_matches_new_line: false,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Fails if the program exceeds the size limit
+    pub fn with_options(node: &Node, options: CompileOptions) -> Result<Self, RegexError> {
+        Self::set_with_options(std::slice::from_ref(node), options)
+    }
+
+    /// Same as new_set, failing if the program exceeds the size limit
+    pub fn set_with_options(nodes: &[Node], options: CompileOptions) -> Result<Self, RegexError> {
+        let program = Self::compile(nodes, options);
+        if let Some(size_limit) = options.size_limit {
+            if program.insts.len() > size_limit {
+                return Err(RegexError::SizeLimitExceeded { limit: size_limit });
+            }
+        }
+        Ok(program)
+    }
+
+    fn compile(nodes: &[Node], options: CompileOptions) -> Self {
+        let mut compiler = Compiler {
+            insts: Vec::new(),
+            nb_groups: 1,
+            names: Vec::new(),
+            options,
+        };
+        let mut match_pcs = Vec::with_capacity(nodes.len());
+        // split L1, L2 / L1: first / match / L2: split L2', L3 ...
+        for (i, node) in nodes.iter().enumerate() {
+            if i == nodes.len() - 1 {
+                compiler.compile(node, None);
+                match_pcs.push(compiler.emit(Inst::Match));
+            } else {
+                let split = compiler.emit(Inst::Split(0, 0));
+                compiler.compile(node, None);
+                match_pcs.push(compiler.emit(Inst::Match));
+                compiler.insts[split] = Inst::Split(split + 1, compiler.insts.len());
+            }
+        }
+        if nodes.is_empty() {
+            compiler.emit(Inst::Class(CharClass::default()));
+        }
+
+        Self {
+            insts: compiler.insts,
+            nb_slots: compiler.nb_groups * 2,
+            names: compiler.names,
+            match_kind: options.match_kind,
+            match_pcs,
+        }
+    }
+
+    /// Index of the pattern whose Match is at pc
+    pub fn pattern_at(&self, pc: usize) -> Option<usize> {
+        self.match_pcs.iter().position(|&match_pc| match_pc == pc)
+    }
+
+    /// Backreferences and lookarounds can not be simulated by the Pike VM
+    pub fn needs_backtracking(&self) -> bool {
+        self.insts
+            .iter()
+            .any(|inst| matches!(inst, Inst::BackRef { .. } | Inst::Look { .. }))
+    }
+}
+
+struct Compiler {
+    insts: Vec<Inst>,
+    nb_groups: usize,
+    names: Vec<(String, usize)>,
+    options: CompileOptions,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> usize {
+        self.insts.push(inst);
+        self.insts.len() - 1
+    }
+
+    /// `current_group` is the group the node belongs to. The parser wraps the
+    /// branches of an alternation in a group with the same ref as the enclosing
+    /// one, these groups do not capture anything.
+    fn compile(&mut self, node: &Node, current_group: Option<usize>) {
+        // the program is discarded once it exceeds the size limit, there is no
+        // need to finish it
+        if self
+            .options
+            .size_limit
+            .is_some_and(|size_limit| self.insts.len() > size_limit)
+        {
+            return;
+        }
+        match node {
+            Node::Literal(c) => {
+                self.emit(Inst::Char(*c));
+            }
+            Node::Digit => {
+                self.emit(Inst::Class(CharClass::digit()));
+            }
+            Node::Alphanum => {
+                self.emit(Inst::Class(CharClass::alphanum()));
+            }
+            Node::Wildcard if !self.options.dot_matches_new_line => {
+                let mut class = CharClass::single('\n');
+                class.negate();
+                self.emit(Inst::Class(class));
+            }
+            Node::Wildcard => {
+                self.emit(Inst::Class(CharClass::any()));
+            }
+            Node::Class(class) => {
+                self.emit(Inst::Class(class.clone()));
+            }
+            Node::StartAnchor if self.options.multiline => {
+                self.emit(Inst::Assert(Assertion::StartLine));
+            }
+            Node::StartAnchor => {
+                self.emit(Inst::Assert(Assertion::StartText));
+            }
+            Node::EndAnchor if self.options.multiline => {
+                self.emit(Inst::Assert(Assertion::EndLine));
+            }
+            Node::EndAnchor => {
+                self.emit(Inst::Assert(Assertion::EndText));
+            }
+            Node::NoWordBefore => {
+                self.emit(Inst::Assert(Assertion::NoWordBefore));
+            }
+            Node::NoWordAfter => {
+                self.emit(Inst::Assert(Assertion::NoWordAfter));
+            }
+            Node::Group { nodes, group_ref } => {
+                let captures = current_group != Some(*group_ref);
+                if captures {
+                    self.nb_groups = self.nb_groups.max(group_ref + 1);
+                    self.emit(Inst::Save(group_ref * 2));
+                }
+                for node in nodes {
+                    self.compile(node, Some(*group_ref));
+                }
+                if captures {
+                    self.emit(Inst::Save(group_ref * 2 + 1));
+                }
+            }
+            Node::Or { nodes } => {
+                // split L1, L2 / L1: first / jmp end / L2: split L2', L3 ...
+                let mut jumps = Vec::new();
+                for (i, node) in nodes.iter().enumerate() {
+                    if i == nodes.len() - 1 {
+                        self.compile(node, current_group);
+                    } else {
+                        let split = self.emit(Inst::Split(0, 0));
+                        self.compile(node, current_group);
+                        jumps.push(self.emit(Inst::Jmp(0)));
+                        self.insts[split] = Inst::Split(split + 1, self.insts.len());
+                    }
+                }
+                let end = self.insts.len();
+                for jump in jumps {
+                    self.insts[jump] = Inst::Jmp(end);
+                }
+            }
+            Node::Quantifier {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                // a lazy quantifier prefers to skip the node
+                let split = |repeat, skip| {
+                    if *greedy {
+                        Inst::Split(repeat, skip)
+                    } else {
+                        Inst::Split(skip, repeat)
+                    }
+                };
+                for _ in 0..*min {
+                    self.compile(node, current_group);
+                }
+                match max {
+                    None => {
+                        let start = self.emit(Inst::Split(0, 0));
+                        self.compile(node, current_group);
+                        self.emit(Inst::Jmp(start));
+                        self.insts[start] = split(start + 1, self.insts.len());
+                    }
+                    Some(max) => {
+                        let mut starts = Vec::new();
+                        for _ in *min..*max {
+                            starts.push(self.emit(Inst::Split(0, 0)));
+                            self.compile(node, current_group);
+                        }
+                        let end = self.insts.len();
+                        for start in starts {
+                            self.insts[start] = split(start + 1, end);
+                        }
+                    }
+                }
+            }
+            Node::Named { name, group } => {
+                if let Node::Group { group_ref, .. } = **group {
+                    self.names.push((name.clone(), group_ref));
+                }
+                self.compile(group, current_group);
+            }
+            Node::BackRef { group, ignore_case } => {
+                self.emit(Inst::BackRef {
+                    group: *group,
+                    ignore_case: *ignore_case,
+                });
+            }
+            Node::LookAround {
+                node,
+                ahead,
+                negated,
+            } => {
+                let look = self.emit(Inst::LookEnd);
+                self.compile(node, current_group);
+                self.emit(Inst::LookEnd);
+                self.insts[look] = Inst::Look {
+                    ahead: *ahead,
+                    negated: *negated,
+                    end: self.insts.len(),
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::regex_lexer::{Dialect, RegexLexer};
+    use crate::regex_parser::RegexParser;
+
+    use super::*;
+
+    #[rstest]
+    #[case("ab", vec![Inst::Save(0), Inst::Char('a'), Inst::Char('b'), Inst::Save(1), Inst::Match], 2)]
+    #[case("a(b)?", vec![
+        Inst::Save(0), Inst::Char('a'), Inst::Split(3, 6), Inst::Save(2), Inst::Char('b'), Inst::Save(3),
+        Inst::Save(1), Inst::Match], 4)]
+    #[case("a|b", vec![
+        Inst::Save(0), Inst::Split(2, 4), Inst::Char('a'), Inst::Jmp(5), Inst::Char('b'),
+        Inst::Save(1), Inst::Match], 2)]
+    #[case("^a+$", vec![
+        Inst::Save(0), Inst::Assert(Assertion::StartText), Inst::Char('a'), Inst::Split(4, 6), Inst::Char('a'),
+        Inst::Jmp(3), Inst::Assert(Assertion::EndText), Inst::Save(1), Inst::Match], 2)]
+    #[case("a??", vec![
+        Inst::Save(0), Inst::Split(3, 2), Inst::Char('a'), Inst::Save(1), Inst::Match], 2)]
+    #[case("(?!a)\\1", vec![
+        Inst::Save(0), Inst::Look { ahead: true, negated: true, end: 4 }, Inst::Char('a'), Inst::LookEnd,
+        Inst::BackRef { group: 1, ignore_case: false }, Inst::Save(1), Inst::Match], 2)]
+    fn test_compiler(
+        #[case] pat: &str,
+        #[case] expected: Vec<Inst>,
+        #[case] nb_slots: usize,
+    ) -> anyhow::Result<()> {
+        let lexer = RegexLexer::with_dialect(pat, Dialect::Perl);
+        let mut parser = RegexParser::new(lexer)?;
+        let node = parser.build_ast(0)?;
+
+        let program = Program::new(&node);
+        assert_eq!(program.insts, expected);
+        assert_eq!(program.nb_slots, nb_slots);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compiler_set() -> anyhow::Result<()> {
+        let nodes = ["a", "b"]
+            .iter()
+            .map(|pat| RegexParser::new(RegexLexer::new(pat))?.build_ast(0))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let program = Program::new_set(&nodes);
+        let expected = vec![
+            Inst::Split(1, 5),
+            Inst::Save(0),
+            Inst::Char('a'),
+            Inst::Save(1),
+            Inst::Match,
+            Inst::Save(0),
+            Inst::Char('b'),
+            Inst::Save(1),
+            Inst::Match,
+        ];
+        assert_eq!(program.insts, expected);
+        assert_eq!(program.match_pcs, [4, 8]);
+        assert_eq!(program.pattern_at(8), Some(1));
+        Ok(())
+    }
+}
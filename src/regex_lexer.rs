@@ -1,3 +1,7 @@
+use std::fmt;
+
+use crate::error::RegexError;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum RegexToken {
     /// Literal char in pattern
@@ -31,6 +35,53 @@ pub enum RegexToken {
     EndAnchor,
     /// .
     Wildcard,
+    /// (?: in Perl regular expressions
+    NonCapturingParen,
+    /// (?<name> or (?P<name> in Perl regular expressions
+    NamedParen(String),
+    /// (?=, (?!, (?<= or (?<! in Perl regular expressions
+    LookAround { ahead: bool, negated: bool },
+    /// ? after a quantifier in Perl regular expressions
+    Lazy,
+    /// [:alpha:] in brackets
+    PosixClass(String),
+}
+
+impl fmt::Display for RegexToken {
+    /// Name of the token with its value, as printed by --dump-tokens
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegexToken::Literal(c) => write!(f, "Literal {c:?}"),
+            RegexToken::Quantifier { min, max } => match max {
+                Some(max) if max == min => write!(f, "Quantifier {{{min}}}"),
+                Some(max) => write!(f, "Quantifier {{{min},{max}}}"),
+                None => write!(f, "Quantifier {{{min},}}"),
+            },
+            RegexToken::BackRef(group) => write!(f, "BackRef {group}"),
+            RegexToken::NamedParen(name) => write!(f, "NamedParen {name}"),
+            RegexToken::PosixClass(name) => write!(f, "PosixClass {name}"),
+            RegexToken::LookAround { ahead, negated } => write!(
+                f,
+                "{}Look{}",
+                if *negated { "Negative" } else { "" },
+                if *ahead { "Ahead" } else { "Behind" }
+            ),
+            token => write!(f, "{token:?}"),
+        }
+    }
+}
+
+/// Syntax of the patterns, the only place deciding which features the lexer
+/// recognizes. In basic regular expressions, `|`, `+`, `?`, `(`, `)` and `{`
+/// are literals, and are special when escaped with a backslash. Perl regular
+/// expressions extend the extended ones with non capturing groups, lookarounds
+/// and lazy quantifiers
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    Basic,
+    #[default]
+    Extended,
+    Perl,
 }
 
 /// Overengineered struct to transform the pattern into a set of tokens
@@ -48,10 +99,30 @@ pub struct RegexLexer {
     read_position: usize,
     /// current char under examination (None for EOF)
     ch: Option<char>,
+    /// true between [ and ]: most special chars are literals there
+    in_bracket: bool,
+    /// true right after [, where ^ means negation
+    bracket_start: bool,
+    /// true until the first member of the brackets, where ] is a literal
+    bracket_empty: bool,
+    dialect: Dialect,
+    /// true where a new expression starts: at the beginning of the pattern and
+    /// after ^, ( and |. In basic regular expressions, a * there is a literal
+    expression_start: bool,
+    /// true right after a quantifier, where a ? makes it lazy in Perl regular
+    /// expressions
+    after_quantifier: bool,
+    /// position of the first char of the last token read
+    token_start: usize,
 }
 
 impl RegexLexer {
+    /// Lexer of an extended regular expression
     pub fn new(input: &str) -> Self {
+        Self::with_dialect(input, Dialect::Extended)
+    }
+
+    pub fn with_dialect(input: &str, dialect: Dialect) -> Self {
         let chars = input.chars().collect::<Vec<_>>();
 
         let mut regex_lexer = Self {
@@ -59,6 +130,13 @@ impl RegexLexer {
             position: 0,
             read_position: 0,
             ch: None,
+            in_bracket: false,
+            bracket_start: false,
+            bracket_empty: false,
+            dialect,
+            expression_start: true,
+            after_quantifier: false,
+            token_start: 0,
         };
         regex_lexer.read_char();
         regex_lexer
@@ -68,7 +146,7 @@ impl RegexLexer {
         if self.read_position >= self.chars.len() {
             self.ch = None
         } else {
-            self.ch = Some(self.chars[self.read_position].clone());
+            self.ch = Some(self.chars[self.read_position]);
         }
         self.position = self.read_position;
         self.read_position += 1;
@@ -78,11 +156,12 @@ impl RegexLexer {
         if self.read_position >= self.chars.len() {
             None
         } else {
-            Some(self.chars[self.read_position].clone())
+            Some(self.chars[self.read_position])
         }
     }
 
-    pub fn read_number(&mut self) -> anyhow::Result<usize> {
+    pub fn read_number(&mut self) -> Result<usize, RegexError> {
+        let pos = self.position;
         let mut s = String::new();
 
         while let Some(c) = self.ch {
@@ -101,50 +180,210 @@ impl RegexLexer {
             }
         }
 
-        Ok(s.parse::<usize>()?)
+        s.parse::<usize>()
+            .map_err(|_| RegexError::InvalidNumber { pos })
     }
 
-    pub fn read_brace_quantifier(&mut self) -> anyhow::Result<RegexToken> {
+    /// Position of the first char of the last token read
+    pub fn token_start(&self) -> usize {
+        self.token_start
+    }
+
+    pub fn read_brace_quantifier(&mut self) -> Result<RegexToken, RegexError> {
+        let braces_error = RegexError::InvalidRepetition {
+            pos: self.token_start,
+        };
         self.read_char();
         let min = self.read_number()?;
         self.read_char();
-        let max;
-        match self.ch {
-            Some('}') => {
-                max = Some(min);
+        let max = if self.read_brace_end() {
+            Some(min)
+        } else if self.ch == Some(',') {
+            self.read_char();
+            if self.read_brace_end() {
+                None
+            } else {
+                let max = self.read_number()?;
+                self.read_char();
+                if !self.read_brace_end() || max < min {
+                    return Err(braces_error);
+                }
+                Some(max)
+            }
+        } else {
+            return Err(braces_error);
+        };
+
+        Ok(RegexToken::Quantifier { min, max })
+    }
+
+    /// Whether the current char closes a brace quantifier, which is \} in
+    /// basic regular expressions. The lexer is left on the }
+    fn read_brace_end(&mut self) -> bool {
+        match (self.dialect, self.ch) {
+            (Dialect::Extended | Dialect::Perl, Some('}')) => true,
+            (Dialect::Basic, Some('\\')) if self.peek_char() == Some('}') => {
+                self.read_char();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// In basic regular expressions, $ is an anchor only at the end of the
+    /// pattern or before \) and \|
+    fn is_expression_end(&self) -> bool {
+        matches!(
+            self.chars.get(self.read_position..),
+            None | Some([] | ['\\', '|' | ')', ..])
+        )
+    }
+
+    /// Inside brackets, only ], escapes, [: and a leading ^ are special. A ]
+    /// right after the [ or the ^ is a literal
+    fn next_bracket_token(&mut self, c: char) -> RegexToken {
+        let bracket_start = std::mem::take(&mut self.bracket_start);
+        let bracket_empty = std::mem::take(&mut self.bracket_empty);
+        match c {
+            ']' if !bracket_empty => {
+                self.in_bracket = false;
+                RegexToken::RBracket
+            }
+            '^' if bracket_start => {
+                self.bracket_empty = true;
+                RegexToken::StartAnchor
             }
-            Some(',') => {
-                if let Some('}') = self.peek_char() {
-                    max = None;
+            '[' if self.peek_char() == Some(':') => match self.read_class_name() {
+                Some(name) => RegexToken::PosixClass(name),
+                None => RegexToken::Literal('['),
+            },
+            '\\' => match self.peek_char() {
+                Some('w') => {
                     self.read_char();
-                } else {
+                    RegexToken::AlphaNum
+                }
+                Some('d') => {
                     self.read_char();
-                    max = Some(self.read_number()?);
+                    RegexToken::Digit
+                }
+                Some(x) if x.is_ascii_punctuation() => {
                     self.read_char();
+                    RegexToken::Literal(x)
+                }
+                _ => RegexToken::Literal('\\'),
+            },
+            x => RegexToken::Literal(x),
+        }
+    }
 
-                    if self.ch.unwrap() != '}' {
-                        println!("Problem parsing braces");
-                        std::process::exit(1);
-                    }
+    /// Reads the name of [:name:], the lexer is left on the last ]. Returns
+    /// None, without reading anything, if the [: is not closed by :]
+    fn read_class_name(&mut self) -> Option<String> {
+        let rest = self.chars.get(self.read_position + 1..)?;
+        let len = rest.iter().take_while(|c| c.is_ascii_alphabetic()).count();
+        if rest.get(len..len + 2)? != [':', ']'] {
+            return None;
+        }
+        let name = rest[..len].iter().collect();
+        for _ in 0..len + 3 {
+            self.read_char();
+        }
+        Some(name)
+    }
+
+    pub fn next_token(&mut self) -> Result<RegexToken, RegexError> {
+        self.token_start = self.position;
+        let tok = self.read_token()?;
+        self.expression_start = matches!(
+            tok,
+            RegexToken::StartAnchor | RegexToken::LParen | RegexToken::Pipe
+        ) && !self.in_bracket;
+        self.after_quantifier = matches!(tok, RegexToken::Quantifier { .. });
+        Ok(tok)
+    }
+
+    /// Reads the (? starting a Perl group, the lexer is left on its last char
+    fn read_group_extension(&mut self) -> Result<RegexToken, RegexError> {
+        self.read_char();
+        self.read_char();
+        let tok = match self.ch {
+            Some(':') => RegexToken::NonCapturingParen,
+            Some('=') => RegexToken::LookAround {
+                ahead: true,
+                negated: false,
+            },
+            Some('!') => RegexToken::LookAround {
+                ahead: true,
+                negated: true,
+            },
+            Some('<') if matches!(self.peek_char(), Some('=' | '!')) => {
+                self.read_char();
+                RegexToken::LookAround {
+                    ahead: false,
+                    negated: self.ch == Some('!'),
                 }
             }
+            Some('<') => self.read_group_name()?,
+            Some('P') if self.peek_char() == Some('<') => {
+                self.read_char();
+                self.read_group_name()?
+            }
             _ => {
-                println!("Problem parsing braces");
-                std::process::exit(1);
+                return Err(RegexError::UnknownGroup {
+                    pos: self.token_start,
+                })
             }
-        }
+        };
+        Ok(tok)
+    }
 
-        Ok(RegexToken::Quantifier { min, max })
+    /// Reads the name after the <, up to the >
+    fn read_group_name(&mut self) -> Result<RegexToken, RegexError> {
+        let error = RegexError::InvalidGroupName {
+            pos: self.token_start,
+        };
+        let mut name = String::new();
+        loop {
+            self.read_char();
+            match self.ch {
+                Some('>') => break,
+                Some(c) if c.is_alphanumeric() || c == '_' => name.push(c),
+                _ => return Err(error),
+            }
+        }
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(error);
+        }
+        Ok(RegexToken::NamedParen(name))
     }
 
-    pub fn next_token(&mut self) -> anyhow::Result<RegexToken> {
+    fn read_token(&mut self) -> Result<RegexToken, RegexError> {
+        if let (true, Some(c)) = (self.in_bracket, self.ch) {
+            let tok = self.next_bracket_token(c);
+            self.read_char();
+            return Ok(tok);
+        }
+
+        let basic = self.dialect == Dialect::Basic;
+        let perl = self.dialect == Dialect::Perl;
         let tok = match self.ch {
             None => RegexToken::Eof,
             Some(c) => match c {
+                '(' if perl && self.peek_char() == Some('?') => self.read_group_extension()?,
+                '?' if perl && self.after_quantifier => RegexToken::Lazy,
+                '|' | '(' | ')' | '+' | '?' | '{' if basic => RegexToken::Literal(c),
+                '*' if basic && self.expression_start => RegexToken::Literal(c),
+                '^' if basic && !self.expression_start => RegexToken::Literal(c),
+                '$' if basic && !self.is_expression_end() => RegexToken::Literal(c),
                 '|' => RegexToken::Pipe,
                 '(' => RegexToken::LParen,
                 ')' => RegexToken::RParen,
-                '[' => RegexToken::LBracket,
+                '[' => {
+                    self.in_bracket = true;
+                    self.bracket_start = true;
+                    self.bracket_empty = true;
+                    RegexToken::LBracket
+                }
                 ']' => RegexToken::RBracket,
                 '^' => RegexToken::StartAnchor,
                 '$' => RegexToken::EndAnchor,
@@ -156,6 +395,27 @@ impl RegexLexer {
                     max: Some(1),
                 },
                 '\\' => match self.peek_char() {
+                    Some('{') if basic => {
+                        self.read_char();
+                        self.read_brace_quantifier()?
+                    }
+                    Some(x @ ('|' | '(' | ')' | '+' | '?')) if basic => {
+                        self.read_char();
+                        match x {
+                            '|' => RegexToken::Pipe,
+                            '(' => RegexToken::LParen,
+                            ')' => RegexToken::RParen,
+                            '+' => RegexToken::Quantifier { min: 1, max: None },
+                            _ => RegexToken::Quantifier {
+                                min: 0,
+                                max: Some(1),
+                            },
+                        }
+                    }
+                    Some(x @ ('n' | 't')) => {
+                        self.read_char();
+                        RegexToken::Literal(if x == 'n' { '\n' } else { '\t' })
+                    }
                     Some('w') => {
                         let tok = RegexToken::AlphaNum;
                         self.read_char();
@@ -177,9 +437,16 @@ impl RegexLexer {
                         self.read_char();
                         tok
                     }
-                    _ => {
-                        println!("Error parsing expression");
-                        std::process::exit(1);
+                    Some(ch) => {
+                        return Err(RegexError::UnknownEscape {
+                            pos: self.token_start,
+                            ch,
+                        })
+                    }
+                    None => {
+                        return Err(RegexError::TrailingBackslash {
+                            pos: self.token_start,
+                        })
                     }
                 },
                 '{' => self.read_brace_quantifier()?,
@@ -207,6 +474,12 @@ mod tests {
     #[case("a[bwz]b", vec![RegexToken::Literal('a'), RegexToken::LBracket , RegexToken::Literal('b'), RegexToken::Literal('w'), RegexToken::Literal('z'), RegexToken::RBracket, RegexToken::Literal('b')])]
     #[case("^a.b$", vec![RegexToken::StartAnchor,RegexToken::Literal('a'), RegexToken::Wildcard, RegexToken::Literal('b'), RegexToken::EndAnchor])]
     #[case(r#"a\{"#, vec![RegexToken::Literal('a'), RegexToken::Literal('{')])]
+    #[case("[^a.*-z\\d]^", vec![RegexToken::LBracket, RegexToken::StartAnchor, RegexToken::Literal('a'), RegexToken::Literal('.'), RegexToken::Literal('*'), RegexToken::Literal('-'), RegexToken::Literal('z'), RegexToken::Digit, RegexToken::RBracket, RegexToken::StartAnchor])]
+    #[case("[a^]", vec![RegexToken::LBracket, RegexToken::Literal('a'), RegexToken::Literal('^'), RegexToken::RBracket])]
+    #[case("[]a]", vec![RegexToken::LBracket, RegexToken::Literal(']'), RegexToken::Literal('a'), RegexToken::RBracket])]
+    #[case("[^]]", vec![RegexToken::LBracket, RegexToken::StartAnchor, RegexToken::Literal(']'), RegexToken::RBracket])]
+    #[case("[[:digit:]x]", vec![RegexToken::LBracket, RegexToken::PosixClass("digit".to_string()), RegexToken::Literal('x'), RegexToken::RBracket])]
+    #[case("[[:a]", vec![RegexToken::LBracket, RegexToken::Literal('['), RegexToken::Literal(':'), RegexToken::Literal('a'), RegexToken::RBracket])]
     fn test_lexer(#[case] pat: &str, #[case] expected: Vec<RegexToken>) -> anyhow::Result<()> {
         let mut lexer = RegexLexer::new(pat);
 
@@ -222,4 +495,101 @@ mod tests {
         assert_eq!(tokens, expected);
         Ok(())
     }
+
+    #[rstest]
+    #[case("a+(b|c)?", vec![RegexToken::Literal('a'), RegexToken::Literal('+'), RegexToken::Literal('('), RegexToken::Literal('b'), RegexToken::Literal('|'), RegexToken::Literal('c'), RegexToken::Literal(')'), RegexToken::Literal('?')])]
+    #[case("a\\+\\(b\\|c\\)\\?", vec![RegexToken::Literal('a'), RegexToken::Quantifier { min: 1, max: None }, RegexToken::LParen, RegexToken::Literal('b'), RegexToken::Pipe, RegexToken::Literal('c'), RegexToken::RParen, RegexToken::Quantifier { min: 0, max: Some(1) }])]
+    #[case("a{1}\\{2,\\}", vec![RegexToken::Literal('a'), RegexToken::Literal('{'), RegexToken::Literal('1'), RegexToken::Literal('}'), RegexToken::Quantifier { min: 2, max: None }])]
+    #[case("*a*", vec![RegexToken::Literal('*'), RegexToken::Literal('a'), RegexToken::Quantifier { min: 0, max: None }])]
+    #[case("^*a^$b$", vec![RegexToken::StartAnchor, RegexToken::Literal('*'), RegexToken::Literal('a'), RegexToken::Literal('^'), RegexToken::Literal('$'), RegexToken::Literal('b'), RegexToken::EndAnchor])]
+    #[case("\\(^a$\\)", vec![RegexToken::LParen, RegexToken::StartAnchor, RegexToken::Literal('a'), RegexToken::EndAnchor, RegexToken::RParen])]
+    fn test_lexer_basic(
+        #[case] pat: &str,
+        #[case] expected: Vec<RegexToken>,
+    ) -> anyhow::Result<()> {
+        let mut lexer = RegexLexer::with_dialect(pat, Dialect::Basic);
+
+        let mut tokens = Vec::new();
+        loop {
+            let token = lexer.next_token()?;
+            if let RegexToken::Eof = token {
+                break;
+            }
+            tokens.push(token);
+        }
+
+        assert_eq!(tokens, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("a\\nb\\t", vec![RegexToken::Literal('a'), RegexToken::Literal('\n'), RegexToken::Literal('b'), RegexToken::Literal('\t')])]
+    #[case("(?:a)", vec![RegexToken::NonCapturingParen, RegexToken::Literal('a'), RegexToken::RParen])]
+    #[case("(?=a)(?!b)", vec![RegexToken::LookAround { ahead: true, negated: false }, RegexToken::Literal('a'), RegexToken::RParen, RegexToken::LookAround { ahead: true, negated: true }, RegexToken::Literal('b'), RegexToken::RParen])]
+    #[case("(?<=a)(?<!b)", vec![RegexToken::LookAround { ahead: false, negated: false }, RegexToken::Literal('a'), RegexToken::RParen, RegexToken::LookAround { ahead: false, negated: true }, RegexToken::Literal('b'), RegexToken::RParen])]
+    #[case("(?<y>a)(?P<m_2>)", vec![RegexToken::NamedParen("y".to_string()), RegexToken::Literal('a'), RegexToken::RParen, RegexToken::NamedParen("m_2".to_string()), RegexToken::RParen])]
+    #[case("a*?b??", vec![RegexToken::Literal('a'), RegexToken::Quantifier { min: 0, max: None }, RegexToken::Lazy, RegexToken::Literal('b'), RegexToken::Quantifier { min: 0, max: Some(1) }, RegexToken::Lazy])]
+    #[case("a{2}?\\1", vec![RegexToken::Literal('a'), RegexToken::Quantifier { min: 2, max: Some(2) }, RegexToken::Lazy, RegexToken::BackRef(1)])]
+    fn test_lexer_perl(#[case] pat: &str, #[case] expected: Vec<RegexToken>) -> anyhow::Result<()> {
+        let mut lexer = RegexLexer::with_dialect(pat, Dialect::Perl);
+
+        let mut tokens = Vec::new();
+        loop {
+            let token = lexer.next_token()?;
+            if let RegexToken::Eof = token {
+                break;
+            }
+            tokens.push(token);
+        }
+
+        assert_eq!(tokens, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("a{2", RegexError::InvalidRepetition { pos: 1 })]
+    #[case("a{2,3", RegexError::InvalidRepetition { pos: 1 })]
+    #[case("a{2x}", RegexError::InvalidRepetition { pos: 1 })]
+    #[case("ab{2,1}", RegexError::InvalidRepetition { pos: 2 })]
+    #[case("a{,2}", RegexError::InvalidNumber { pos: 2 })]
+    #[case("a\\", RegexError::TrailingBackslash { pos: 1 })]
+    #[case("ab\\q", RegexError::UnknownEscape { pos: 2, ch: 'q' })]
+    fn test_lexer_error(#[case] pat: &str, #[case] expected: RegexError) {
+        let mut lexer = RegexLexer::new(pat);
+        let result = std::iter::from_fn(|| match lexer.next_token() {
+            Ok(RegexToken::Eof) => None,
+            result => Some(result),
+        })
+        .collect::<Result<Vec<_>, _>>();
+        assert_eq!(result, Err(expected));
+    }
+
+    #[rstest]
+    #[case(Dialect::Perl, "(?x)", RegexError::UnknownGroup { pos: 0 })]
+    #[case(Dialect::Perl, "a(?<1a>b)", RegexError::InvalidGroupName { pos: 1 })]
+    #[case(Dialect::Basic, "a\\{1", RegexError::InvalidRepetition { pos: 1 })]
+    fn test_lexer_error_dialect(
+        #[case] dialect: Dialect,
+        #[case] pat: &str,
+        #[case] expected: RegexError,
+    ) {
+        let mut lexer = RegexLexer::with_dialect(pat, dialect);
+        let result = std::iter::from_fn(|| match lexer.next_token() {
+            Ok(RegexToken::Eof) => None,
+            result => Some(result),
+        })
+        .collect::<Result<Vec<_>, _>>();
+        assert_eq!(result, Err(expected));
+    }
+
+    #[rstest]
+    #[case(RegexToken::Literal('\n'), "Literal '\\n'")]
+    #[case(RegexToken::Quantifier { min: 2, max: Some(2) }, "Quantifier {2}")]
+    #[case(RegexToken::Quantifier { min: 0, max: None }, "Quantifier {0,}")]
+    #[case(RegexToken::LookAround { ahead: false, negated: true }, "NegativeLookBehind")]
+    #[case(RegexToken::NamedParen("year".to_string()), "NamedParen year")]
+    #[case(RegexToken::LParen, "LParen")]
+    fn test_token_display(#[case] token: RegexToken, #[case] expected: &str) {
+        assert_eq!(token.to_string(), expected);
+    }
 }
@@ -1,189 +1,533 @@
-use crate::regex_parser::Node;
-use std::collections::HashSet;
-
-/// Struct that tries to match an input string to a pattern.
-/// To do so, go through the whole AST (starting from root node),
-/// and check if the node currently under examination matches one of the position in th
-/// char vector. Note that quantifiers and Or can generate multiple potential paths,
-/// which is why other matchers are spawned
-/// Note to self: This is completely overkill
-/// + the way I wrote it make it very difficult to handle backreferences with Or and quantifiers
+use std::collections::VecDeque;
+
+use crate::regex_compiler::{Inst, MatchKind, Program};
+use crate::sparse_set::SparseSet;
+use crate::stats::Stats;
+use crate::utf8::{boundary_from, char_at, char_before};
+
+/// Capture slots of a thread, see Program
+type Slots = Vec<Option<usize>>;
+
+/// Set of threads (one per instruction) alive at a given position.
+/// The sparse set keeps the threads in priority order and makes adding and
+/// clearing them O(1), which matters for patterns with many alternations
 #[derive(Debug, Clone)]
-pub struct Matcher {
-    positions: HashSet<usize>,
+struct Threads {
+    set: SparseSet,
+    /// capture slots of the thread at pc are at pc * nb_slots..(pc + 1) * nb_slots
+    slots: Vec<Option<usize>>,
+    nb_slots: usize,
 }
 
-impl Matcher {
-    /// When creating a new matcher, we try to match starting all the positions in the
-    /// char vec
-    pub fn new(len_char: usize) -> Self {
-        let mut positions = HashSet::new();
-        for pos in 0..len_char {
-            positions.insert(pos);
-        }
-        Matcher { positions }
-    }
-    pub fn matches(&mut self, node_to_match: &Node, chars: &[char]) -> bool {
-        self.positions = self
-            .positions
-            .clone()
-            .into_iter()
-            .filter(|&x| x < chars.len())
-            .collect();
-        match node_to_match {
-            Node::StartAnchor => {
-                if self.positions.contains(&0) {
-                    self.positions.clear();
-                    self.positions.insert(0);
-                    true
-                } else {
-                    false
+impl Threads {
+    fn new(nb_insts: usize, nb_slots: usize) -> Self {
+        Self {
+            set: SparseSet::new(nb_insts),
+            slots: vec![None; nb_insts * nb_slots],
+            nb_slots,
+        }
+    }
+
+    fn slots(&self, pc: usize) -> &[Option<usize>] {
+        &self.slots[pc * self.nb_slots..(pc + 1) * self.nb_slots]
+    }
+
+    fn slots_mut(&mut self, pc: usize) -> &mut [Option<usize>] {
+        &mut self.slots[pc * self.nb_slots..(pc + 1) * self.nb_slots]
+    }
+}
+
+/// Work left while following the epsilon transitions of a new thread
+#[derive(Debug, Clone)]
+enum Frame {
+    Explore(usize),
+    /// Puts back the value of the slot once the branch that modified it was explored
+    RestoreSlot {
+        slot: usize,
+        value: Option<usize>,
+    },
+}
+
+/// Position being examined, with the chars around it (None at the edges of the text)
+#[derive(Debug, Clone, Copy)]
+struct Context {
+    pos: usize,
+    prev: Option<char>,
+    next: Option<char>,
+}
+
+/// Pike VM: simulates the program on all the possible paths at once, advancing
+/// every thread one char at a time, so the input is read only once and the
+/// running time stays linear.
+/// Threads are kept in priority order so that the reported match is the
+/// leftmost one, with the same preferences as a backtracking engine. The older
+/// threads come first, so they are also ordered by start.
+/// The epsilon transitions of a thread are only followed once the chars around
+/// its position are known, which is what allows to feed the input progressively.
+#[derive(Debug, Clone)]
+struct PikeVm<'p> {
+    program: &'p Program,
+    /// threads that reached the current position
+    seeds: Threads,
+    /// seeds after following their epsilon transitions
+    clist: Threads,
+    /// threads that reached the next position
+    nlist: Threads,
+    stack: Vec<Frame>,
+    /// slots of the thread being added
+    scratch: Slots,
+    /// slots of the best match found so far
+    matched: Option<Slots>,
+    stats: Stats,
+}
+
+impl<'p> PikeVm<'p> {
+    fn new(program: &'p Program) -> Self {
+        let nb_insts = program.insts.len();
+        Self {
+            program,
+            seeds: Threads::new(nb_insts, program.nb_slots),
+            clist: Threads::new(nb_insts, program.nb_slots),
+            nlist: Threads::new(nb_insts, program.nb_slots),
+            stack: Vec::new(),
+            scratch: vec![None; program.nb_slots],
+            matched: None,
+            stats: Stats::default(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.seeds.set.clear();
+        self.matched = None;
+    }
+
+    /// No thread can improve the match found
+    fn is_done(&self) -> bool {
+        self.matched.is_some() && self.seeds.set.is_empty()
+    }
+
+    /// Follows the epsilon transitions from pc and adds the resulting threads to
+    /// the current list, with the slots in scratch
+    fn add_thread(&mut self, pc: usize, ctx: Context) {
+        let program = self.program;
+        let list = &mut self.clist;
+        let stack = &mut self.stack;
+        let slots = &mut self.scratch;
+
+        stack.push(Frame::Explore(pc));
+        while let Some(frame) = stack.pop() {
+            let pc = match frame {
+                Frame::Explore(pc) => pc,
+                Frame::RestoreSlot { slot, value } => {
+                    slots[slot] = value;
+                    continue;
                 }
+            };
+            if !list.set.insert(pc) {
+                continue;
             }
-            Node::EndAnchor => {
-                todo!()
-            }
-            Node::Wildcard => {
-                let mut new_positions = HashSet::new();
-                for pos in self.positions.iter() {
-                    new_positions.insert(*pos + 1);
+            self.stats.states_touched += 1;
+            match &program.insts[pc] {
+                Inst::Jmp(target) => stack.push(Frame::Explore(*target)),
+                Inst::Split(first, second) => {
+                    stack.push(Frame::Explore(*second));
+                    stack.push(Frame::Explore(*first));
                 }
-                self.positions = new_positions;
-                true
-            }
-            Node::Literal(c) => {
-                let mut at_least_one_match = false;
-
-                let mut new_positions = HashSet::new();
-                for pos in self.positions.iter() {
-                    let is_matching = *c == chars[*pos];
-                    if is_matching {
-                        new_positions.insert(*pos + 1);
-                        at_least_one_match = true;
+                Inst::Save(slot) => {
+                    stack.push(Frame::RestoreSlot {
+                        slot: *slot,
+                        value: slots[*slot],
+                    });
+                    slots[*slot] = Some(ctx.pos);
+                    stack.push(Frame::Explore(pc + 1));
+                }
+                Inst::Assert(assertion) => {
+                    if assertion.holds(ctx.prev, ctx.next) {
+                        stack.push(Frame::Explore(pc + 1));
                     }
                 }
-                self.positions = new_positions;
-                at_least_one_match
+                Inst::Char(_) | Inst::Class(_) | Inst::Match => {
+                    list.slots_mut(pc).copy_from_slice(slots);
+                }
+                // only run by the backtracker, see Program::needs_backtracking
+                Inst::BackRef { .. } | Inst::Look { .. } | Inst::LookEnd => {}
             }
-            Node::Digit => {
-                let mut at_least_one_match = false;
-
-                let mut new_positions = HashSet::new();
-                for pos in self.positions.iter() {
-                    let c = chars[*pos];
-                    let is_matching = c.is_ascii_digit();
-                    if is_matching {
-                        new_positions.insert(*pos + 1);
-                        at_least_one_match = true;
-                    }
+            self.stats.backtrack_depth = self.stats.backtrack_depth.max(stack.len());
+        }
+    }
+
+    /// Examines the position: a new thread starts there if `can_start` is true
+    /// and no match was found yet, with a lower priority than the older threads.
+    /// Returns true if a match ends at this position
+    fn step(&mut self, ctx: Context, can_start: bool) -> bool {
+        let program = self.program;
+        if let Some(c) = ctx.next {
+            self.stats.bytes_scanned += c.len_utf8();
+        }
+
+        self.clist.set.clear();
+        for i in 0..self.seeds.set.len() {
+            let pc = self.seeds.set.get(i);
+            self.scratch.copy_from_slice(self.seeds.slots(pc));
+            self.add_thread(pc, ctx);
+        }
+        if can_start && self.matched.is_none() {
+            self.scratch.fill(None);
+            self.add_thread(0, ctx);
+        }
+
+        let longest = program.match_kind == MatchKind::LeftmostLongest;
+        let mut found = false;
+        self.nlist.set.clear();
+        for i in 0..self.clist.set.len() {
+            let pc = self.clist.set.get(i);
+            let start = self.clist.slots(pc)[0];
+            if longest
+                && self
+                    .matched
+                    .as_ref()
+                    .is_some_and(|matched| start > matched[0])
+            {
+                // a thread starting after the match can not replace it
+                continue;
+            }
+            let is_matching = match &program.insts[pc] {
+                Inst::Char(c) => ctx.next == Some(*c),
+                Inst::Class(class) => ctx.next.is_some_and(|c| class.contains(c)),
+                Inst::Match if longest => {
+                    // the threads with the same start may match a longer text
+                    self.matched = Some(self.clist.slots(pc).to_vec());
+                    found = true;
+                    false
                 }
-                self.positions = new_positions;
-                at_least_one_match
+                Inst::Match => {
+                    self.matched = Some(self.clist.slots(pc).to_vec());
+                    found = true;
+                    // threads with a lower priority can be discarded
+                    break;
+                }
+                _ => false,
+            };
+            if is_matching && self.nlist.set.insert(pc + 1) {
+                self.nlist
+                    .slots_mut(pc + 1)
+                    .copy_from_slice(self.clist.slots(pc));
             }
-            Node::Alphanum => {
-                let mut at_least_one_match = false;
-
-                let mut new_positions = HashSet::new();
-                for pos in self.positions.iter() {
-                    let c = chars[*pos];
-                    let is_matching = c.is_ascii_alphanumeric() || c == '_';
-                    if is_matching {
-                        new_positions.insert(*pos + 1);
-                        at_least_one_match = true;
+        }
+        std::mem::swap(&mut self.seeds, &mut self.nlist);
+        found
+    }
+
+    /// Examines the position when searching a set of patterns: a new thread
+    /// starts at every position, and the pattern of each Match reached is
+    /// marked as matched
+    fn step_set(&mut self, ctx: Context, matched: &mut [bool]) {
+        let program = self.program;
+        if let Some(c) = ctx.next {
+            self.stats.bytes_scanned += c.len_utf8();
+        }
+
+        self.clist.set.clear();
+        for i in 0..self.seeds.set.len() {
+            let pc = self.seeds.set.get(i);
+            self.scratch.copy_from_slice(self.seeds.slots(pc));
+            self.add_thread(pc, ctx);
+        }
+        self.scratch.fill(None);
+        self.add_thread(0, ctx);
+
+        self.nlist.set.clear();
+        for i in 0..self.clist.set.len() {
+            let pc = self.clist.set.get(i);
+            let is_matching = match &program.insts[pc] {
+                Inst::Char(c) => ctx.next == Some(*c),
+                Inst::Class(class) => ctx.next.is_some_and(|c| class.contains(c)),
+                Inst::Match => {
+                    if let Some(pattern) = program.pattern_at(pc) {
+                        matched[pattern] = true;
                     }
+                    false
                 }
-                self.positions = new_positions;
-                at_least_one_match
+                _ => false,
+            };
+            if is_matching && self.nlist.set.insert(pc + 1) {
+                self.nlist
+                    .slots_mut(pc + 1)
+                    .copy_from_slice(self.clist.slots(pc));
             }
-            // should only contain literal nodes
-            Node::Not { nodes } => {
-                let mut chars_not_to_match = HashSet::new();
-                for node in nodes {
-                    match node {
-                        Node::Literal(x) => {
-                            chars_not_to_match.insert(*x);
-                        }
-                        _ => todo!(),
-                    }
+        }
+        std::mem::swap(&mut self.seeds, &mut self.nlist);
+    }
+}
+
+/// Searches a stream given in successive chunks of bytes, so that the input
+/// does not have to be kept in memory.
+/// A match is reported, as byte offsets from the start of the stream, once it
+/// can not be extended anymore, so it may span several chunks.
+/// Invalid UTF-8 sequences are read as U+FFFD.
+#[derive(Debug, Clone)]
+pub struct PartialMatcher<'p> {
+    vm: PikeVm<'p>,
+    /// start of an incomplete UTF-8 sequence at the end of the last chunk
+    partial_char: Vec<u8>,
+    /// number of bytes pushed so far
+    offset: usize,
+    /// decoded chars not examined yet, with their offset
+    queue: VecDeque<(usize, char)>,
+    /// chars examined since the end of the current candidate match. When the
+    /// match is reported, the search restarts from its end so they are
+    /// examined again
+    history: Vec<(usize, char)>,
+    /// char before the current position
+    prev: Option<char>,
+    /// char before the end of the current candidate match
+    candidate_prev: Option<char>,
+    /// no match can start there, to avoid reporting twice the same empty match
+    no_start_at: Option<usize>,
+    /// stop at the first match found, which may not be the one a full search
+    /// would report
+    earliest: bool,
+}
+
+impl<'p> PartialMatcher<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        Self {
+            vm: PikeVm::new(program),
+            partial_char: Vec::new(),
+            offset: 0,
+            queue: VecDeque::new(),
+            history: Vec::new(),
+            prev: None,
+            candidate_prev: None,
+            no_start_at: None,
+            earliest: false,
+        }
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.vm.stats
+    }
+
+    /// Forgets the input pushed so far to search a new stream
+    pub fn reset(&mut self) {
+        self.vm.reset();
+        self.partial_char.clear();
+        self.offset = 0;
+        self.queue.clear();
+        self.history.clear();
+        self.prev = None;
+        self.candidate_prev = None;
+        self.no_start_at = None;
+    }
+
+    /// Searches a whole text, typically a line, stopping as soon as a match is
+    /// found
+    pub fn is_match(&mut self, bytes: &[u8]) -> bool {
+        self.reset();
+        self.earliest = true;
+        let found = !self.push(bytes).is_empty() || !self.finish().is_empty();
+        self.earliest = false;
+        found
+    }
+
+    /// Successive non overlapping matches in a whole text
+    pub fn find_all(&mut self, bytes: &[u8]) -> Vec<(usize, usize)> {
+        self.reset();
+        let mut matches = self.push(bytes);
+        matches.extend(self.finish());
+        matches
+    }
+
+    /// Same as find_all, with the slots of each match
+    pub fn captures_all(&mut self, bytes: &[u8]) -> Vec<Slots> {
+        self.reset();
+        let mut matches = self.push_captures(bytes);
+        matches.extend(self.finish_captures());
+        matches
+    }
+
+    /// Which patterns of a set match a whole text, in a single pass, see
+    /// Program::new_set
+    pub fn matching_patterns(&mut self, bytes: &[u8]) -> Vec<bool> {
+        self.reset();
+        let mut matched = vec![false; self.vm.program.match_pcs.len()];
+        self.decode(bytes, 0);
+        // an incomplete sequence at the end is invalid
+        let partial_char = std::mem::take(&mut self.partial_char);
+        let start = bytes.len() - partial_char.len();
+        for i in 0..partial_char.len() {
+            self.queue
+                .push_back((start + i, char::REPLACEMENT_CHARACTER));
+        }
+        let mut prev = None;
+        while let Some((pos, c)) = self.queue.pop_front() {
+            let ctx = Context {
+                pos,
+                prev,
+                next: Some(c),
+            };
+            self.vm.step_set(ctx, &mut matched);
+            if matched.iter().all(|&matched| matched) {
+                return matched;
+            }
+            prev = Some(c);
+        }
+        let ctx = Context {
+            pos: bytes.len(),
+            prev,
+            next: None,
+        };
+        self.vm.step_set(ctx, &mut matched);
+        matched
+    }
+
+    /// Leftmost match starting at or after the byte offset start, or only at
+    /// start if anchored. Of the text before start, only the char before it is
+    /// read, for the assertions
+    pub fn captures_at(&mut self, bytes: &[u8], start: usize, anchored: bool) -> Option<Slots> {
+        self.reset();
+        let start = boundary_from(bytes, start);
+        let mut ctx = Context {
+            pos: start,
+            prev: char_before(bytes, start).map(|(c, _)| c),
+            next: None,
+        };
+        loop {
+            let next = char_at(bytes, ctx.pos);
+            ctx.next = next.map(|(c, _)| c);
+            self.vm.step(ctx, !anchored || ctx.pos == start);
+            // anchored, no thread can start after start
+            let is_over = self.vm.is_done() || (anchored && self.vm.seeds.set.is_empty());
+            match next {
+                Some((c, len)) if !is_over => {
+                    ctx.prev = Some(c);
+                    ctx.pos += len;
                 }
+                _ => break,
+            }
+        }
+        self.vm.matched.take()
+    }
 
-                let mut at_least_one_match = false;
+    /// Searches the next chunk, returns the matches that are complete
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<(usize, usize)> {
+        Self::spans(self.push_captures(bytes))
+    }
 
-                let mut new_positions = HashSet::new();
-                for pos in self.positions.iter() {
-                    let is_matching = !chars_not_to_match.contains(&chars[*pos]);
-                    if is_matching {
-                        new_positions.insert(*pos + 1);
-                        at_least_one_match = true;
-                    }
+    /// Signals the end of the stream, returns the remaining matches
+    pub fn finish(&mut self) -> Vec<(usize, usize)> {
+        Self::spans(self.finish_captures())
+    }
+
+    fn spans(matches: Vec<Slots>) -> Vec<(usize, usize)> {
+        matches
+            .iter()
+            .filter_map(|slots| Some((slots[0]?, slots[1]?)))
+            .collect()
+    }
+
+    /// Same as push, with the slots of each match
+    fn push_captures(&mut self, bytes: &[u8]) -> Vec<Slots> {
+        let start = self.offset - self.partial_char.len();
+        let mut buf = std::mem::take(&mut self.partial_char);
+        buf.extend_from_slice(bytes);
+        self.offset += bytes.len();
+        self.decode(&buf, start);
+        self.run(false)
+    }
+
+    /// Same as finish, with the slots of each match
+    fn finish_captures(&mut self) -> Vec<Slots> {
+        let partial_char = std::mem::take(&mut self.partial_char);
+        let start = self.offset - partial_char.len();
+        for i in 0..partial_char.len() {
+            self.queue
+                .push_back((start + i, char::REPLACEMENT_CHARACTER));
+        }
+        self.run(true)
+    }
+
+    /// Adds the chars of buf, which starts at the given offset, to the queue.
+    /// An incomplete sequence at the end is kept for the next chunk
+    fn decode(&mut self, mut buf: &[u8], mut offset: usize) {
+        loop {
+            let (valid, invalid_len) = match std::str::from_utf8(buf) {
+                Ok(s) => (s, None),
+                Err(e) => (
+                    // valid_up_to is guaranteed to be a char boundary
+                    std::str::from_utf8(&buf[..e.valid_up_to()]).unwrap_or_default(),
+                    Some(e.error_len()),
+                ),
+            };
+            for (i, c) in valid.char_indices() {
+                self.queue.push_back((offset + i, c));
+            }
+            buf = &buf[valid.len()..];
+            offset += valid.len();
+            match invalid_len {
+                None => return,
+                Some(Some(len)) => {
+                    self.queue.push_back((offset, char::REPLACEMENT_CHARACTER));
+                    buf = &buf[len..];
+                    offset += len;
+                }
+                Some(None) => {
+                    self.partial_char = buf.to_vec();
+                    return;
                 }
-                self.positions = new_positions;
-                at_least_one_match
             }
-            Node::Or { nodes } => {
-                let matcher_clone = self.clone();
-                let mut positions = HashSet::new();
-                let mut at_least_one_match = false;
-                for node in nodes {
-                    let mut matcher = matcher_clone.clone();
-                    if matcher.matches(node, chars) {
-                        at_least_one_match = true;
-                        for pos in matcher.positions {
-                            positions.insert(pos);
-                        }
-                    }
+        }
+    }
+
+    fn run(&mut self, at_end: bool) -> Vec<Slots> {
+        let mut matches = Vec::new();
+        loop {
+            let ctx = match self.queue.front() {
+                Some(&(pos, c)) => Context {
+                    pos,
+                    prev: self.prev,
+                    next: Some(c),
+                },
+                None if at_end => Context {
+                    pos: self.offset,
+                    prev: self.prev,
+                    next: None,
+                },
+                None => break,
+            };
+
+            if self.vm.step(ctx, self.no_start_at != Some(ctx.pos)) {
+                if self.earliest {
+                    matches.extend(self.vm.matched.take());
+                    return matches;
                 }
-                self.positions = positions;
-                at_least_one_match
+                self.history.clear();
+                self.candidate_prev = self.prev;
             }
-            Node::Quantifier { node, min, max } => {
-                let mut positions = HashSet::new();
-                let mut at_least_one_match = false;
-                let mut min = *min;
-                if min == 0 {
-                    positions.extend(self.positions.clone());
-                    at_least_one_match = true;
-                    min = 1;
+            if let Some(c) = ctx.next {
+                self.queue.pop_front();
+                if self.vm.matched.is_some() {
+                    self.history.push((ctx.pos, c));
                 }
+                self.prev = Some(c);
+            }
 
-                let max = match max {
-                    Some(max) => *max,
-                    None => {
-                        let min_pos = *self.positions.iter().min().unwrap_or(&0);
-                        chars.len() - min_pos + 1
-                    }
-                };
-
-                let mut nb_match = 0;
-                let mut matcher = self.clone();
-                while nb_match < max {
-                    let is_matching = matcher.matches(node, chars);
-                    if is_matching {
-                        nb_match += 1;
-                        if nb_match >= min {
-                            at_least_one_match = true;
-                            positions.extend(matcher.positions.clone());
-                        }
-                    } else {
-                        break;
-                    }
+            if self.vm.is_done() {
+                let slots = self.vm.matched.take().unwrap_or_default();
+                if let (Some(Some(start)), Some(Some(end))) = (slots.first(), slots.get(1)) {
+                    self.no_start_at = (start == end).then_some(*end);
+                    matches.push(slots);
                 }
-
-                self.positions = positions;
-                at_least_one_match
-            }
-            Node::Group { nodes, group_ref } => {
-                let mut is_matching = true;
-                for (i, node) in nodes.iter().enumerate() {
-                    if !self.matches(node, chars) {
-                        is_matching = false;
-                        break;
-                    }
+                self.vm.reset();
+                for item in self.history.drain(..).rev() {
+                    self.queue.push_front(item);
                 }
-                is_matching
+                self.prev = self.candidate_prev;
+            } else if ctx.next.is_none() {
+                break;
             }
-            _ => todo!(),
         }
+        matches
     }
 }
 
@@ -191,6 +535,7 @@ impl Matcher {
 mod tests {
     use rstest::rstest;
 
+    use crate::regex_compiler::CompileOptions;
     use crate::{regex_lexer::RegexLexer, regex_parser::RegexParser};
 
     use super::*;
@@ -202,30 +547,236 @@ mod tests {
     #[case("ba?c+d{2,3}f*g", "bccdffffffffg", false)]
     #[case("Ap[^pb]le", "Apple is good", false)]
     #[case("Ap[^ab]le", "Apple is good", true)]
+    #[case("[a-c][0-9x]+[^\\d]", "zzc9x9_", true)]
+    #[case("[a-c][0-9x]+[^\\d]", "zzc99", false)]
     #[case("a.*b", "assgshgsoghsfohgsfoghsfghsgbe", true)]
     #[case("^aa(wz)?43", "aawz43xuy", true)]
     #[case("^(aa|bb)(ef)", "bbefg", true)]
     #[case("^(aa|bb)(ef)", " bbefg", false)]
     #[case("^aa", "baa", false)]
-    // #[case("aa$", "aaaaab", false)]
-    // #[case("aa$", "b(aa)a", true)]
+    #[case("aa$", "aaaaab", false)]
+    #[case("aa$", "b(aa)a", false)]
+    #[case("aa$", "b(aa)aa", true)]
+    #[case("(a|b)c|d", "ad", true)]
+    #[case("(a|b)c|d", "ae", false)]
+    #[case("x*", "", true)]
     fn test_matcher(
         #[case] pat: &str,
         #[case] input: &str,
         #[case] expected: bool,
     ) -> anyhow::Result<()> {
         let pat = pat.to_string();
-        let chars = input.chars().collect::<Vec<_>>();
 
         let lexer = RegexLexer::new(&pat);
         let mut parser = RegexParser::new(lexer)?;
 
         let node = parser.build_ast(0)?;
         dbg!(&node);
-        let mut matcher = Matcher::new(chars.len());
-        let is_match = matcher.matches(&node, &chars);
+        let program = Program::new(&node);
+        let mut matcher = PartialMatcher::new(&program);
+        let is_match = matcher.is_match(input.as_bytes());
         assert_eq!(is_match, expected);
+        // the matcher can be reused
+        assert_eq!(matcher.is_match(input.as_bytes()), expected);
+
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("b+", "aabbbc", vec![Some(2), Some(5)])]
+    #[case("(a|ab)(c|bcd)", "xabcd", vec![Some(1), Some(5), Some(1), Some(2), Some(2), Some(5)])]
+    #[case("(a)?b", "b", vec![Some(0), Some(1), None, None])]
+    #[case("(\\d)+", "a123", vec![Some(1), Some(4), Some(3), Some(4)])]
+    #[case("x*$", "abc", vec![Some(3), Some(3)])]
+    fn test_captures(
+        #[case] pat: &str,
+        #[case] input: &str,
+        #[case] expected: Vec<Option<usize>>,
+    ) -> anyhow::Result<()> {
+        let lexer = RegexLexer::new(pat);
+        let mut parser = RegexParser::new(lexer)?;
+        let node = parser.build_ast(0)?;
+        let program = Program::new(&node);
+        let mut matcher = PartialMatcher::new(&program);
+
+        let mut matches = matcher.push_captures(input.as_bytes());
+        matches.extend(matcher.finish_captures());
+        assert_eq!(matches.first(), Some(&expected));
+        Ok(())
+    }
+
+    #[test]
+    fn test_matcher_stats() -> anyhow::Result<()> {
+        let lexer = RegexLexer::new("b|c");
+        let mut parser = RegexParser::new(lexer)?;
+        let node = parser.build_ast(0)?;
+        let program = Program::new(&node);
+        let mut matcher = PartialMatcher::new(&program);
+
+        assert!(matcher.is_match("aéb".as_bytes()));
+        // 'b' is consumed and the match is seen when examining the next position
+        assert_eq!(matcher.stats().bytes_scanned, 4);
+        // Save, Split, Char, Char for the thread started at each of the 4 positions,
+        // then Jmp, Save, Match after 'b'
+        assert_eq!(matcher.stats().states_touched, 4 * 4 + 3);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("b+", vec!["aab", "bbc", "b"], vec![(2, 5), (6, 7)])]
+    #[case("é+!", vec!["caf\u{e9}", "\u{e9}!"], vec![(3, 8)])]
+    #[case("x*", vec!["ab"], vec![(0, 0), (1, 1), (2, 2)])]
+    #[case("a$", vec!["ba", "a"], vec![(2, 3)])]
+    #[case("^a", vec!["a", "a"], vec![(0, 1)])]
+    #[case("ab|a", vec!["a", "ab"], vec![(0, 1), (1, 3)])]
+    fn test_partial_matcher(
+        #[case] pat: &str,
+        #[case] chunks: Vec<&str>,
+        #[case] expected: Vec<(usize, usize)>,
+    ) -> anyhow::Result<()> {
+        let lexer = RegexLexer::new(pat);
+        let mut parser = RegexParser::new(lexer)?;
+        let node = parser.build_ast(0)?;
+        let program = Program::new(&node);
+        let mut matcher = PartialMatcher::new(&program);
+
+        let mut matches = Vec::new();
+        for chunk in chunks {
+            matches.extend(matcher.push(chunk.as_bytes()));
+        }
+        matches.extend(matcher.finish());
+        assert_eq!(matches, expected);
+        Ok(())
+    }
+
+    fn partial_matcher_results(pat: &str, chunks: &[&[u8]]) -> Vec<Vec<(usize, usize)>> {
+        let lexer = RegexLexer::new(pat);
+        let mut parser = RegexParser::new(lexer).unwrap();
+        let node = parser.build_ast(0).unwrap();
+        let program = Program::new(&node);
+        let mut matcher = PartialMatcher::new(&program);
+
+        let mut results = chunks
+            .iter()
+            .map(|chunk| matcher.push(chunk))
+            .collect::<Vec<_>>();
+        results.push(matcher.finish());
+        results
+    }
+
+    #[rstest]
+    #[case("a+", "baaca", vec![(1, 3), (4, 5)])]
+    #[case("x?", "ab", vec![(0, 0), (1, 1), (2, 2)])]
+    #[case("^a", "aa", vec![(0, 1)])]
+    fn test_find_all(
+        #[case] pat: &str,
+        #[case] input: &str,
+        #[case] expected: Vec<(usize, usize)>,
+    ) -> anyhow::Result<()> {
+        let lexer = RegexLexer::new(pat);
+        let mut parser = RegexParser::new(lexer)?;
+        let node = parser.build_ast(0)?;
+        let program = Program::new(&node);
+        let mut matcher = PartialMatcher::new(&program);
 
+        matcher.push(b"previous input");
+        assert_eq!(matcher.find_all(input.as_bytes()), expected);
         Ok(())
     }
+
+    #[rstest]
+    #[case("a|ab", "abab", vec![(0, 2), (2, 4)])]
+    #[case("abcd|c|bcde", "abcde", vec![(0, 4)])]
+    #[case("(a|ab)(c|bcd)", "abcd", vec![(0, 4)])]
+    fn test_find_all_longest(
+        #[case] pat: &str,
+        #[case] input: &str,
+        #[case] expected: Vec<(usize, usize)>,
+    ) -> anyhow::Result<()> {
+        let lexer = RegexLexer::new(pat);
+        let mut parser = RegexParser::new(lexer)?;
+        let node = parser.build_ast(0)?;
+        let options = CompileOptions {
+            match_kind: MatchKind::LeftmostLongest,
+            ..Default::default()
+        };
+        let program = Program::with_options(&node, options)?;
+        let mut matcher = PartialMatcher::new(&program);
+
+        assert_eq!(matcher.find_all(input.as_bytes()), expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("^a", "aa", 1, false, None)]
+    #[case("é+", "aééb", 1, true, Some((1, 5)))]
+    #[case("\\d+", "a12b345", 1, true, Some((1, 3)))]
+    #[case("\\d+", "a12b345", 3, false, Some((4, 7)))]
+    #[case("\\d+", "a12b345", 3, true, None)]
+    #[case("b", "éb", 1, true, Some((2, 3)))]
+    #[case("x*", "ab", 2, true, Some((2, 2)))]
+    fn test_captures_at(
+        #[case] pat: &str,
+        #[case] input: &str,
+        #[case] start: usize,
+        #[case] anchored: bool,
+        #[case] expected: Option<(usize, usize)>,
+    ) -> anyhow::Result<()> {
+        let lexer = RegexLexer::new(pat);
+        let mut parser = RegexParser::new(lexer)?;
+        let node = parser.build_ast(0)?;
+        let program = Program::new(&node);
+        let mut matcher = PartialMatcher::new(&program);
+
+        let found = matcher.captures_at(input.as_bytes(), start, anchored);
+        assert_eq!(
+            found.and_then(|slots| Some((slots[0]?, slots[1]?))),
+            expected
+        );
+        Ok(())
+    }
+
+    /// Tokenizing with captures_at reads the same bytes for each block added to
+    /// the text: the text before the offset is not decoded again
+    #[test]
+    fn test_captures_at_scaling() -> anyhow::Result<()> {
+        let node = RegexParser::new(RegexLexer::new("\\d+|[a-z]+| "))?.build_ast(0)?;
+        let program = Program::new(&node);
+        let tokenize = |nb_blocks: usize| {
+            let text = "ab 12 ".repeat(nb_blocks);
+            let mut matcher = PartialMatcher::new(&program);
+            let mut pos = 0;
+            while let Some(end) = matcher
+                .captures_at(text.as_bytes(), pos, true)
+                .and_then(|slots| slots[1])
+            {
+                pos = end;
+            }
+            assert_eq!(pos, text.len());
+            *matcher.stats()
+        };
+        let [one, two, three] = [100, 200, 300].map(tokenize);
+        assert_eq!(
+            three.bytes_scanned - two.bytes_scanned,
+            two.bytes_scanned - one.bytes_scanned
+        );
+        assert_eq!(
+            three.states_touched - two.states_touched,
+            two.states_touched - one.states_touched
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_matcher_split_char() {
+        let bytes = "aé!".as_bytes();
+        let results = partial_matcher_results("é", &[&bytes[..2], &bytes[2..]]);
+        assert_eq!(results, vec![vec![], vec![(1, 3)], vec![]]);
+    }
+
+    #[test]
+    fn test_partial_matcher_invalid_utf8() {
+        let results = partial_matcher_results("a.b|a.$", &[b"a\xffba", b"\xc3"]);
+        assert_eq!(results, vec![vec![(0, 3)], vec![], vec![(3, 5)]]);
+    }
 }
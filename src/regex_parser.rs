@@ -1,19 +1,27 @@
-use crate::regex_lexer::{RegexLexer, RegexToken};
+use std::fmt;
+use std::str::FromStr;
 
-#[derive(Debug, PartialEq)]
-/// Node from the AST created by the parser
+use crate::char_class::{CharClass, ClassRange};
+use crate::error::RegexError;
+use crate::regex_lexer::{Dialect, RegexLexer, RegexToken};
+
+#[derive(Debug, Clone, PartialEq)]
+/// Node from the AST created by the parser, which can be walked with a Visitor
 pub enum Node {
     Or {
         nodes: Vec<Node>,
     },
-    Not {
-        nodes: Vec<Node>,
-    },
+    /// Bracket expression such as [a-z\d] or [^abc]
+    Class(CharClass),
     Literal(char),
     // I am actually conflicted about having Start and EndAnchors as nodes
     // Maybe wrap the node instead?
     StartAnchor,
     EndAnchor,
+    /// Start of the text or after a non word char, added by -w
+    NoWordBefore,
+    /// End of the text or before a non word char, added by -w
+    NoWordAfter,
     Digit,
     Alphanum,
     Wildcard,
@@ -25,23 +33,330 @@ pub enum Node {
         node: Box<Node>,
         min: usize,
         max: Option<usize>,
+        /// repeats as much as possible, false for the lazy quantifiers of -P
+        greedy: bool,
     },
+    /// Capturing group with a name, (?<name>...) in Perl regular expressions
+    Named {
+        name: String,
+        group: Box<Node>,
+    },
+    /// \n, the text captured by group n, in any case with -i
+    BackRef {
+        group: usize,
+        ignore_case: bool,
+    },
+    /// Zero width assertion that the node matches (or not, if negated) after
+    /// the current position, or before it if not ahead
+    LookAround {
+        node: Box<Node>,
+        ahead: bool,
+        negated: bool,
+    },
+}
+
+/// Adds the other cases of the chars of the class, beyond ASCII if unicode
+fn case_fold(class: &mut CharClass, unicode: bool) {
+    if unicode {
+        class.case_fold();
+    } else {
+        class.ascii_case_fold();
+    }
+}
+
+/// Literal node, or the class of its cases when ignoring case
+fn literal_node(c: char, ignore_case: bool, unicode: bool) -> Node {
+    if ignore_case {
+        let mut class = CharClass::single(c);
+        case_fold(&mut class, unicode);
+        if class != CharClass::single(c) {
+            return Node::Class(class);
+        }
+    }
+    Node::Literal(c)
+}
+
+impl Node {
+    /// Pattern matching any of the strings (-F), where no char is an operator
+    pub fn fixed_strings(patterns: &[&str], ignore_case: bool) -> Node {
+        let branches = patterns
+            .iter()
+            .map(|pattern| Node::Group {
+                nodes: pattern
+                    .chars()
+                    .map(|c| literal_node(c, ignore_case, true))
+                    .collect(),
+                group_ref: 0,
+            })
+            .collect::<Vec<_>>();
+        Node::alternatives(branches)
+    }
+
+    /// Pattern matching any of the patterns, given as root nodes.
+    /// Without patterns, nothing matches
+    pub fn alternatives(mut patterns: Vec<Node>) -> Node {
+        if patterns.is_empty() {
+            return Node::Class(CharClass::default());
+        }
+        if patterns.len() == 1 {
+            return patterns.remove(0);
+        }
+        Node::Group {
+            nodes: vec![Node::Or { nodes: patterns }],
+            group_ref: 0,
+        }
+    }
+
+    /// Wraps the pattern so that matches are not preceded or followed by a
+    /// word char (-w). The pattern becomes a non capturing part of the root group
+    pub fn word_bounded(self) -> Node {
+        Node::Group {
+            nodes: vec![Node::NoWordBefore, self, Node::NoWordAfter],
+            group_ref: 0,
+        }
+    }
+
+    /// Nodes directly below this one, in the order of the pattern
+    pub fn children(&self) -> Vec<&Node> {
+        match self {
+            Node::Or { nodes } | Node::Group { nodes, .. } => nodes.iter().collect(),
+            Node::Quantifier { node, .. } | Node::LookAround { node, .. } => vec![&**node],
+            Node::Named { group, .. } => vec![&**group],
+            _ => vec![],
+        }
+    }
+
+    /// Indented tree of the nodes, one per line, as printed by --dump-ast
+    pub fn tree(&self) -> String {
+        let mut tree = String::new();
+        self.write_tree(&mut tree, 0);
+        tree
+    }
+
+    fn write_tree(&self, tree: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let label = match self {
+            Node::Or { .. } => "Or".to_string(),
+            Node::Class(class) => format!("Class {class}"),
+            Node::Literal(c) => format!("Literal {c:?}"),
+            Node::Group { group_ref, .. } => format!("Group {group_ref}"),
+            Node::Quantifier {
+                min, max, greedy, ..
+            } => {
+                let max = max.map_or(String::new(), |max| max.to_string());
+                let lazy = if *greedy { "" } else { " lazy" };
+                format!("Quantifier {{{min},{max}}}{lazy}")
+            }
+            Node::Named { name, .. } => format!("Named {name}"),
+            Node::BackRef { group, ignore_case } => {
+                let ignore_case = if *ignore_case { " ignore case" } else { "" };
+                format!("BackRef {group}{ignore_case}")
+            }
+            Node::LookAround { ahead, negated, .. } => {
+                let negative = if *negated { "Negative" } else { "" };
+                let direction = if *ahead { "Ahead" } else { "Behind" };
+                format!("{negative}Look{direction}")
+            }
+            node => format!("{node:?}"),
+        };
+        tree.push_str(&format!("{indent}{label}\n"));
+        for child in self.children() {
+            child.write_tree(tree, depth + 1);
+        }
+    }
+
+    /// Wraps the pattern in ^ and $ so that it has to match the whole line (-x)
+    pub fn line_bounded(self) -> Node {
+        Node::Group {
+            nodes: vec![Node::StartAnchor, self, Node::EndAnchor],
+            group_ref: 0,
+        }
+    }
+}
+
+/// Chars with a meaning in a pattern, written after a \\ to match themselves
+pub(crate) const META_CHARS: &str = "\\.^$|?*+()[]{}";
+
+/// Writes the nodes one after the other, in the group group_ref
+fn write_sequence(f: &mut fmt::Formatter<'_>, nodes: &[Node], group_ref: usize) -> fmt::Result {
+    if let [Node::Or { nodes }] = nodes {
+        return write_branches(f, nodes, group_ref);
+    }
+    for (i, node) in nodes.iter().enumerate() {
+        // \1 followed by 0 would be read as \10
+        let before_digit = matches!(nodes.get(i + 1), Some(Node::Literal(c)) if c.is_ascii_digit());
+        if before_digit && matches!(node, Node::BackRef { .. }) {
+            write!(f, "(?:{node})")?;
+        } else {
+            node.write_pattern(f, group_ref)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the alternatives separated by |, without the groups the parser
+/// creates around each of them
+fn write_branches(f: &mut fmt::Formatter<'_>, nodes: &[Node], group_ref: usize) -> fmt::Result {
+    for (i, node) in nodes.iter().enumerate() {
+        if i > 0 {
+            write!(f, "|")?;
+        }
+        write_inner(f, node, group_ref)?;
+    }
+    Ok(())
+}
+
+/// Writes a node inside parentheses already written, without its own if it
+/// is a non capturing group
+fn write_inner(f: &mut fmt::Formatter<'_>, node: &Node, group_ref: usize) -> fmt::Result {
+    match node {
+        Node::Group {
+            nodes,
+            group_ref: inner_ref,
+        } if *inner_ref == group_ref => write_sequence(f, nodes, group_ref),
+        node => node.write_pattern(f, group_ref),
+    }
+}
+
+impl fmt::Display for Node {
+    /// Pattern in the syntax of -P that parses back to the node. The nodes
+    /// added by -w, which have no syntax, are written as lookarounds
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Group {
+                nodes,
+                group_ref: 0,
+            } => write_sequence(f, nodes, 0),
+            node => node.write_pattern(f, 0),
+        }
+    }
+}
+
+/// Parses a pattern of -P, such as the ones written by Display, so that
+/// ASTs can be exchanged as strings
+impl FromStr for Node {
+    type Err = RegexError;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        let lexer = RegexLexer::with_dialect(pattern, Dialect::Perl);
+        RegexParser::new(lexer)?.build_ast(0)
+    }
+}
+
+impl Node {
+    /// Writes the node as part of the group group_ref. A group with the same
+    /// ref does not capture
+    fn write_pattern(&self, f: &mut fmt::Formatter<'_>, group_ref: usize) -> fmt::Result {
+        match self {
+            Node::Or { nodes } => {
+                write!(f, "(?:")?;
+                write_branches(f, nodes, group_ref)?;
+                write!(f, ")")
+            }
+            Node::Class(class) => write!(f, "{class:#}"),
+            Node::Literal('\n') => write!(f, "\\n"),
+            Node::Literal('\t') => write!(f, "\\t"),
+            Node::Literal(c) if META_CHARS.contains(*c) => write!(f, "\\{c}"),
+            Node::Literal(c) => write!(f, "{c}"),
+            Node::StartAnchor => write!(f, "^"),
+            Node::EndAnchor => write!(f, "$"),
+            Node::NoWordBefore => write!(f, "(?<!\\w)"),
+            Node::NoWordAfter => write!(f, "(?!\\w)"),
+            Node::Digit => write!(f, "\\d"),
+            Node::Alphanum => write!(f, "\\w"),
+            Node::Wildcard => write!(f, "."),
+            Node::Group {
+                nodes,
+                group_ref: inner_ref,
+            } => {
+                let open = if *inner_ref == group_ref { "(?:" } else { "(" };
+                write!(f, "{open}")?;
+                write_sequence(f, nodes, *inner_ref)?;
+                write!(f, ")")
+            }
+            Node::Quantifier {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                // a+? would be lazy
+                if let Node::Quantifier { .. } = **node {
+                    write!(f, "(?:")?;
+                    node.write_pattern(f, group_ref)?;
+                    write!(f, ")")?;
+                } else {
+                    node.write_pattern(f, group_ref)?;
+                }
+                match (min, max) {
+                    (0, None) => write!(f, "*")?,
+                    (1, None) => write!(f, "+")?,
+                    (0, Some(1)) => write!(f, "?")?,
+                    (min, Some(max)) if min == max => write!(f, "{{{min}}}")?,
+                    (min, None) => write!(f, "{{{min},}}")?,
+                    (min, Some(max)) => write!(f, "{{{min},{max}}}")?,
+                }
+                if !greedy {
+                    write!(f, "?")?;
+                }
+                Ok(())
+            }
+            Node::Named { name, group } => {
+                write!(f, "(?<{name}>")?;
+                match &**group {
+                    Node::Group { nodes, group_ref } => write_sequence(f, nodes, *group_ref)?,
+                    group => group.write_pattern(f, group_ref)?,
+                }
+                write!(f, ")")
+            }
+            // the syntax has no case insensitive backreference
+            Node::BackRef { group, .. } => write!(f, "\\{group}"),
+            Node::LookAround {
+                node,
+                ahead,
+                negated,
+            } => {
+                let direction = if *ahead { "" } else { "<" };
+                let kind = if *negated { "!" } else { "=" };
+                write!(f, "(?{direction}{kind}")?;
+                write_inner(f, node, group_ref)?;
+                write!(f, ")")
+            }
+        }
+    }
 }
 
 pub struct RegexParser {
     l: RegexLexer,
     cur_token: RegexToken,
     peek_token: RegexToken,
+    /// positions of the tokens in the pattern
+    cur_pos: usize,
+    peek_pos: usize,
     group_ref: usize,
+    /// positions of the ( of the groups being parsed
+    open_parens: Vec<usize>,
+    /// letters match both their lower and upper case
+    ignore_case: bool,
+    /// with ignore_case, the letters beyond ASCII too
+    unicode: bool,
+    /// whether an uppercase letter was read as a literal, for --smart-case
+    has_uppercase: bool,
 }
 
 impl RegexParser {
-    pub fn new(lexer: RegexLexer) -> anyhow::Result<Self> {
+    pub fn new(lexer: RegexLexer) -> Result<Self, RegexError> {
         let mut parser = Self {
             l: lexer,
             cur_token: RegexToken::Eof,
             peek_token: RegexToken::Eof,
+            cur_pos: 0,
+            peek_pos: 0,
             group_ref: 0,
+            open_parens: Vec::new(),
+            ignore_case: false,
+            unicode: true,
+            has_uppercase: false,
         };
 
         // sets cur and peek token
@@ -50,15 +365,35 @@ impl RegexParser {
         Ok(parser)
     }
 
-    pub fn next_token(&mut self) -> anyhow::Result<()> {
+    pub fn set_ignore_case(&mut self, ignore_case: bool) {
+        self.ignore_case = ignore_case;
+    }
+
+    pub fn set_unicode(&mut self, unicode: bool) {
+        self.unicode = unicode;
+    }
+
+    /// Whether the pattern read so far has an uppercase literal, such as the
+    /// A of [A-Z]. Escapes and group names do not count
+    pub fn has_uppercase(&self) -> bool {
+        self.has_uppercase
+    }
+
+    pub fn next_token(&mut self) -> Result<(), RegexError> {
         self.cur_token = self.peek_token.clone();
+        self.cur_pos = self.peek_pos;
         self.peek_token = self.l.next_token()?;
+        self.peek_pos = self.l.token_start();
+        if let RegexToken::Literal(c) = self.peek_token {
+            self.has_uppercase |= c.is_uppercase();
+        }
         Ok(())
     }
 
-    /// For bracket we only match litterals
-    pub fn build_bracket_group(&mut self) -> anyhow::Result<Node> {
-        let mut nodes = Vec::new();
+    /// Builds the char class between brackets, the [ being at pos. Supports
+    /// ranges and \d, \w
+    pub fn build_bracket_group(&mut self, pos: usize) -> Result<Node, RegexError> {
+        let mut class = CharClass::default();
 
         let mut negated = false;
 
@@ -69,32 +404,62 @@ impl RegexParser {
 
         loop {
             match self.cur_token {
-                RegexToken::Literal(x) => {
-                    nodes.push(Node::Literal(x));
+                RegexToken::Literal(start) => {
+                    if self.peek_token != RegexToken::Literal('-') {
+                        class.union(&CharClass::single(start));
+                    } else {
+                        let start_pos = self.cur_pos;
+                        self.next_token()?;
+                        if let RegexToken::Literal(end) = self.peek_token {
+                            if end < start {
+                                return Err(RegexError::InvalidRange { pos: start_pos });
+                            }
+                            self.next_token()?;
+                            class.union(&CharClass::new(vec![ClassRange::new(start, end)]));
+                        } else {
+                            // trailing dash, as in [a-]
+                            class.union(&CharClass::single(start));
+                            class.union(&CharClass::single('-'));
+                        }
+                    }
+                }
+                RegexToken::Digit => {
+                    class.union(&CharClass::digit());
+                }
+                RegexToken::AlphaNum => {
+                    class.union(&CharClass::alphanum());
+                }
+                RegexToken::PosixClass(ref name) => {
+                    let posix = CharClass::posix(name)
+                        .ok_or(RegexError::UnknownClass { pos: self.cur_pos })?;
+                    class.union(&posix);
                 }
                 RegexToken::RBracket => {
-                    let final_node = if negated {
-                        Node::Not { nodes }
-                    } else {
-                        Node::Or { nodes }
-                    };
-
-                    return Ok(final_node);
+                    // folding happens before the negation so that [^a] does not
+                    // match A
+                    if self.ignore_case {
+                        case_fold(&mut class, self.unicode);
+                    }
+                    if negated {
+                        class.negate();
+                    }
+                    return Ok(Node::Class(class));
                 }
-
-                _ => todo!(),
+                RegexToken::Eof => return Err(RegexError::UnclosedBracket { pos }),
+                _ => unreachable!("the lexer only reads literals and classes in brackets"),
             }
             self.next_token()?;
         }
     }
 
-    pub fn build_ast(&mut self, group_ref: usize) -> anyhow::Result<Node> {
+    /// Parses the group being read, up to its ). The pattern is group 0
+    pub fn build_ast(&mut self, group_ref: usize) -> Result<Node, RegexError> {
         let mut nodes = Vec::new();
 
         loop {
             match self.cur_token {
                 RegexToken::Literal(x) => {
-                    nodes.push(Node::Literal(x));
+                    nodes.push(literal_node(x, self.ignore_case, self.unicode));
                 }
                 RegexToken::Digit => {
                     nodes.push(Node::Digit);
@@ -114,56 +479,111 @@ impl RegexParser {
                 RegexToken::Quantifier { min, max } => {
                     let prev_node = nodes
                         .pop()
-                        .ok_or_else(|| anyhow::anyhow!("Misplaced quantifier"))?;
+                        .ok_or(RegexError::InvalidQuantifier { pos: self.cur_pos })?;
 
                     let node = Node::Quantifier {
                         min,
                         max,
                         node: Box::new(prev_node),
+                        greedy: true,
                     };
                     nodes.push(node);
                 }
+                RegexToken::Lazy => match nodes.last_mut() {
+                    Some(Node::Quantifier { greedy, .. }) => *greedy = false,
+                    _ => return Err(RegexError::InvalidQuantifier { pos: self.cur_pos }),
+                },
+                RegexToken::BackRef(group) => {
+                    nodes.push(Node::BackRef {
+                        group,
+                        ignore_case: self.ignore_case,
+                    });
+                }
                 RegexToken::Pipe => {
                     self.next_token()?;
                     let left_node = Node::Group { nodes, group_ref };
+                    // the right side consumes everything up to the end of the group
                     let right_node = self.build_ast(group_ref)?;
-                    nodes = vec![Node::Or {
-                        nodes: vec![left_node, right_node],
-                    }];
+                    return Ok(Node::Group {
+                        nodes: vec![Node::Or {
+                            nodes: vec![left_node, right_node],
+                        }],
+                        group_ref,
+                    });
                 }
                 RegexToken::LBracket => {
+                    let pos = self.cur_pos;
                     self.next_token()?;
-                    let node = self.build_bracket_group()?;
+                    let node = self.build_bracket_group(pos)?;
                     nodes.push(node);
                 }
+                // a ] outside brackets is a literal
+                RegexToken::RBracket => {
+                    nodes.push(Node::Literal(']'));
+                }
+                RegexToken::PosixClass(_) => {
+                    unreachable!("the lexer only reads [:name:] in brackets")
+                }
                 RegexToken::LParen => {
                     self.group_ref += 1;
-                    self.next_token()?;
-                    let node = self.build_ast(self.group_ref)?;
+                    let node = self.build_group(self.group_ref)?;
                     nodes.push(node);
                 }
-                RegexToken::RParen => {
-                    return Ok(Node::Group { nodes, group_ref });
+                RegexToken::NamedParen(ref name) => {
+                    let name = name.clone();
+                    self.group_ref += 1;
+                    let group = self.build_group(self.group_ref)?;
+                    nodes.push(Node::Named {
+                        name,
+                        group: Box::new(group),
+                    });
                 }
-                RegexToken::Eof => {
-                    return Ok(Node::Group {
-                        nodes,
-                        group_ref: 0,
+                RegexToken::NonCapturingParen => {
+                    // same ref as the enclosing group, so it does not capture
+                    let node = self.build_group(group_ref)?;
+                    nodes.push(node);
+                }
+                RegexToken::LookAround { ahead, negated } => {
+                    let node = self.build_group(group_ref)?;
+                    nodes.push(Node::LookAround {
+                        node: Box::new(node),
+                        ahead,
+                        negated,
                     });
                 }
-                _ => todo!(),
+                RegexToken::RParen if self.open_parens.is_empty() => {
+                    return Err(RegexError::UnbalancedParen { pos: self.cur_pos });
+                }
+                RegexToken::RParen | RegexToken::Eof => {
+                    return Ok(Node::Group { nodes, group_ref });
+                }
             }
 
             self.next_token()?;
         }
     }
+
+    /// Parses a parenthesized group, from its opening token to its )
+    fn build_group(&mut self, group_ref: usize) -> Result<Node, RegexError> {
+        self.open_parens.push(self.cur_pos);
+        self.next_token()?;
+        let node = self.build_ast(group_ref)?;
+        let pos = self.open_parens.pop();
+        if self.cur_token == RegexToken::Eof {
+            return Err(RegexError::UnbalancedParen {
+                pos: pos.unwrap_or_default(),
+            });
+        }
+        Ok(node)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use rstest::rstest;
 
-    use crate::{regex_lexer::RegexLexer, regex_parser::RegexParser};
+    use crate::regex_lexer::{Dialect, RegexLexer};
+    use crate::regex_parser::RegexParser;
 
     use super::*;
 
@@ -186,9 +606,26 @@ Node::Group { nodes: vec![Node::Or { nodes: vec![
                 group_ref: 0 }] }], 
             group_ref: 0 }
     )]
-    #[case("[^abc]", Node::Group{nodes: vec![Node::Not { nodes: vec![
-        Node::Literal('a'), Node::Literal('b'), Node::Literal('c')] 
-    }], group_ref:0})]
+    #[case("(a|b)c",
+    Node::Group { nodes: vec![
+            Node::Group { nodes: vec![Node::Or { nodes: vec![
+                Node::Group { nodes: vec![Node::Literal('a')], group_ref: 1 },
+                Node::Group { nodes: vec![Node::Literal('b')], group_ref: 1 }] }],
+                group_ref: 1 },
+            Node::Literal('c')],
+            group_ref: 0 }
+    )]
+    #[case("[^abc]", Node::Group{nodes: vec![Node::Class({
+        let mut class = CharClass::new(vec![ClassRange::new('a', 'c')]);
+        class.negate();
+        class
+    })], group_ref:0})]
+    #[case("[a-c\\dx-]", Node::Group{nodes: vec![Node::Class(CharClass::new(vec![
+        ClassRange::new('a', 'c'), ClassRange::new('0', '9'), ClassRange::new('x', 'x'), ClassRange::new('-', '-')
+    ]))], group_ref:0})]
+    #[case("[]a[:digit:]]", Node::Group{nodes: vec![Node::Class(CharClass::new(vec![
+        ClassRange::new(']', ']'), ClassRange::new('a', 'a'), ClassRange::new('0', '9')
+    ]))], group_ref:0})]
     fn test_parser(#[case] pat: &str, #[case] expected: Node) -> anyhow::Result<()> {
         let pat = pat.to_string();
 
@@ -201,4 +638,203 @@ Node::Group { nodes: vec![Node::Or { nodes: vec![
 
         Ok(())
     }
+
+    #[rstest]
+    #[case("ab(c", RegexError::UnbalancedParen { pos: 2 })]
+    #[case("(a(b)", RegexError::UnbalancedParen { pos: 0 })]
+    #[case("a)b", RegexError::UnbalancedParen { pos: 1 })]
+    #[case("(a))", RegexError::UnbalancedParen { pos: 3 })]
+    #[case("a[bc", RegexError::UnclosedBracket { pos: 1 })]
+    #[case("[ab-az]", RegexError::InvalidRange { pos: 2 })]
+    #[case("a[[:foo:]]", RegexError::UnknownClass { pos: 2 })]
+    #[case("[]", RegexError::UnclosedBracket { pos: 0 })]
+    #[case("*a", RegexError::InvalidQuantifier { pos: 0 })]
+    #[case("a(+b)", RegexError::InvalidQuantifier { pos: 2 })]
+    #[case("a|?", RegexError::InvalidQuantifier { pos: 2 })]
+    fn test_parser_error(#[case] pat: &str, #[case] expected: RegexError) -> anyhow::Result<()> {
+        let mut parser = RegexParser::new(RegexLexer::new(pat))?;
+        assert_eq!(parser.build_ast(0), Err(expected));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_rbracket() -> anyhow::Result<()> {
+        let mut parser = RegexParser::new(RegexLexer::new("a]"))?;
+        let expected = Node::Group {
+            nodes: vec![Node::Literal('a'), Node::Literal(']')],
+            group_ref: 0,
+        };
+        assert_eq!(parser.build_ast(0)?, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("a1", Node::Group{nodes: vec![
+        Node::Class(CharClass::new(vec![ClassRange::new('a', 'a'), ClassRange::new('A', 'A')])),
+        Node::Literal('1'),
+    ], group_ref: 0})]
+    #[case("[^a-c]", Node::Group{nodes: vec![Node::Class({
+        let mut class = CharClass::new(vec![ClassRange::new('a', 'c'), ClassRange::new('A', 'C')]);
+        class.negate();
+        class
+    })], group_ref: 0})]
+    fn test_parser_ignore_case(#[case] pat: &str, #[case] expected: Node) -> anyhow::Result<()> {
+        let lexer = RegexLexer::new(pat);
+        let mut parser = RegexParser::new(lexer)?;
+        parser.set_ignore_case(true);
+
+        let node = parser.build_ast(0)?;
+        assert_eq!(node, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("(?:ab)+?", Node::Group{nodes: vec![Node::Quantifier {
+        node: Box::new(Node::Group{nodes: vec![Node::Literal('a'), Node::Literal('b')], group_ref: 0}),
+        min: 1, max: None, greedy: false,
+    }], group_ref: 0})]
+    #[case("(a)(?<!b)\\1", Node::Group{nodes: vec![
+        Node::Group{nodes: vec![Node::Literal('a')], group_ref: 1},
+        Node::LookAround {
+            node: Box::new(Node::Group{nodes: vec![Node::Literal('b')], group_ref: 0}),
+            ahead: false, negated: true,
+        },
+        Node::BackRef { group: 1, ignore_case: false },
+    ], group_ref: 0})]
+    #[case("(?<y>a)", Node::Group{nodes: vec![Node::Named {
+        name: "y".to_string(),
+        group: Box::new(Node::Group{nodes: vec![Node::Literal('a')], group_ref: 1}),
+    }], group_ref: 0})]
+    fn test_parser_perl(#[case] pat: &str, #[case] expected: Node) -> anyhow::Result<()> {
+        let lexer = RegexLexer::with_dialect(pat, Dialect::Perl);
+        let mut parser = RegexParser::new(lexer)?;
+
+        let node = parser.build_ast(0)?;
+        assert_eq!(node, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixed_strings() {
+        assert_eq!(
+            Node::fixed_strings(&["a.", ""], false),
+            Node::Group {
+                nodes: vec![Node::Or {
+                    nodes: vec![
+                        Node::Group {
+                            nodes: vec![Node::Literal('a'), Node::Literal('.')],
+                            group_ref: 0
+                        },
+                        Node::Group {
+                            nodes: vec![],
+                            group_ref: 0
+                        },
+                    ]
+                }],
+                group_ref: 0
+            }
+        );
+        assert_eq!(
+            Node::fixed_strings(&["a1"], true),
+            Node::Group {
+                nodes: vec![
+                    Node::Class(CharClass::new(vec![
+                        ClassRange::new('a', 'a'),
+                        ClassRange::new('A', 'A')
+                    ])),
+                    Node::Literal('1'),
+                ],
+                group_ref: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_alternatives() -> anyhow::Result<()> {
+        let patterns = ["a", "b|c"]
+            .iter()
+            .map(|pat| RegexParser::new(RegexLexer::new(pat))?.build_ast(0))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(
+            Node::alternatives(patterns),
+            Node::Group {
+                nodes: vec![Node::Or {
+                    nodes: vec![
+                        Node::Group {
+                            nodes: vec![Node::Literal('a')],
+                            group_ref: 0
+                        },
+                        RegexParser::new(RegexLexer::new("b|c"))?.build_ast(0)?,
+                    ]
+                }],
+                group_ref: 0
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_tree() -> anyhow::Result<()> {
+        let lexer = RegexLexer::with_dialect("(?<y>\\d{4})|[^a]+?$", Dialect::Perl);
+        let node = RegexParser::new(lexer)?.build_ast(0)?;
+        assert_eq!(
+            node.tree(),
+            "Group 0\n  Or\n    Group 0\n      Named y\n        Group 1\n          \
+             Quantifier {4,4}\n            Digit\n    Group 0\n      Quantifier {1,} lazy\n        \
+             Class [^a]\n      EndAnchor\n"
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("a(b|cd)*e", "a(b|cd)*e")]
+    #[case("(?:ab|c)+?|\\d{2,}x{3}", "(?:ab|c)+?|\\d{2,}x{3}")]
+    #[case("(?<year>\\d{4})-(\\w?)\\2", "(?<year>\\d{4})-(\\w?)\\2")]
+    #[case("(?<=a)b(?!c|d)", "(?<=a)b(?!c|d)")]
+    #[case("^\\.\\{\\n[^a-c\\]\t]$", "^\\.\\{\\n[^\t\\]a-c]$")]
+    #[case("(a)\\1(?:0)", "(a)\\1(?:0)")]
+    #[case("(?:a*)*", "(?:a*)*")]
+    fn test_display(#[case] pat: &str, #[case] expected: &str) -> anyhow::Result<()> {
+        let node = pat.parse::<Node>()?;
+        let pattern = node.to_string();
+        assert_eq!(pattern, expected);
+        assert_eq!(pattern.parse::<Node>()?, node);
+        Ok(())
+    }
+
+    #[test]
+    fn test_display_built() {
+        let node = Node::fixed_strings(&["a.b"], false).word_bounded();
+        assert_eq!(node.to_string(), "(?<!\\w)(?:a\\.b)(?!\\w)");
+        let node = Node::Group {
+            nodes: vec![
+                Node::Group {
+                    nodes: vec![Node::Literal('a')],
+                    group_ref: 1,
+                },
+                Node::BackRef {
+                    group: 1,
+                    ignore_case: false,
+                },
+                Node::Literal('0'),
+            ],
+            group_ref: 0,
+        };
+        assert_eq!(node.to_string(), "(a)(?:\\1)0");
+    }
+
+    #[rstest]
+    #[case("abc", false)]
+    #[case("aBc", true)]
+    #[case("[A-Z]", true)]
+    #[case("\\w\\d\\1", false)]
+    #[case("(?<Year>\\d+)", false)]
+    #[case("É", true)]
+    fn test_has_uppercase(#[case] pat: &str, #[case] expected: bool) -> anyhow::Result<()> {
+        let mut parser = RegexParser::new(RegexLexer::with_dialect(pat, Dialect::Perl))?;
+        parser.build_ast(0)?;
+        assert_eq!(parser.has_uppercase(), expected);
+        Ok(())
+    }
 }
@@ -0,0 +1,120 @@
+use crate::backtrack::Backtracker;
+use crate::error::RegexError;
+use crate::regex::{RegexBuilder, DEFAULT_OPTIONS};
+use crate::regex_compiler::Program;
+use crate::regex_matcher::PartialMatcher;
+
+/// Patterns searched together in a single pass, which tells which of them
+/// match. They are compiled with the default options of a Regex
+#[derive(Debug, Clone)]
+pub struct RegexSet {
+    patterns: Vec<String>,
+    program: Program,
+}
+
+impl RegexSet {
+    pub fn new<I, S>(patterns: I) -> Result<Self, RegexError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let patterns = patterns
+            .into_iter()
+            .map(|pattern| pattern.as_ref().to_string())
+            .collect::<Vec<_>>();
+        let nodes = patterns
+            .iter()
+            .map(|pattern| RegexBuilder::new(pattern).build_ast())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            program: Program::set_with_options(&nodes, DEFAULT_OPTIONS)?,
+            patterns,
+        })
+    }
+
+    /// Whether one of the patterns matches
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.matches(haystack).matched_any()
+    }
+
+    /// Which patterns match
+    pub fn matches(&self, haystack: &str) -> SetMatches {
+        let matched = if self.program.needs_backtracking() {
+            Backtracker::new(&self.program).matching_patterns(haystack.as_bytes())
+        } else {
+            PartialMatcher::new(&self.program).matching_patterns(haystack.as_bytes())
+        };
+        SetMatches { matched }
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+}
+
+/// Patterns of a RegexSet that match a haystack, by index
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetMatches {
+    matched: Vec<bool>,
+}
+
+impl SetMatches {
+    pub fn matched_any(&self) -> bool {
+        self.matched.contains(&true)
+    }
+
+    pub fn matched(&self, pattern: usize) -> bool {
+        self.matched.get(pattern).copied().unwrap_or_default()
+    }
+
+    /// Indexes of the patterns that match, in order
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.matched
+            .iter()
+            .enumerate()
+            .filter_map(|(pattern, &matched)| matched.then_some(pattern))
+    }
+
+    /// Number of patterns of the set
+    pub fn len(&self) -> usize {
+        self.matched.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matched.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(&["a+", "b", "^c", "d$"], "xaabd", vec![0, 1, 3])]
+    #[case(&["ab", "b", "abc"], "ab", vec![0, 1])]
+    #[case(&["(a)\\1", "(?<=a)b", "c"], "aab", vec![0, 1])]
+    #[case(&["x", ""], "", vec![1])]
+    #[case(&[], "a", vec![])]
+    fn test_matches(
+        #[case] patterns: &[&str],
+        #[case] haystack: &str,
+        #[case] expected: Vec<usize>,
+    ) -> anyhow::Result<()> {
+        let set = RegexSet::new(patterns)?;
+        let matches = set.matches(haystack);
+        assert_eq!(matches.iter().collect::<Vec<_>>(), expected);
+        assert_eq!(matches.len(), patterns.len());
+        assert_eq!(set.is_match(haystack), !expected.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+use crate::regex::Captures;
+
+/// Piece of a replacement template
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    /// text captured by the group, $0 being the whole match
+    Group(usize),
+}
+
+/// Replacement of the matches with --replace or Regex::replace. $n and ${n}
+/// are the text
+/// captured by group n, $name and ${name} the one of a named group, and $$ is
+/// a $. Unknown groups are replaced by nothing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// `names` are the named groups of the pattern, with their number
+    pub fn new(template: &str, names: &[(String, usize)]) -> Self {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+        while let Some(dollar) = rest.find('$') {
+            literal.push_str(&rest[..dollar]);
+            rest = &rest[dollar + 1..];
+            let (reference, len) = if let Some(braced) = rest.strip_prefix('{') {
+                match braced.find('}') {
+                    Some(end) => (&braced[..end], end + 2),
+                    None => ("", 0),
+                }
+            } else {
+                let len = if rest.starts_with(|c: char| c.is_ascii_digit()) {
+                    rest.find(|c: char| !c.is_ascii_digit())
+                } else {
+                    rest.find(|c: char| !c.is_alphanumeric() && c != '_')
+                };
+                let len = len.unwrap_or(rest.len());
+                (&rest[..len], len)
+            };
+            if reference.is_empty() {
+                // $$, or a $ not followed by a reference
+                literal.push('$');
+                if rest.starts_with('$') {
+                    rest = &rest[1..];
+                }
+                continue;
+            }
+            rest = &rest[len..];
+            let group = match reference.parse::<usize>() {
+                Ok(group) => Some(group),
+                Err(_) => names
+                    .iter()
+                    .find(|(name, _)| name == reference)
+                    .map(|(_, group)| *group),
+            };
+            if !literal.is_empty() {
+                parts.push(Part::Literal(std::mem::take(&mut literal)));
+            }
+            parts.extend(group.map(Part::Group));
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Self { parts }
+    }
+
+    /// Appends the replacement of a match of the text, given the capture slots
+    /// of the match
+    pub fn expand(&self, text: &[u8], slots: &[Option<usize>], out: &mut Vec<u8>) {
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => out.extend_from_slice(literal.as_bytes()),
+                Part::Group(group) => {
+                    let start = slots.get(group * 2).copied().flatten();
+                    let end = slots.get(group * 2 + 1).copied().flatten();
+                    if let (Some(start), Some(end)) = (start, end) {
+                        out.extend_from_slice(&text[start..end]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Replacement of the matches of Regex::replace and its variants: a template
+/// string, or a closure computing it from the groups of the match
+pub trait Replacer {
+    /// Appends the replacement of the match to dst
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String);
+}
+
+impl Replacer for &str {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+}
+
+impl Replacer for String {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+}
+
+impl Replacer for &String {
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+}
+
+impl<F, T> Replacer for F
+where
+    F: FnMut(&Captures<'_>) -> T,
+    T: AsRef<str>,
+{
+    fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+        dst.push_str(self(caps).as_ref());
+    }
+}
+
+/// Replacement used as is, where $ is not special
+#[derive(Debug, Clone, Copy)]
+pub struct NoExpand<'s>(pub &'s str);
+
+impl Replacer for NoExpand<'_> {
+    fn replace_append(&mut self, _: &Captures<'_>, dst: &mut String) {
+        dst.push_str(self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case("[$0]", "[abc]")]
+    #[case("$2-$1", "c-ab")]
+    #[case("${1}x$1x", "abxabx")]
+    #[case("${last}$last.", "cc.")]
+    #[case("$$1 $ ${", "$1 $ ${")]
+    #[case("$3$unknown${9}", "")]
+    fn test_template(#[case] template: &str, #[case] expected: &str) {
+        let names = [("last".to_string(), 2)];
+        let template = Template::new(template, &names);
+        // "abc" matched by (ab)(c)
+        let slots = [Some(0), Some(3), Some(0), Some(2), Some(2), Some(3)];
+
+        let mut out = Vec::new();
+        template.expand(b"abc", &slots, &mut out);
+        assert_eq!(String::from_utf8_lossy(&out), expected);
+    }
+}
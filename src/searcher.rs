@@ -0,0 +1,2003 @@
+use std::cell::Cell;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{BufRead, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+
+use grep_starter_rust::aho_corasick::AhoCorasick;
+use grep_starter_rust::backtrack::Backtracker;
+use grep_starter_rust::horspool::Horspool;
+use grep_starter_rust::regex_compiler::{CompileOptions, MatchKind, Program, DEFAULT_SIZE_LIMIT};
+use grep_starter_rust::regex_matcher::PartialMatcher;
+use grep_starter_rust::regex_parser::Node;
+use grep_starter_rust::replace::Template;
+use grep_starter_rust::shift_or::ShiftOr;
+use grep_starter_rust::stats::Stats;
+use grep_starter_rust::RegexError;
+
+use crate::annotation::Annotation;
+use crate::colors::Colors;
+use crate::format::{Fields, Format};
+use crate::hyperlink::{self, HyperlinkFormat};
+use crate::json;
+
+/// Engines faster than the Pike VM, which only handle some patterns
+#[derive(Debug, Clone)]
+enum FastEngine {
+    Literal(Horspool),
+    Literals(AhoCorasick),
+    /// boxed, its masks make it much larger than the other engines
+    ShiftOr(Box<ShiftOr>),
+}
+
+/// Minimum width of the line numbers and columns with --initial-tab
+const NUMBER_WIDTH: usize = 4;
+/// Minimum width of the byte offsets with --initial-tab
+const OFFSET_WIDTH: usize = 9;
+
+/// Replaces the parts of the lines longer than --max-columns
+const OMITTED: &[u8] = b"[... omitted]";
+
+/// Byte ranges of parts of a line, such as its matches
+type Spans = Vec<(usize, usize)>;
+
+/// Engine matching the lines when no fast engine handles the pattern
+#[derive(Debug)]
+enum LineMatcher<'p> {
+    Pike(Box<PartialMatcher<'p>>),
+    /// for the backreferences and lookarounds
+    Backtrack(Backtracker<'p>),
+    /// with -U, the matches in the whole input, computed before reading its
+    /// lines. The results are those of the line starting at line_start.
+    /// The offsets given are from base, the offset of the input
+    Multiline {
+        matches: Vec<Vec<Option<usize>>>,
+        line_start: usize,
+        base: usize,
+        stats: Stats,
+    },
+}
+
+impl<'p> LineMatcher<'p> {
+    fn new(program: &'p Program) -> Self {
+        if program.needs_backtracking() {
+            LineMatcher::Backtrack(Backtracker::new(program))
+        } else {
+            LineMatcher::Pike(Box::new(PartialMatcher::new(program)))
+        }
+    }
+
+    /// Searches the whole input, whose lines are then given one by one. base
+    /// is the offset of the input, from which the offsets of the lines are
+    fn multiline(program: &'p Program, input: &[u8], base: usize) -> Self {
+        let mut matcher = LineMatcher::new(program);
+        let matches = matcher.captures_all(input);
+        LineMatcher::Multiline {
+            matches,
+            line_start: 0,
+            base,
+            stats: *matcher.stats(),
+        }
+    }
+
+    /// With -U, gives the offset of the next line searched
+    fn set_line_start(&mut self, offset: usize) {
+        if let LineMatcher::Multiline {
+            line_start, base, ..
+        } = self
+        {
+            *line_start = offset - *base;
+        }
+    }
+
+    fn is_match(&mut self, bytes: &[u8]) -> bool {
+        match self {
+            LineMatcher::Pike(matcher) => matcher.is_match(bytes),
+            LineMatcher::Backtrack(backtracker) => backtracker.is_match(bytes),
+            LineMatcher::Multiline { .. } => !self.captures_all(bytes).is_empty(),
+        }
+    }
+
+    fn find_all(&mut self, bytes: &[u8]) -> Vec<(usize, usize)> {
+        match self {
+            LineMatcher::Pike(matcher) => matcher.find_all(bytes),
+            LineMatcher::Backtrack(backtracker) => backtracker.find_all(bytes),
+            LineMatcher::Multiline { .. } => self
+                .captures_all(bytes)
+                .iter()
+                .filter_map(|slots| Some((slots[0]?, slots[1]?)))
+                .collect(),
+        }
+    }
+
+    /// Slots of the groups of each match, as byte offsets. With -U, these are
+    /// the matches overlapping the line or its terminator, cut to the line
+    fn captures_all(&mut self, bytes: &[u8]) -> Vec<Vec<Option<usize>>> {
+        match self {
+            LineMatcher::Pike(matcher) => matcher.captures_all(bytes),
+            LineMatcher::Backtrack(backtracker) => backtracker.captures_all(bytes),
+            LineMatcher::Multiline {
+                matches,
+                line_start,
+                ..
+            } => {
+                let (line_start, line_end) = (*line_start, *line_start + bytes.len());
+                let bounds = |slots: &[Option<usize>]| Some((slots[0]?, slots[1]?));
+                // the matches do not overlap, so they are sorted by their end
+                let first = matches.partition_point(|slots| {
+                    bounds(slots).is_some_and(|(_, end)| end < line_start)
+                });
+                matches[first..]
+                    .iter()
+                    .take_while(|slots| bounds(slots).is_some_and(|(start, _)| start <= line_end))
+                    .filter(|slots| {
+                        // a match ending where the line starts is on the previous line
+                        bounds(slots).is_some_and(|(start, end)| start == end || end > line_start)
+                    })
+                    .map(|slots| {
+                        let clamp = |offset: usize| offset.clamp(line_start, line_end) - line_start;
+                        slots.iter().map(|slot| slot.map(clamp)).collect()
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Which patterns of a set match the line, see Program::new_set
+    fn matching_patterns(&mut self, bytes: &[u8]) -> Vec<bool> {
+        match self {
+            LineMatcher::Pike(matcher) => matcher.matching_patterns(bytes),
+            LineMatcher::Backtrack(backtracker) => backtracker.matching_patterns(bytes),
+            // never built for a set
+            LineMatcher::Multiline { .. } => Vec::new(),
+        }
+    }
+
+    fn stats(&self) -> &Stats {
+        match self {
+            LineMatcher::Pike(matcher) => matcher.stats(),
+            LineMatcher::Backtrack(backtracker) => backtracker.stats(),
+            LineMatcher::Multiline { stats, .. } => stats,
+        }
+    }
+}
+
+/// What is written for each file
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutputMode {
+    /// the selected lines
+    #[default]
+    Lines,
+    /// the number of selected lines
+    Count,
+    /// the number of matches in the selected lines, which is the number of
+    /// selected lines with -v
+    CountMatches,
+    /// the number of selected lines matched by each pattern, see
+    /// Searcher::set_patterns
+    CountPerPattern,
+    /// the name of the file if a line is selected
+    FilesWithMatches,
+    /// the name of the file if no line is selected
+    FilesWithoutMatch,
+    /// nothing, only whether a line is selected matters
+    Quiet,
+    /// one JSON object per line for each event: the beginning of a file,
+    /// a selected or context line, the end of a file
+    Json,
+    /// a GitHub Actions warning annotation for each selected line
+    Github,
+    /// a SARIF result for each selected line, separated by commas. The
+    /// caller writes the document around them
+    Sarif,
+}
+
+/// How the files with a NUL byte are searched
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum BinaryFiles {
+    /// print a one-line message instead of the selected lines
+    #[default]
+    Binary,
+    /// search them as text files
+    Text,
+    /// consider that the rest of the file does not match
+    WithoutMatch,
+}
+
+/// Parts of the output that can be colored
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Filename,
+    LineNumber,
+    Separator,
+    SelectedMatch,
+    ContextMatch,
+    SelectedLine,
+    ContextLine,
+    ByteOffset,
+}
+
+/// Where a printed line, or a match with -o, is in the input
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    /// 1-based
+    line_number: usize,
+    /// 0-based, from the beginning of the input
+    byte_offset: usize,
+    /// 1-based byte column of the first match, with --column
+    column: Option<usize>,
+}
+
+/// Options of the search given on the command line
+#[derive(Debug, Default, Clone)]
+pub struct SearchOptions {
+    /// select the lines that do not match
+    pub invert_match: bool,
+    pub output_mode: OutputMode,
+    /// stop reading a file after this number of selected lines
+    pub max_count: Option<usize>,
+    /// number of lines printed before each selected line
+    pub before_context: usize,
+    /// number of lines printed after each selected line
+    pub after_context: usize,
+    /// line between the groups of context, instead of --
+    pub group_separator: Option<String>,
+    /// print nothing between the groups of context
+    pub no_group_separator: bool,
+    /// print only the matched parts of the lines, one per line
+    pub only_matching: bool,
+    /// with only_matching, print each distinct match of a file once, at the
+    /// end of the file, with the position of its first occurrence
+    pub unique: bool,
+    /// with unique, prefix the matches with their number of occurrences
+    pub unique_count: bool,
+    /// prefix the lines with their 1-based number
+    pub line_number: bool,
+    /// prefix the lines, or the matches with -o, with their 0-based byte offset
+    pub byte_offset: bool,
+    /// prefix the selected lines, or the matches with -o, with the 1-based
+    /// column of their first match
+    pub column: bool,
+    /// with column, print the selected lines once per match, with the column
+    /// of the match
+    pub vimgrep: bool,
+    /// prefix the lines and counts with the name of the file
+    pub with_filename: bool,
+    /// terminate the file names with a NUL byte instead of the separator or
+    /// newline that follows them, for xargs -0
+    pub null: bool,
+    /// lines longer than this number of bytes are replaced by a marker
+    pub max_columns: Option<usize>,
+    /// with max_columns, print the part of the long lines around their first
+    /// match instead
+    pub max_columns_preview: bool,
+    /// align the numbers of the prefixes, and separate the prefixes from the
+    /// line with a tab, so that the lines start on a tab stop
+    pub initial_tab: bool,
+    /// with with_filename, print the name of the file once before its lines
+    /// instead of prefixing them, and an empty line between the files
+    pub heading: bool,
+    /// highlight the output, None to print it as is
+    pub colors: Option<Colors>,
+    /// make the file names hyperlinks to the files, None to print them as is
+    pub hyperlink: Option<HyperlinkFormat>,
+    /// lines end with a NUL byte instead of a newline, in the input and the
+    /// output
+    pub null_data: bool,
+    /// a carriage return before the newline is part of the line terminator:
+    /// $ matches before it, and it is not printed
+    pub crlf: bool,
+    pub binary_files: BinaryFiles,
+    /// flush the output after each line, for live pipelines
+    pub line_buffered: bool,
+    /// print all the lines, the selected ones being highlighted
+    pub passthru: bool,
+    /// template replacing the matches in the selected lines (--replace)
+    pub replace: Option<String>,
+    /// template printed for each match of the selected lines instead of the
+    /// lines (--format)
+    pub format: Option<String>,
+    /// search the whole input at once, so that the matches can span several
+    /// lines. The lines they overlap are selected
+    pub multiline: bool,
+    /// which match is reported among the ones starting at the leftmost
+    /// position, with -o, --color and the other outputs showing the matches
+    pub match_kind: MatchKind,
+}
+
+impl SearchOptions {
+    fn line_terminator(&self) -> u8 {
+        if self.null_data {
+            b'\0'
+        } else {
+            b'\n'
+        }
+    }
+}
+
+/// Searches the pattern in each line of the input and prints the selected lines
+#[derive(Debug, Clone)]
+pub struct Searcher {
+    options: SearchOptions,
+    fast_engine: Option<FastEngine>,
+    program: Program,
+    replace: Option<Template>,
+    format: Option<Format>,
+    /// with hyperlink, absolute path of the file being searched, None if it is
+    /// not a file
+    link_path: Option<PathBuf>,
+    /// with CountPerPattern, the patterns counted separately, and the program
+    /// searching all of them in a single pass
+    patterns: Vec<String>,
+    pattern_set: Program,
+    /// counters of the line matchers for the inputs already searched
+    stats: Stats,
+    /// with heading, whether the name of the file being searched was printed.
+    /// With Sarif, whether a result was printed for it
+    heading_printed: Cell<bool>,
+    /// with heading, whether the name of a file was printed, in which case the
+    /// next one is preceded by an empty line. With Sarif, whether a result was
+    /// printed, in which case the next one is preceded by a comma
+    any_heading_printed: Cell<bool>,
+}
+
+impl Searcher {
+    /// Fails if the compiled pattern exceeds the default size limit
+    pub fn new(node: &Node, options: SearchOptions) -> Result<Self, RegexError> {
+        // use the fastest engine able to handle the pattern. They only search
+        // lines
+        let fast_engine = if options.multiline {
+            None
+        } else if let Some(horspool) = Horspool::from_node(node) {
+            Some(FastEngine::Literal(horspool))
+        } else if let Some(aho_corasick) = AhoCorasick::from_node(node) {
+            Some(FastEngine::Literals(aho_corasick))
+        } else {
+            ShiftOr::new(node).map(|shift_or| FastEngine::ShiftOr(Box::new(shift_or)))
+        };
+
+        // with -U, ^ and $ match around the newlines, and . does not match a
+        // newline, see Program::new_multiline
+        let program = Program::with_options(
+            node,
+            CompileOptions {
+                multiline: options.multiline,
+                dot_matches_new_line: !options.multiline,
+                match_kind: options.match_kind,
+                size_limit: Some(DEFAULT_SIZE_LIMIT),
+            },
+        )?;
+        let replace = options
+            .replace
+            .as_ref()
+            .map(|template| Template::new(template, &program.names));
+        let format = options
+            .format
+            .as_ref()
+            .map(|template| Format::new(template, &program.names));
+        Ok(Self {
+            options,
+            fast_engine,
+            program,
+            replace,
+            format,
+            link_path: None,
+            patterns: Vec::new(),
+            pattern_set: Program::new_set(&[]),
+            stats: Stats::default(),
+            heading_printed: Cell::new(false),
+            any_heading_printed: Cell::new(false),
+        })
+    }
+
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.stats;
+        match &self.fast_engine {
+            Some(FastEngine::Literal(horspool)) => stats.merge(horspool.stats()),
+            Some(FastEngine::Literals(aho_corasick)) => stats.merge(aho_corasick.stats()),
+            Some(FastEngine::ShiftOr(shift_or)) => stats.merge(shift_or.stats()),
+            None => {}
+        }
+        stats
+    }
+
+    /// Adds the counters of a searcher that searched other inputs
+    pub fn merge_stats(&mut self, stats: &Stats) {
+        self.stats.merge(stats);
+    }
+
+    /// Whether the last search printed the name of its file above its lines,
+    /// or a SARIF result
+    pub fn printed_heading(&self) -> bool {
+        self.heading_printed.get()
+    }
+
+    /// Separator written by the caller between the outputs of two searches
+    /// put together after reset_headings, when both printed_heading
+    pub fn heading_separator(&self) -> &'static [u8] {
+        if self.options.output_mode == OutputMode::Sarif {
+            b","
+        } else {
+            b"\n"
+        }
+    }
+
+    /// Gives the patterns whose selected lines are counted separately with
+    /// CountPerPattern, with the text printed for each of them
+    pub fn set_patterns(&mut self, patterns: Vec<(String, Node)>) -> Result<(), RegexError> {
+        let (patterns, nodes): (Vec<_>, Vec<_>) = patterns.into_iter().unzip();
+        let options = CompileOptions {
+            size_limit: Some(DEFAULT_SIZE_LIMIT),
+            ..Default::default()
+        };
+        self.pattern_set = Program::set_with_options(&nodes, options)?;
+        self.patterns = patterns;
+        Ok(())
+    }
+
+    /// Forgets the headings already printed, so that the next one is not
+    /// preceded by an empty line, for outputs put together by the caller
+    pub fn reset_headings(&self) {
+        self.any_heading_printed.set(false);
+    }
+
+    /// Writes the selected lines of the input to out, or what the output mode
+    /// asks for. Returns the number of selected lines
+    pub fn search(
+        &mut self,
+        filename: &str,
+        reader: impl BufRead,
+        out: &mut impl Write,
+    ) -> anyhow::Result<usize> {
+        self.search_from(filename, reader, out, 0, 0)
+    }
+
+    /// Same as search, for an input following nb_lines lines of byte_offset
+    /// bytes already searched, such as the lines appended to a file: the
+    /// positions printed are the ones in the whole file
+    pub fn search_from(
+        &mut self,
+        filename: &str,
+        mut reader: impl BufRead,
+        out: &mut impl Write,
+        nb_lines: usize,
+        byte_offset: usize,
+    ) -> anyhow::Result<usize> {
+        // with -U, the whole input is searched, then its lines are read from
+        // the buffer
+        let mut buffer = Vec::new();
+        let mut matcher = if self.options.multiline {
+            reader.read_to_end(&mut buffer)?;
+            LineMatcher::multiline(&self.program, &buffer, byte_offset)
+        } else {
+            LineMatcher::new(&self.program)
+        };
+        let mut buffered = &buffer[..];
+        let reader: &mut dyn BufRead = if self.options.multiline {
+            &mut buffered
+        } else {
+            &mut reader
+        };
+        self.heading_printed.set(false);
+        self.link_path = self
+            .options
+            .hyperlink
+            .as_ref()
+            .and_then(|_| fs::canonicalize(filename).ok());
+        let mut nb_selected = 0;
+        let mut nb_matches = 0;
+        let mut line = Vec::new();
+        let mut line_number = nb_lines;
+        let mut next_offset = byte_offset;
+        // lines that may be printed as context before the next selected line
+        let mut before: VecDeque<(Position, Vec<u8>)> = VecDeque::new();
+        // with --unique, the distinct matches with the position of their first
+        // occurrence and their number of occurrences, and their index there
+        let mut unique: Vec<(Position, Vec<u8>, usize)> = Vec::new();
+        let mut unique_indexes: HashMap<Vec<u8>, usize> = HashMap::new();
+        let mut pattern_matcher = LineMatcher::new(&self.pattern_set);
+        let mut pattern_counts = vec![0; self.patterns.len()];
+        let mut after_remaining = 0;
+        let mut last_printed = None;
+        // a NUL byte marks a binary file, except with -z where it ends the lines.
+        // As in GNU grep, the beginning of the file is checked before any output
+        let detect_binary =
+            self.options.binary_files != BinaryFiles::Text && !self.options.null_data;
+        let mut is_binary = detect_binary && reader.fill_buf()?.contains(&0);
+        let is_json = self.options.output_mode == OutputMode::Json;
+        // the lines that are not selected are printed as context lines
+        let passthru = self.options.passthru
+            && matches!(
+                self.options.output_mode,
+                OutputMode::Lines | OutputMode::Json
+            );
+        if is_json {
+            let path = json::data(filename.as_bytes());
+            writeln!(out, r#"{{"type":"begin","data":{{"path":{path}}}}}"#)?;
+        }
+
+        loop {
+            let max_reached = self.options.max_count.is_some_and(|max| nb_selected >= max);
+            // the context after the last selected line is still printed
+            if max_reached && after_remaining == 0 {
+                break;
+            }
+            line.clear();
+            if reader.read_until(self.options.line_terminator(), &mut line)? == 0 {
+                break;
+            }
+            line_number += 1;
+            let position = Position {
+                line_number,
+                byte_offset: next_offset,
+                column: None,
+            };
+            next_offset += line.len();
+            matcher.set_line_start(position.byte_offset);
+            is_binary |= detect_binary && line.contains(&0);
+            if is_binary && self.options.binary_files == BinaryFiles::WithoutMatch {
+                break;
+            }
+            let mut content = line
+                .strip_suffix(&[self.options.line_terminator()])
+                .unwrap_or(&line);
+            if self.options.crlf && !self.options.null_data {
+                content = content.strip_suffix(b"\r").unwrap_or(content);
+            }
+
+            if max_reached {
+                let spans = self.highlights(&mut matcher, content, '-');
+                self.write_group_separator(out, last_printed, line_number)?;
+                self.write_line(out, filename, position, content, '-', &spans)?;
+                last_printed = Some(line_number);
+                after_remaining -= 1;
+                continue;
+            }
+
+            let is_match = match &mut self.fast_engine {
+                Some(FastEngine::Literal(horspool)) => horspool.is_match(content),
+                Some(FastEngine::Literals(aho_corasick)) => aho_corasick.is_match(content),
+                Some(FastEngine::ShiftOr(shift_or)) => shift_or.is_match(content),
+                None => matcher.is_match(content),
+            };
+            if is_match != self.options.invert_match {
+                nb_selected += 1;
+                match self.options.output_mode {
+                    OutputMode::Lines if is_binary => {
+                        writeln!(out, "Binary file {filename} matches")?;
+                        break;
+                    }
+                    OutputMode::Json | OutputMode::Github | OutputMode::Sarif if is_binary => break,
+                    OutputMode::Github | OutputMode::Sarif => {
+                        // with -v, the selected lines have no match
+                        let span = if self.options.invert_match {
+                            None
+                        } else {
+                            matcher.find_all(content).first().copied()
+                        };
+                        self.write_annotation(out, filename, line_number, content, span)?;
+                    }
+                    OutputMode::Lines if self.format.is_some() => {
+                        let mut matches = if self.options.invert_match {
+                            Vec::new()
+                        } else {
+                            matcher.captures_all(content)
+                        };
+                        // with -v, the line is printed once, without match
+                        if matches.is_empty() {
+                            matches.push(Vec::new());
+                        }
+                        for slots in matches {
+                            let fields = Fields {
+                                file: filename,
+                                line_number,
+                                line_offset: position.byte_offset,
+                                line: content,
+                                slots: &slots,
+                            };
+                            let mut formatted = Vec::new();
+                            if let Some(format) = &self.format {
+                                format.expand(&fields, &mut formatted);
+                            }
+                            formatted.push(self.options.line_terminator());
+                            out.write_all(&formatted)?;
+                        }
+                        if self.options.line_buffered {
+                            out.flush()?;
+                        }
+                    }
+                    OutputMode::Lines if self.options.only_matching => {
+                        // with -v, the selected lines have no matched part
+                        if !self.options.invert_match {
+                            for slots in matcher.captures_all(content) {
+                                let (Some(start), Some(end)) = (slots[0], slots[1]) else {
+                                    continue;
+                                };
+                                if start < end {
+                                    let position = Position {
+                                        byte_offset: position.byte_offset + start,
+                                        column: self.options.column.then_some(start + 1),
+                                        ..position
+                                    };
+                                    let mut part = content[start..end].to_vec();
+                                    if let Some(template) = &self.replace {
+                                        part.clear();
+                                        template.expand(content, &slots, &mut part);
+                                    }
+                                    if self.options.unique {
+                                        match unique_indexes.entry(part) {
+                                            Entry::Occupied(entry) => unique[*entry.get()].2 += 1,
+                                            Entry::Vacant(entry) => {
+                                                unique.push((position, entry.key().clone(), 1));
+                                                entry.insert(unique.len() - 1);
+                                            }
+                                        }
+                                        continue;
+                                    }
+                                    self.write_line(
+                                        out,
+                                        filename,
+                                        position,
+                                        &part,
+                                        ':',
+                                        &[(0, part.len())],
+                                    )?;
+                                }
+                            }
+                        }
+                    }
+                    OutputMode::Lines | OutputMode::Json => {
+                        let first = before
+                            .front()
+                            .map_or(line_number, |(position, _)| position.line_number);
+                        self.write_group_separator(out, last_printed, first)?;
+                        for (context_position, context) in before.drain(..) {
+                            matcher.set_line_start(context_position.byte_offset);
+                            let spans = self.highlights(&mut matcher, &context, '-');
+                            self.write_line(
+                                out,
+                                filename,
+                                context_position,
+                                &context,
+                                '-',
+                                &spans,
+                            )?;
+                        }
+                        matcher.set_line_start(position.byte_offset);
+                        for column in self.match_columns(&mut matcher, content) {
+                            let position = Position { column, ..position };
+                            match self.replaced(&mut matcher, content) {
+                                Some((replaced, spans)) => self
+                                    .write_line(out, filename, position, &replaced, ':', &spans)?,
+                                None => {
+                                    let spans = self.highlights(&mut matcher, content, ':');
+                                    self.write_line(out, filename, position, content, ':', &spans)?;
+                                }
+                            }
+                        }
+                        last_printed = Some(line_number);
+                        after_remaining = self.options.after_context;
+                    }
+                    OutputMode::Count => {}
+                    OutputMode::CountMatches if self.options.invert_match => nb_matches += 1,
+                    OutputMode::CountMatches => nb_matches += matcher.find_all(content).len(),
+                    OutputMode::CountPerPattern => {
+                        let matched = pattern_matcher.matching_patterns(content);
+                        for (count, matched) in pattern_counts.iter_mut().zip(matched) {
+                            *count += usize::from(matched);
+                        }
+                    }
+                    // the rest of the file does not change the output
+                    OutputMode::FilesWithMatches
+                    | OutputMode::FilesWithoutMatch
+                    | OutputMode::Quiet => break,
+                }
+            } else if (after_remaining > 0 || passthru) && !is_binary {
+                let spans = self.highlights(&mut matcher, content, '-');
+                self.write_line(out, filename, position, content, '-', &spans)?;
+                last_printed = Some(line_number);
+                after_remaining = after_remaining.saturating_sub(1);
+            } else if self.options.before_context > 0 {
+                if before.len() == self.options.before_context {
+                    before.pop_front();
+                }
+                before.push_back((position, content.to_vec()));
+            }
+        }
+
+        for (position, part, count) in unique {
+            let mut line = Vec::new();
+            if self.options.unique_count {
+                write!(line, "{count:>7} ")?;
+            }
+            let start = line.len();
+            line.extend_from_slice(&part);
+            self.write_line(out, filename, position, &line, ':', &[(start, line.len())])?;
+        }
+
+        match self.options.output_mode {
+            OutputMode::FilesWithMatches if nb_selected > 0 => {
+                self.write_filename(out, filename, None, None)?;
+            }
+            OutputMode::FilesWithoutMatch if nb_selected == 0 => {
+                self.write_filename(out, filename, None, None)?;
+            }
+            _ => {}
+        }
+        let count = match self.options.output_mode {
+            OutputMode::Count => Some(nb_selected),
+            OutputMode::CountMatches => Some(nb_matches),
+            _ => None,
+        };
+        if let Some(count) = count {
+            if self.options.with_filename {
+                self.write_filename(out, filename, Some(b":"), None)?;
+            }
+            writeln!(out, "{count}")?;
+        }
+        if self.options.output_mode == OutputMode::CountPerPattern {
+            for (pattern, count) in self.patterns.iter().zip(pattern_counts) {
+                if self.options.with_filename {
+                    self.write_filename(out, filename, Some(b":"), None)?;
+                }
+                writeln!(out, "{count}:{pattern}")?;
+            }
+        }
+
+        let bytes_searched = next_offset - byte_offset;
+        if is_json {
+            let path = json::data(filename.as_bytes());
+            writeln!(
+                out,
+                r#"{{"type":"end","data":{{"path":{path},"stats":{{"matched_lines":{nb_selected},"bytes_searched":{bytes_searched}}}}}}}"#
+            )?;
+        }
+        if self.options.line_buffered {
+            out.flush()?;
+        }
+        self.stats.files_searched += 1;
+        self.stats.files_with_matches += usize::from(nb_selected > 0);
+        self.stats.matched_lines += nb_selected;
+        self.stats.bytes_searched += bytes_searched;
+        self.stats.merge(matcher.stats());
+        Ok(nb_selected)
+    }
+
+    /// Writes the group separator, -- by default, between groups of lines that
+    /// are not contiguous, when context is printed
+    fn write_group_separator(
+        &self,
+        out: &mut impl Write,
+        last_printed: Option<usize>,
+        next_line_number: usize,
+    ) -> anyhow::Result<()> {
+        let has_context = self.options.before_context > 0 || self.options.after_context > 0;
+        let is_json = self.options.output_mode == OutputMode::Json;
+        if has_context
+            && !is_json
+            && !self.options.no_group_separator
+            && last_printed.is_some_and(|last| last + 1 < next_line_number)
+        {
+            let separator = self.options.group_separator.as_deref().unwrap_or("--");
+            self.paint(out, Field::Separator, separator.as_bytes())?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Parts of the line to highlight: the matches in the lines that match,
+    /// which are the context lines with -v. The JSON events report the matches
+    /// of every line
+    fn highlights(
+        &self,
+        matcher: &mut LineMatcher,
+        content: &[u8],
+        separator: char,
+    ) -> Vec<(usize, usize)> {
+        let is_highlighted =
+            self.options.colors.is_some() && (separator == ':') != self.options.invert_match;
+        // the preview of a long line shows its first match
+        let is_previewed = self.options.max_columns_preview
+            && self
+                .options
+                .max_columns
+                .is_some_and(|max| content.len() > max);
+        if !is_highlighted && !is_previewed && self.options.output_mode != OutputMode::Json {
+            return Vec::new();
+        }
+        let mut spans = matcher.find_all(content);
+        spans.retain(|(start, end)| start < end);
+        spans
+    }
+
+    /// Columns printed for a selected line, which is printed once per column:
+    /// the 1-based column of its first match with --column, of each of its
+    /// matches with --vimgrep. With -v, the selected lines have no match
+    fn match_columns(&self, matcher: &mut LineMatcher, content: &[u8]) -> Vec<Option<usize>> {
+        if !self.options.column || self.options.invert_match {
+            return vec![None];
+        }
+        let mut columns = matcher
+            .find_all(content)
+            .into_iter()
+            .map(|(start, _)| Some(start + 1))
+            .collect::<Vec<_>>();
+        if !self.options.vimgrep {
+            columns.truncate(1);
+        }
+        columns
+    }
+
+    /// Selected line with its matches replaced by the template, and the spans
+    /// of the replacements. None without --replace, and with -v where the
+    /// selected lines have no match
+    fn replaced(&self, matcher: &mut LineMatcher, content: &[u8]) -> Option<(Vec<u8>, Spans)> {
+        let template = self.replace.as_ref()?;
+        if self.options.invert_match || self.options.output_mode == OutputMode::Json {
+            return None;
+        }
+        let mut replaced = Vec::with_capacity(content.len());
+        let mut spans = Vec::new();
+        let mut written = 0;
+        for slots in matcher.captures_all(content) {
+            let (Some(start), Some(end)) = (slots[0], slots[1]) else {
+                continue;
+            };
+            replaced.extend_from_slice(&content[written..start]);
+            let replacement_start = replaced.len();
+            template.expand(content, &slots, &mut replaced);
+            if replacement_start < replaced.len() {
+                spans.push((replacement_start, replaced.len()));
+            }
+            written = end;
+        }
+        replaced.extend_from_slice(&content[written..]);
+        if self.options.colors.is_none() {
+            spans.clear();
+        }
+        Some((replaced, spans))
+    }
+
+    /// Writes the text with the color of the field, if the output is colored
+    fn paint(&self, out: &mut impl Write, field: Field, text: &[u8]) -> anyhow::Result<()> {
+        let Some(colors) = &self.options.colors else {
+            out.write_all(text)?;
+            return Ok(());
+        };
+        let color = match field {
+            Field::Filename => &colors.filename,
+            Field::LineNumber => &colors.line_number,
+            Field::Separator => &colors.separator,
+            Field::SelectedMatch => &colors.selected_match,
+            Field::ContextMatch => &colors.context_match,
+            Field::SelectedLine => &colors.selected_line,
+            Field::ContextLine => &colors.context_line,
+            Field::ByteOffset => &colors.byte_offset,
+        };
+        colors.paint(out, color, text)?;
+        Ok(())
+    }
+
+    /// Writes the name of the file followed by the separator, or by a newline
+    /// when there is none. With --null, it is followed by a NUL byte instead.
+    /// With --hyperlink-format, the name links to the file at the position
+    fn write_filename(
+        &self,
+        out: &mut impl Write,
+        filename: &str,
+        separator: Option<&[u8]>,
+        position: Option<Position>,
+    ) -> anyhow::Result<()> {
+        let link = self.options.hyperlink.as_ref().zip(self.link_path.as_ref());
+        if let Some((hyperlink, path)) = link {
+            let line = position.map_or(1, |position| position.line_number);
+            let column = position.and_then(|position| position.column).unwrap_or(1);
+            out.write_all(hyperlink::start(&hyperlink.url(path, line, column)).as_bytes())?;
+        }
+        self.paint(out, Field::Filename, filename.as_bytes())?;
+        if link.is_some() {
+            out.write_all(hyperlink::END)?;
+        }
+        match separator {
+            _ if self.options.null => out.write_all(b"\0")?,
+            Some(separator) => self.paint(out, Field::Separator, separator)?,
+            None => writeln!(out)?,
+        }
+        Ok(())
+    }
+
+    /// Writes a line of the input with its prefix. The separator after the
+    /// prefix is ':' for selected lines and '-' for context lines.
+    /// The spans are the parts of the content to highlight
+    fn write_line(
+        &self,
+        out: &mut impl Write,
+        filename: &str,
+        position: Position,
+        content: &[u8],
+        separator: char,
+        spans: &[(usize, usize)],
+    ) -> anyhow::Result<()> {
+        if self.options.output_mode == OutputMode::Json {
+            return self.write_json_line(out, filename, position, content, separator, spans);
+        }
+        let separator_bytes = [separator as u8];
+        let heading = self.options.heading && self.options.with_filename;
+        if heading && !self.heading_printed.replace(true) {
+            if self.any_heading_printed.replace(true) {
+                writeln!(out)?;
+            }
+            self.write_filename(out, filename, None, None)?;
+        }
+        let mut has_prefix = false;
+        if self.options.with_filename && !heading {
+            self.write_filename(out, filename, Some(&separator_bytes), Some(position))?;
+            has_prefix = true;
+        }
+        // the numbers are aligned with --initial-tab
+        let (number_width, offset_width) = if self.options.initial_tab {
+            (NUMBER_WIDTH, OFFSET_WIDTH)
+        } else {
+            (0, 0)
+        };
+        let numbers = [
+            (
+                Field::LineNumber,
+                self.options.line_number.then_some(position.line_number),
+                number_width,
+            ),
+            (Field::LineNumber, position.column, number_width),
+            (
+                Field::ByteOffset,
+                self.options.byte_offset.then_some(position.byte_offset),
+                offset_width,
+            ),
+        ];
+        for (field, number, width) in numbers {
+            if let Some(number) = number {
+                let number = format!("{number:>width$}");
+                self.paint(out, field, number.as_bytes())?;
+                self.paint(out, Field::Separator, &separator_bytes)?;
+                has_prefix = true;
+            }
+        }
+        if self.options.initial_tab && has_prefix {
+            out.write_all(b"\t")?;
+        }
+
+        let match_field = if separator == ':' {
+            Field::SelectedMatch
+        } else {
+            Field::ContextMatch
+        };
+        let reversed = self.options.invert_match
+            && self
+                .options
+                .colors
+                .as_ref()
+                .is_some_and(|colors| colors.reverse);
+        let line_field = if (separator == ':') != reversed {
+            Field::SelectedLine
+        } else {
+            Field::ContextLine
+        };
+        let line_len = content.len();
+        let preview = self.preview(content, spans);
+        let (content, spans) = match &preview {
+            Some(range) if range.is_empty() => {
+                out.write_all(OMITTED)?;
+                (&[][..], Vec::new())
+            }
+            Some(range) => {
+                if range.start > 0 {
+                    out.write_all(OMITTED)?;
+                    out.write_all(b" ")?;
+                }
+                let spans = spans
+                    .iter()
+                    .map(|&(start, end)| (start.max(range.start), end.min(range.end)))
+                    .filter(|(start, end)| start < end)
+                    .map(|(start, end)| (start - range.start, end - range.start))
+                    .collect();
+                (&content[range.clone()], spans)
+            }
+            None => (content, spans.to_vec()),
+        };
+        // the text around the matches has the color of the line
+        let mut written = 0;
+        for (start, end) in spans.into_iter().chain([(content.len(), content.len())]) {
+            if written < start {
+                self.paint(out, line_field, &content[written..start])?;
+            }
+            if start < end {
+                self.paint(out, match_field, &content[start..end])?;
+            }
+            written = end;
+        }
+        if preview.is_some_and(|range| !range.is_empty() && range.end < line_len) {
+            out.write_all(b" ")?;
+            out.write_all(OMITTED)?;
+        }
+        out.write_all(&[self.options.line_terminator()])?;
+        if self.options.line_buffered {
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Part of a line longer than --max-columns that is printed, None to print
+    /// the whole line. It is empty without --max-columns-preview, otherwise it
+    /// is the bytes around the first span
+    fn preview(&self, content: &[u8], spans: &[(usize, usize)]) -> Option<Range<usize>> {
+        let max = self
+            .options
+            .max_columns
+            .filter(|&max| content.len() > max)?;
+        if !self.options.max_columns_preview {
+            return Some(0..0);
+        }
+        let first = spans.first().map_or(0, |&(start, _)| start);
+        let mut start = first.saturating_sub(max / 2).min(content.len() - max);
+        let mut end = start + max;
+        // only whole chars are printed
+        let is_continuation = |byte: u8| byte & 0xc0 == 0x80;
+        while start < end && is_continuation(content[start]) {
+            start += 1;
+        }
+        while end > start && end < content.len() && is_continuation(content[end]) {
+            end -= 1;
+        }
+        Some(start..end)
+    }
+
+    /// Writes the event of a selected line, or of a context line with a '-'
+    /// separator, in the same format as ripgrep
+    /// Writes the selected line as a GitHub Actions annotation or a SARIF
+    /// result, at its match if any
+    fn write_annotation(
+        &self,
+        out: &mut impl Write,
+        filename: &str,
+        line_number: usize,
+        content: &[u8],
+        span: Option<(usize, usize)>,
+    ) -> anyhow::Result<()> {
+        // 1-based, in chars
+        let column = |offset: usize| decode(&content[..offset]).len() + 1;
+        let (start, end) = span.unwrap_or((0, content.len()));
+        let message = String::from_utf8_lossy(content);
+        let annotation = Annotation {
+            file: filename,
+            line: line_number,
+            start_column: column(start),
+            end_column: column(end),
+            message: &message,
+        };
+        if self.options.output_mode == OutputMode::Github {
+            writeln!(out, "{}", annotation.github())?;
+        } else {
+            self.heading_printed.set(true);
+            if self.any_heading_printed.replace(true) {
+                out.write_all(self.heading_separator())?;
+            }
+            write!(out, "{}", annotation.sarif())?;
+        }
+        if self.options.line_buffered {
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    fn write_json_line(
+        &self,
+        out: &mut impl Write,
+        filename: &str,
+        position: Position,
+        content: &[u8],
+        separator: char,
+        spans: &[(usize, usize)],
+    ) -> anyhow::Result<()> {
+        let kind = if separator == ':' { "match" } else { "context" };
+        let path = json::data(filename.as_bytes());
+        let mut line = content.to_vec();
+        line.push(self.options.line_terminator());
+        let lines = json::data(&line);
+        let submatches = spans
+            .iter()
+            .map(|&(start, end)| {
+                let text = json::data(&content[start..end]);
+                format!(r#"{{"match":{text},"start":{start},"end":{end}}}"#)
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(
+            out,
+            r#"{{"type":"{kind}","data":{{"path":{path},"lines":{lines},"line_number":{},"absolute_offset":{},"submatches":[{submatches}]}}}}"#,
+            position.line_number, position.byte_offset
+        )?;
+        if self.options.line_buffered {
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Chars of the line, invalid UTF-8 sequences are read as U+FFFD
+fn decode(bytes: &[u8]) -> Vec<char> {
+    String::from_utf8_lossy(bytes).chars().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use grep_starter_rust::regex_lexer::{Dialect, RegexLexer};
+    use grep_starter_rust::regex_parser::RegexParser;
+    use rstest::rstest;
+
+    use super::*;
+
+    /// Output of the search and the number of selected lines
+    fn search(pat: &str, input: &str, options: SearchOptions) -> anyhow::Result<(String, usize)> {
+        let lexer = RegexLexer::new(pat);
+        let mut parser = RegexParser::new(lexer)?;
+        let node = parser.build_ast(0)?;
+        let mut searcher = Searcher::new(&node, options)?;
+
+        let mut out = Vec::new();
+        let nb_selected = searcher.search("file", input.as_bytes(), &mut out)?;
+        Ok((String::from_utf8(out)?, nb_selected))
+    }
+
+    #[rstest]
+    #[case("ab", "xab\nba\nab", "xab\nab\n", 2)]
+    #[case("^a\\d$", "a1\na12\n a1\na2\n", "a1\na2\n", 2)]
+    #[case("b+|c", "aaa\nbb\n\nc", "bb\nc\n", 2)]
+    #[case("^$", "a\n\nb\n", "\n", 1)]
+    #[case("z", "a\nb\n", "", 0)]
+    #[case("a", "", "", 0)]
+    fn test_search(
+        #[case] pat: &str,
+        #[case] input: &str,
+        #[case] expected: &str,
+        #[case] expected_selected: usize,
+    ) -> anyhow::Result<()> {
+        let result = search(pat, input, SearchOptions::default())?;
+        assert_eq!(result, (expected.to_string(), expected_selected));
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("ab", "xab\nba\nab", "ba\n", 1)]
+    #[case("a|b", "a\nb\n", "", 0)]
+    #[case("^$", "a\n\nb", "a\nb\n", 2)]
+    fn test_search_invert_match(
+        #[case] pat: &str,
+        #[case] input: &str,
+        #[case] expected: &str,
+        #[case] expected_selected: usize,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            invert_match: true,
+            ..Default::default()
+        };
+        let result = search(pat, input, options)?;
+        assert_eq!(result, (expected.to_string(), expected_selected));
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false, 2)]
+    #[case(true, 1)]
+    fn test_search_count(
+        #[case] invert_match: bool,
+        #[case] expected: usize,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            invert_match,
+            output_mode: OutputMode::Count,
+            ..Default::default()
+        };
+        let result = search("a", "ab\nb\nba\n", options)?;
+        assert_eq!(result, (format!("{expected}\n"), expected));
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false, "4\n")]
+    #[case(true, "1\n")]
+    fn test_search_count_matches(
+        #[case] invert_match: bool,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            invert_match,
+            output_mode: OutputMode::CountMatches,
+            ..Default::default()
+        };
+        let (output, _) = search("a", "aba\nb\nbaa\n", options)?;
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(
+        false,
+        "\x1b[01;31m\x1b[Ka\x1b[m\x1b[Kb\nb\nb\x1b[01;31m\x1b[Ka\x1b[m\x1b[K\n",
+        2
+    )]
+    #[case(
+        true,
+        "\x1b[01;31m\x1b[Ka\x1b[m\x1b[Kb\nb\nb\x1b[01;31m\x1b[Ka\x1b[m\x1b[K\n",
+        1
+    )]
+    fn test_search_passthru(
+        #[case] invert_match: bool,
+        #[case] expected: &str,
+        #[case] expected_selected: usize,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            invert_match,
+            passthru: true,
+            colors: Some(Colors::default()),
+            ..Default::default()
+        };
+        let result = search("a", "ab\nb\nba\n", options)?;
+        assert_eq!(result, (expected.to_string(), expected_selected));
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false, "x\n[... omitted]\n")]
+    #[case(true, "x\n[... omitted] 6789a0123 [... omitted]\n")]
+    fn test_search_max_columns(
+        #[case] max_columns_preview: bool,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            max_columns: Some(9),
+            max_columns_preview,
+            ..Default::default()
+        };
+        let (result, _) = search("a|x", "x\n0123456789a0123456789\n", options)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false, false, "ab\nca\n")]
+    #[case(true, false, "file:   1:\tab\nfile:   3:\tca\n")]
+    #[case(false, true, "        0:\tab\n        5:\tca\n")]
+    fn test_search_initial_tab(
+        #[case] line_number: bool,
+        #[case] byte_offset: bool,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            initial_tab: true,
+            with_filename: line_number,
+            line_number,
+            byte_offset,
+            ..Default::default()
+        };
+        let (result, _) = search("a", "ab\nb\nca\n", options)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(OutputMode::Lines, "file\0ab\nfile\0ca\n")]
+    #[case(OutputMode::Count, "file\x002\n")]
+    #[case(OutputMode::FilesWithMatches, "file\0")]
+    fn test_search_null(
+        #[case] output_mode: OutputMode,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            output_mode,
+            with_filename: true,
+            null: true,
+            ..Default::default()
+        };
+        let (result, _) = search("a", "ab\nb\nca\n", options)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("a$", "ba\r\nab\r\n", "ba\n")]
+    #[case("\r", "a\r\nb\rc\n", "b\rc\n")]
+    #[case("^a$", "a\r", "a\n")]
+    fn test_search_crlf(
+        #[case] pat: &str,
+        #[case] input: &str,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            crlf: true,
+            ..Default::default()
+        };
+        let (result, _) = search(pat, input, options)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("b\\nc", false, "a\nb\nc\nd\n", "b\nc\n")]
+    #[case("b\\nc", true, "a\nb\nc\nd\n", "b\nc\n")]
+    #[case("^c$", false, "a\nb\nc\nd\n", "c\n")]
+    #[case("a.*", true, "ab\ncd\n", "ab\n")]
+    #[case("b$\\n^c", false, "ab\ncd\nbc\n", "ab\ncd\n")]
+    fn test_search_multiline(
+        #[case] pat: &str,
+        #[case] only_matching: bool,
+        #[case] input: &str,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            multiline: true,
+            only_matching,
+            ..Default::default()
+        };
+        let (result, _) = search(pat, input, options)?;
+        assert_eq!(result, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false, "1:ab\n3:ba\n")]
+    #[case(true, "2:b\n")]
+    fn test_search_line_number(
+        #[case] invert_match: bool,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            invert_match,
+            line_number: true,
+            ..Default::default()
+        };
+        let (output, _) = search("a", "ab\nb\nba\n", options)?;
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(OutputMode::Lines, "file:ab\nfile:ba\n")]
+    #[case(OutputMode::Count, "file:2\n")]
+    fn test_search_with_filename(
+        #[case] output_mode: OutputMode,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            output_mode,
+            with_filename: true,
+            ..Default::default()
+        };
+        let (output, _) = search("a", "ab\nb\nba\n", options)?;
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(OutputMode::FilesWithMatches, "a", "file\n", 1)]
+    #[case(OutputMode::FilesWithMatches, "c", "", 0)]
+    #[case(OutputMode::FilesWithoutMatch, "a", "", 1)]
+    #[case(OutputMode::FilesWithoutMatch, "c", "file\n", 0)]
+    #[case(OutputMode::Quiet, "a", "", 1)]
+    #[case(OutputMode::Quiet, "c", "", 0)]
+    fn test_search_files(
+        #[case] output_mode: OutputMode,
+        #[case] pat: &str,
+        #[case] expected: &str,
+        #[case] expected_selected: usize,
+    ) -> anyhow::Result<()> {
+        let input = "ab\nb\nba\n";
+        let options = SearchOptions {
+            output_mode,
+            ..Default::default()
+        };
+        let result = search(pat, input, options)?;
+        // the search stops at the first selected line
+        assert_eq!(result, (expected.to_string(), expected_selected));
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("a+", "baaca\nb\nxa\n", false, "1:aa\n1:a\n3:a\n")]
+    #[case("b*", "abba\n", false, "1:bb\n")]
+    #[case("a", "ab\nb\n", true, "")]
+    fn test_search_only_matching(
+        #[case] pat: &str,
+        #[case] input: &str,
+        #[case] invert_match: bool,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            invert_match,
+            only_matching: true,
+            line_number: true,
+            ..Default::default()
+        };
+        let (output, _) = search(pat, input, options)?;
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(MatchKind::LeftmostFirst, "a\nc\n")]
+    #[case(MatchKind::LeftmostLongest, "ab\nc\n")]
+    fn test_search_only_matching_match_kind(
+        #[case] match_kind: MatchKind,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            only_matching: true,
+            match_kind,
+            ..Default::default()
+        };
+        let (output, _) = search("a|ab|c", "abc\n", options)?;
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false, false, "2:a\n1:b\n0:c\n")]
+    #[case(true, false, "file:2:a\nfile:1:b\nfile:0:c\n")]
+    #[case(false, true, "0:a\n0:b\n0:c\n")]
+    fn test_search_count_per_pattern(
+        #[case] with_filename: bool,
+        #[case] invert_match: bool,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let patterns = ["a", "b", "c"]
+            .iter()
+            .map(|pattern| {
+                let node = RegexParser::new(RegexLexer::new(pattern))?.build_ast(0)?;
+                Ok((pattern.to_string(), node))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let node = RegexParser::new(RegexLexer::new("a|b|c"))?.build_ast(0)?;
+        let options = SearchOptions {
+            output_mode: OutputMode::CountPerPattern,
+            invert_match,
+            with_filename,
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(&node, options)?;
+        searcher.set_patterns(patterns)?;
+
+        let mut out = Vec::new();
+        searcher.search("file", &b"ab\nxa\nd\n"[..], &mut out)?;
+        assert_eq!(String::from_utf8(out)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_hyperlink() -> anyhow::Result<()> {
+        let node = RegexParser::new(RegexLexer::new("a"))?.build_ast(0)?;
+        let options = SearchOptions {
+            with_filename: true,
+            hyperlink: HyperlinkFormat::new("x://{path}:{line}")?,
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(&node, options)?;
+
+        let mut out = Vec::new();
+        searcher.search("Cargo.toml", &b"b\na\n"[..], &mut out)?;
+        searcher.search("(standard input)", &b"a\n"[..], &mut out)?;
+        let path = fs::canonicalize("Cargo.toml")?;
+        let expected = format!(
+            "\x1b]8;;x://{}:2\x1b\\Cargo.toml\x1b]8;;\x1b\\:a\n(standard input):a\n",
+            path.display()
+        );
+        assert_eq!(String::from_utf8(out)?, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("(a)(b)?", false, "file:1:2 xaba b\nfile:1:4 xaba \nfile:3:1 a \n")]
+    #[case("a", true, "file:2:1 c \n")]
+    fn test_search_format(
+        #[case] pat: &str,
+        #[case] invert_match: bool,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            invert_match,
+            format: Some("{file}:{line}:{column} {text} {2}".to_string()),
+            ..Default::default()
+        };
+        let (output, _) = search(pat, "xaba\nc\na\n", options)?;
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false, "::warning file=file,line=1,col=3,endColumn=5::éxab\n")]
+    #[case(true, "::warning file=file,line=2,col=1,endColumn=2::c\n")]
+    fn test_search_github(
+        #[case] invert_match: bool,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            invert_match,
+            output_mode: OutputMode::Github,
+            ..Default::default()
+        };
+        let (output, _) = search("ab", "éxab\nc\n", options)?;
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_sarif() -> anyhow::Result<()> {
+        let options = SearchOptions {
+            output_mode: OutputMode::Sarif,
+            ..Default::default()
+        };
+        let (output, _) = search("a", "a\nb\nba\n", options)?;
+        let result = |line: usize, column: usize| {
+            r#"{"level":"warning","message":{"text":"TEXT"},"locations":[{"physicalLocation":{"artifactLocation":{"uri":"file"},"region":{"startLine":LINE,"startColumn":COLUMN,"endColumn":END}}}]}"#
+                .replace("LINE", &line.to_string())
+                .replace("COLUMN", &column.to_string())
+                .replace("END", &(column + 1).to_string())
+        };
+        let expected = format!(
+            "{},{}",
+            result(1, 1).replace("TEXT", "a"),
+            result(3, 2).replace("TEXT", "ba")
+        );
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false, "ab\nba\nab\nab\n", "1:ab\n2:ba\n")]
+    #[case(true, "ab\nba\nab\nab\n", "1:      3 ab\n2:      1 ba\n")]
+    #[case(false, "cd\n", "")]
+    fn test_search_unique(
+        #[case] unique_count: bool,
+        #[case] input: &str,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            only_matching: true,
+            unique: true,
+            unique_count,
+            line_number: true,
+            ..Default::default()
+        };
+        let (output, _) = search("ab|ba", input, options)?;
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false, 2, "ab\nba\n", 2)]
+    #[case(false, 0, "", 0)]
+    #[case(true, 1, "b\n", 1)]
+    #[case(false, 5, "ab\nba\naa\n", 3)]
+    fn test_search_max_count(
+        #[case] invert_match: bool,
+        #[case] max_count: usize,
+        #[case] expected: &str,
+        #[case] expected_selected: usize,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            invert_match,
+            max_count: Some(max_count),
+            ..Default::default()
+        };
+        let result = search("a", "ab\nb\nba\naa\nc\n", options)?;
+        assert_eq!(result, (expected.to_string(), expected_selected));
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(1, 0, "2-b\n3:a\n--\n6-f\n7:a\n")]
+    #[case(0, 1, "3:a\n4-d\n--\n7:a\n8-h\n")]
+    #[case(2, 2, "1-x\n2-b\n3:a\n4-d\n5-e\n6-f\n7:a\n8-h\n")]
+    fn test_search_context(
+        #[case] before_context: usize,
+        #[case] after_context: usize,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            before_context,
+            after_context,
+            line_number: true,
+            ..Default::default()
+        };
+        let (output, _) = search("a", "x\nb\na\nd\ne\nf\na\nh\n", options)?;
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(None, false, "a\nb\n--\na\n")]
+    #[case(Some("=="), false, "a\nb\n==\na\n")]
+    #[case(Some(""), false, "a\nb\n\na\n")]
+    #[case(None, true, "a\nb\na\n")]
+    fn test_search_group_separator(
+        #[case] group_separator: Option<&str>,
+        #[case] no_group_separator: bool,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            after_context: 1,
+            group_separator: group_separator.map(str::to_string),
+            no_group_separator,
+            ..Default::default()
+        };
+        let (output, _) = search("a", "a\nb\nc\na\n", options)?;
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_context_max_count() -> anyhow::Result<()> {
+        let options = SearchOptions {
+            after_context: 2,
+            max_count: Some(1),
+            ..Default::default()
+        };
+        let (output, nb_selected) = search("a", "a\nb\na\nd\n", options)?;
+        assert_eq!((output.as_str(), nb_selected), ("a\nb\na\n", 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_colors() -> anyhow::Result<()> {
+        let options = SearchOptions {
+            with_filename: true,
+            after_context: 1,
+            colors: Some(Colors::parse("ms=1:mc=2:fn=3:ln=:se=:ne")),
+            ..Default::default()
+        };
+        let (output, _) = search("b", "abcb\nd\n", options)?;
+        assert_eq!(
+            output,
+            "\x1b[3mfile\x1b[m:a\x1b[1mb\x1b[mc\x1b[1mb\x1b[m\n\x1b[3mfile\x1b[m-d\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_colors_invert_match() -> anyhow::Result<()> {
+        let options = SearchOptions {
+            invert_match: true,
+            before_context: 1,
+            colors: Some(Colors::parse("ms=1:mc=2:ne")),
+            ..Default::default()
+        };
+        let (output, _) = search("b", "b\nd\n", options)?;
+        assert_eq!(output, "\x1b[2mb\x1b[m\nd\n");
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false, "\x1b[3ma\x1b[m\x1b[1mb\x1b[m\x1b[3mc\x1b[m\n\x1b[4md\x1b[m\n")]
+    #[case(true, "\x1b[3ma\x1b[m\x1b[2mb\x1b[m\x1b[3mc\x1b[m\n\x1b[4md\x1b[m\n")]
+    fn test_search_colors_lines(
+        #[case] invert_match: bool,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        // with -v, abc is a context line and d is selected, but rv keeps the
+        // color of sl for the line that matches
+        let options = SearchOptions {
+            invert_match,
+            before_context: 1,
+            after_context: 1,
+            colors: Some(Colors::parse("ms=1:mc=2:sl=3:cx=4:rv:ne")),
+            ..Default::default()
+        };
+        let (output, _) = search("b", "abc\nd\n", options)?;
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(
+        "foo",
+        "foo\nfoobar\na foo.\n_foo\nfoo_ foo\n",
+        "foo\na foo.\nfoo_ foo\n"
+    )]
+    #[case("fo*", "foo\nfoobar\n", "foo\n")]
+    #[case("été|b", "un été\nétés\n", "un été\n")]
+    fn test_search_word_regexp(
+        #[case] pat: &str,
+        #[case] input: &str,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let lexer = RegexLexer::new(pat);
+        let mut parser = RegexParser::new(lexer)?;
+        let node = parser.build_ast(0)?.word_bounded();
+        let mut searcher = Searcher::new(&node, SearchOptions::default())?;
+
+        let mut out = Vec::new();
+        searcher.search("file", input.as_bytes(), &mut out)?;
+        assert_eq!(String::from_utf8(out)?, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("foo", "foo\nfoobar\n foo\n", "foo\n")]
+    #[case("a|ab", "ab\na\nb\n", "ab\na\n")]
+    #[case("a\\d", "a1\na12\n", "a1\n")]
+    fn test_search_line_regexp(
+        #[case] pat: &str,
+        #[case] input: &str,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let lexer = RegexLexer::new(pat);
+        let mut parser = RegexParser::new(lexer)?;
+        let node = parser.build_ast(0)?.line_bounded();
+        let mut searcher = Searcher::new(&node, SearchOptions::default())?;
+
+        let mut out = Vec::new();
+        searcher.search("file", input.as_bytes(), &mut out)?;
+        assert_eq!(String::from_utf8(out)?, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(&["a.c", "x*"], false, "a.c\nx*y\n")]
+    #[case(&["A.C"], true, "a.c\n")]
+    #[case(&["b", ""], false, "abc\na.c\nx*y\nxyz\n")]
+    fn test_search_fixed_strings(
+        #[case] patterns: &[&str],
+        #[case] ignore_case: bool,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let node = Node::fixed_strings(patterns, ignore_case);
+        let mut searcher = Searcher::new(&node, SearchOptions::default())?;
+
+        let mut out = Vec::new();
+        searcher.search("file", "abc\na.c\nx*y\nxyz\n".as_bytes(), &mut out)?;
+        assert_eq!(String::from_utf8(out)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_without_patterns() -> anyhow::Result<()> {
+        let node = Node::alternatives(Vec::new());
+        let mut searcher = Searcher::new(&node, SearchOptions::default())?;
+
+        let mut out = Vec::new();
+        let nb_selected = searcher.search("file", "a\n\nb\n".as_bytes(), &mut out)?;
+        assert_eq!((out, nb_selected), (Vec::new(), 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_size_limit() -> anyhow::Result<()> {
+        let node = RegexParser::new(RegexLexer::new("a{1000}{1000}{1000}"))?.build_ast(0)?;
+        assert!(matches!(
+            Searcher::new(&node, SearchOptions::default()),
+            Err(RegexError::SizeLimitExceeded { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_null_data() -> anyhow::Result<()> {
+        let options = SearchOptions {
+            null_data: true,
+            ..Default::default()
+        };
+        let (output, _) = search("^a", "ab\nb\0b\nab\0a", options)?;
+        assert_eq!(output, "ab\nb\0a\0");
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(
+        BinaryFiles::Binary,
+        OutputMode::Lines,
+        "Binary file file matches\n",
+        1
+    )]
+    #[case(BinaryFiles::Binary, OutputMode::Count, "2\n", 2)]
+    #[case(BinaryFiles::Text, OutputMode::Lines, "ab\0\nab\n", 2)]
+    #[case(BinaryFiles::WithoutMatch, OutputMode::Lines, "", 0)]
+    #[case(BinaryFiles::WithoutMatch, OutputMode::FilesWithoutMatch, "file\n", 0)]
+    fn test_search_binary_files(
+        #[case] binary_files: BinaryFiles,
+        #[case] output_mode: OutputMode,
+        #[case] expected: &str,
+        #[case] expected_selected: usize,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            binary_files,
+            output_mode,
+            ..Default::default()
+        };
+        let result = search("a", "ab\0\nb\nab\n", options)?;
+        assert_eq!(result, (expected.to_string(), expected_selected));
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false, 0, "1:0:ab\n2:3:cab\n")]
+    #[case(true, 0, "1:0:a\n2:4:a\n")]
+    #[case(false, 1, "1:0:ab\n2:3:cab\n3-7-d\n")]
+    fn test_search_byte_offset(
+        #[case] only_matching: bool,
+        #[case] after_context: usize,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            byte_offset: true,
+            line_number: true,
+            only_matching,
+            after_context,
+            ..Default::default()
+        };
+        let (output, _) = search("a", "ab\ncab\nd\n", options)?;
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false, "1:2:ab\n2:1:b b\n")]
+    #[case(true, "1:2:b\n2:1:b\n2:3:b\n")]
+    fn test_search_column(
+        #[case] only_matching: bool,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let options = SearchOptions {
+            column: true,
+            line_number: true,
+            only_matching,
+            ..Default::default()
+        };
+        let (output, _) = search("b", "ab\nb b\nc\n", options)?;
+        assert_eq!(output, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_vimgrep() -> anyhow::Result<()> {
+        let options = SearchOptions {
+            column: true,
+            vimgrep: true,
+            line_number: true,
+            with_filename: true,
+            ..Default::default()
+        };
+        let (output, _) = search("b", "ab b\nc\n", options)?;
+        assert_eq!(output, "file:1:2:ab b\nfile:1:4:ab b\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_heading() -> anyhow::Result<()> {
+        let node = RegexParser::new(RegexLexer::new("a"))?.build_ast(0)?;
+        let options = SearchOptions {
+            with_filename: true,
+            heading: true,
+            line_number: true,
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(&node, options)?;
+        let mut out = Vec::new();
+        for (filename, input) in [("f1", "a\nb\na\n"), ("f2", "b\n"), ("f3", "ba\n")] {
+            searcher.search(filename, input.as_bytes(), &mut out)?;
+        }
+        assert_eq!(String::from_utf8(out)?, "f1\n1:a\n3:a\n\nf3\n1:ba\n");
+        Ok(())
+    }
+
+    /// Output that records what was written at each flush
+    #[derive(Default)]
+    struct FlushRecorder {
+        pending: Vec<u8>,
+        flushed: Vec<String>,
+    }
+
+    impl Write for FlushRecorder {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.pending.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            let pending = std::mem::take(&mut self.pending);
+            self.flushed
+                .push(String::from_utf8_lossy(&pending).into_owned());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_search_line_buffered() -> anyhow::Result<()> {
+        let node = RegexParser::new(RegexLexer::new("a"))?.build_ast(0)?;
+        let options = SearchOptions {
+            line_buffered: true,
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(&node, options)?;
+        let mut out = FlushRecorder::default();
+        searcher.search("file", "ab\nb\nca\n".as_bytes(), &mut out)?;
+        assert_eq!(out.flushed, ["ab\n", "ca\n", ""]);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("a")]
+    #[case("a|\u{e9}")]
+    #[case("a.c")]
+    #[case("a.*c")]
+    fn test_search_invalid_utf8(#[case] pat: &str) -> anyhow::Result<()> {
+        let node = RegexParser::new(RegexLexer::new(pat))?.build_ast(0)?;
+        let options = SearchOptions {
+            binary_files: BinaryFiles::Text,
+            only_matching: true,
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(&node, options)?;
+        let mut out = Vec::new();
+        let input = b"\xff\0a\xc3c\n\xe9\n";
+        assert_eq!(searcher.search("file", &input[..], &mut out)?, 1);
+        assert!(out.starts_with(b"a"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_json() -> anyhow::Result<()> {
+        let options = SearchOptions {
+            output_mode: OutputMode::Json,
+            before_context: 1,
+            ..Default::default()
+        };
+        let (output, _) = search("b", "a\"\nab b\n", options)?;
+        let expected = [
+            r#"{"type":"begin","data":{"path":{"text":"file"}}}"#,
+            r#"{"type":"context","data":{"path":{"text":"file"},"lines":{"text":"a\"\n"},"line_number":1,"absolute_offset":0,"submatches":[]}}"#,
+            r#"{"type":"match","data":{"path":{"text":"file"},"lines":{"text":"ab b\n"},"line_number":2,"absolute_offset":3,"submatches":[{"match":{"text":"b"},"start":1,"end":2},{"match":{"text":"b"},"start":3,"end":4}]}}"#,
+            r#"{"type":"end","data":{"path":{"text":"file"},"stats":{"matched_lines":1,"bytes_searched":8}}}"#,
+        ];
+        assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("(\\w+)@(?<host>\\w+)", "${host}/$1", false, "b: c/a. x/y\n")]
+    #[case("(\\w+)@(?<host>\\w+)", "${host}/$1", true, "c/a\nx/y\n")]
+    #[case("@", "$0$0", false, "b: a@@c. y@@x\n")]
+    fn test_search_replace(
+        #[case] pat: &str,
+        #[case] template: &str,
+        #[case] only_matching: bool,
+        #[case] expected: &str,
+    ) -> anyhow::Result<()> {
+        let lexer = RegexLexer::with_dialect(pat, Dialect::Perl);
+        let node = RegexParser::new(lexer)?.build_ast(0)?;
+        let options = SearchOptions {
+            only_matching,
+            replace: Some(template.to_string()),
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(&node, options)?;
+        let mut out = Vec::new();
+        searcher.search("file", "b: a@c. y@x\nd\n".as_bytes(), &mut out)?;
+        assert_eq!(String::from_utf8(out)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_stats() -> anyhow::Result<()> {
+        let node = RegexParser::new(RegexLexer::new("a"))?.build_ast(0)?;
+        let mut searcher = Searcher::new(&node, SearchOptions::default())?;
+        let mut out = Vec::new();
+        searcher.search("file1", "ab\nb\nca\n".as_bytes(), &mut out)?;
+        searcher.search("file2", "b\n".as_bytes(), &mut out)?;
+
+        let stats = searcher.stats();
+        assert_eq!(stats.files_searched, 2);
+        assert_eq!(stats.files_with_matches, 1);
+        assert_eq!(stats.matched_lines, 2);
+        assert_eq!(stats.bytes_searched, 10);
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+use crate::char_class::CharClass;
+use crate::regex_parser::Node;
+use crate::stats::Stats;
+use crate::utf8::char_at;
+
+/// Maximum number of positions a pattern can have to fit in the state word
+const MAX_POSITIONS: usize = u64::BITS as usize;
+
+/// Bit-parallel matcher (Shift-Or / Bitap) for short patterns.
+/// Each position of the pattern matches exactly one char, so the whole set of
+/// partial matches fits in a single u64 where bit i is 0 if the first i+1
+/// positions match the chars just read.
+/// Only patterns made of single char nodes and fixed repetitions are supported,
+/// everything else goes through the Pike VM.
+#[derive(Debug, Clone)]
+pub struct ShiftOr {
+    /// set of chars accepted by each position in the pattern
+    positions: Vec<CharClass>,
+    /// precomputed masks for ascii chars: bit i is 0 if position i accepts the char
+    ascii_masks: [u64; 128],
+    /// pattern starts with ^
+    start_anchor: bool,
+    /// pattern ends with $
+    end_anchor: bool,
+    stats: Stats,
+}
+
+impl ShiftOr {
+    /// Returns None if the pattern can not be handled by the bit-parallel engine
+    pub fn new(node: &Node) -> Option<Self> {
+        let Node::Group { nodes, .. } = node else {
+            return None;
+        };
+
+        let mut nodes = &nodes[..];
+        let mut start_anchor = false;
+        let mut end_anchor = false;
+        if let [Node::StartAnchor, rest @ ..] = nodes {
+            start_anchor = true;
+            nodes = rest;
+        }
+        if let [rest @ .., Node::EndAnchor] = nodes {
+            end_anchor = true;
+            nodes = rest;
+        }
+
+        let mut positions = Vec::new();
+        for node in nodes {
+            if !Self::collect_positions(node, &mut positions) {
+                return None;
+            }
+        }
+        if positions.is_empty() {
+            return None;
+        }
+
+        let mut ascii_masks = [0; 128];
+        for (c, mask) in ascii_masks.iter_mut().enumerate() {
+            *mask = Self::compute_mask(&positions, c as u8 as char);
+        }
+
+        Some(Self {
+            positions,
+            ascii_masks,
+            start_anchor,
+            end_anchor,
+            stats: Stats::default(),
+        })
+    }
+
+    /// Flattens the node into single char positions.
+    /// Returns false if the node is not supported or if there are too many positions
+    fn collect_positions(node: &Node, positions: &mut Vec<CharClass>) -> bool {
+        match node {
+            Node::Literal(c) => positions.push(CharClass::single(*c)),
+            Node::Digit => positions.push(CharClass::digit()),
+            Node::Alphanum => positions.push(CharClass::alphanum()),
+            Node::Wildcard => positions.push(CharClass::any()),
+            Node::Class(class) => positions.push(class.clone()),
+            Node::Group { nodes, .. } => {
+                for node in nodes {
+                    if !Self::collect_positions(node, positions) {
+                        return false;
+                    }
+                }
+            }
+            Node::Quantifier {
+                node,
+                min,
+                max: Some(max),
+                ..
+            } if min == max => {
+                for _ in 0..*min {
+                    if !Self::collect_positions(node, positions) {
+                        return false;
+                    }
+                }
+            }
+            _ => return false,
+        }
+        positions.len() <= MAX_POSITIONS
+    }
+
+    fn compute_mask(positions: &[CharClass], c: char) -> u64 {
+        let mut mask = !0;
+        for (i, class) in positions.iter().enumerate() {
+            if class.contains(c) {
+                mask &= !(1 << i);
+            }
+        }
+        mask
+    }
+
+    fn mask(&self, c: char) -> u64 {
+        if c.is_ascii() {
+            self.ascii_masks[c as usize]
+        } else {
+            Self::compute_mask(&self.positions, c)
+        }
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Searches the UTF-8 bytes of a line, decoding the chars as they are
+    /// read. Invalid sequences are read as U+FFFD
+    pub fn is_match(&mut self, bytes: &[u8]) -> bool {
+        let match_bit = 1 << (self.positions.len() - 1);
+        let mut state = !0u64;
+        // with $, the match must end at the last char
+        let mut matched = false;
+
+        let mut pos = 0;
+        while let Some((c, len)) = char_at(bytes, pos) {
+            self.stats.bytes_scanned += len;
+            // shifting in a 0 lets a new match start at this char.
+            // With ^, only the first char can start a match
+            state <<= 1;
+            if self.start_anchor && pos > 0 {
+                state |= 1;
+            }
+            state |= self.mask(c);
+            pos += len;
+
+            matched = state & match_bit == 0;
+            if matched && !self.end_anchor {
+                return true;
+            }
+        }
+        matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::{regex_lexer::RegexLexer, regex_parser::RegexParser};
+
+    use super::*;
+
+    #[rstest]
+    #[case("ab", true)]
+    #[case("(a(b))\\de", true)]
+    #[case("[^abc]", true)]
+    #[case("\\d{3}", true)]
+    #[case("^a.b$", true)]
+    #[case("a?b", false)]
+    #[case("a+", false)]
+    #[case("a|b", false)]
+    #[case("^$", false)]
+    fn test_shift_or_support(#[case] pat: &str, #[case] expected: bool) -> anyhow::Result<()> {
+        let lexer = RegexLexer::new(pat);
+        let mut parser = RegexParser::new(lexer)?;
+        let node = parser.build_ast(0)?;
+
+        assert_eq!(ShiftOr::new(&node).is_some(), expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("(a(b))\\de\\w.f", "ab5e_%f", true)]
+    #[case("d45", "ded45h_", true)]
+    #[case("Ap[^pb]le", "Apple is good", false)]
+    #[case("Ap[^ab]le", "Apple is good", true)]
+    #[case("[xyz]\\d{3}", "abc y123", true)]
+    #[case("[xyz]\\d{3}", "abc y12a", false)]
+    #[case("[^a-y]{2}", "abyz", false)]
+    #[case("[^a-y]{2}", "abyzz", true)]
+    #[case("^aa", "baa", false)]
+    #[case("^aa", "aab", true)]
+    #[case("aa$", "aaaaab", false)]
+    #[case("aa$", "baaa", true)]
+    #[case("é.", "caféé", true)]
+    #[case("a.b$", "xa\u{fffd}b", true)]
+    fn test_shift_or(
+        #[case] pat: &str,
+        #[case] input: &str,
+        #[case] expected: bool,
+    ) -> anyhow::Result<()> {
+        let lexer = RegexLexer::new(pat);
+        let mut parser = RegexParser::new(lexer)?;
+        let node = parser.build_ast(0)?;
+
+        let mut shift_or = ShiftOr::new(&node).expect("pattern should be supported");
+        assert_eq!(shift_or.is_match(input.as_bytes()), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shift_or_invalid_utf8() -> anyhow::Result<()> {
+        let node = RegexParser::new(RegexLexer::new("a.b$"))?.build_ast(0)?;
+        let mut shift_or = ShiftOr::new(&node).expect("pattern should be supported");
+        assert!(shift_or.is_match(b"xa\xffb"));
+        assert_eq!(shift_or.stats().bytes_scanned, 4);
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+/// Set of integers in [0, capacity) with O(1) insert, lookup and clear,
+/// without hashing (Briggs & Torczon).
+/// `dense` holds the members in insertion order and `sparse` maps a value to its
+/// index in `dense`. A value is a member if both arrays agree, so clearing the set
+/// only resets the length.
+#[derive(Debug, Clone)]
+pub struct SparseSet {
+    dense: Vec<usize>,
+    sparse: Vec<usize>,
+    len: usize,
+}
+
+impl SparseSet {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            dense: vec![0; capacity],
+            sparse: vec![0; capacity],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Inserts the value, returns false if it was already present
+    pub fn insert(&mut self, value: usize) -> bool {
+        if self.contains(value) {
+            return false;
+        }
+        self.dense[self.len] = value;
+        self.sparse[value] = self.len;
+        self.len += 1;
+        true
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        let index = self.sparse[value];
+        index < self.len && self.dense[index] == value
+    }
+
+    /// Value at the given index, in insertion order
+    pub fn get(&self, index: usize) -> usize {
+        self.dense[index]
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparse_set() {
+        let mut set = SparseSet::new(10);
+        assert!(set.is_empty());
+
+        assert!(set.insert(7));
+        assert!(set.insert(2));
+        assert!(!set.insert(7));
+        assert_eq!(set.len(), 2);
+        assert_eq!((set.get(0), set.get(1)), (7, 2));
+        assert!(set.contains(2));
+        assert!(!set.contains(3));
+
+        set.clear();
+        assert!(set.is_empty());
+        assert!(!set.contains(7));
+
+        assert!(set.insert(3));
+        assert!(set.contains(3));
+        assert!(!set.contains(2));
+    }
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+
+/// Counters collected by the searcher and the engines while searching
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub files_searched: usize,
+    /// files with at least a selected line
+    pub files_with_matches: usize,
+    /// selected lines of all the files
+    pub matched_lines: usize,
+    /// bytes of input read by the searcher
+    pub bytes_searched: usize,
+    /// bytes of input read by the engine
+    pub bytes_scanned: usize,
+    /// chars jumped over by the literal search without being compared
+    pub prefilter_skips: usize,
+    /// threads added by the Pike VM
+    pub states_touched: usize,
+    /// deepest stack used while exploring alternatives
+    pub backtrack_depth: usize,
+}
+
+impl Stats {
+    /// Adds the counters of another search
+    pub fn merge(&mut self, other: &Stats) {
+        self.files_searched += other.files_searched;
+        self.files_with_matches += other.files_with_matches;
+        self.matched_lines += other.matched_lines;
+        self.bytes_searched += other.bytes_searched;
+        self.bytes_scanned += other.bytes_scanned;
+        self.prefilter_skips += other.prefilter_skips;
+        self.states_touched += other.states_touched;
+        self.backtrack_depth = self.backtrack_depth.max(other.backtrack_depth);
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} files searched", self.files_searched)?;
+        writeln!(f, "{} files with matches", self.files_with_matches)?;
+        writeln!(f, "{} matched lines", self.matched_lines)?;
+        writeln!(f, "{} bytes searched", self.bytes_searched)?;
+        writeln!(f, "{} bytes scanned", self.bytes_scanned)?;
+        writeln!(f, "{} prefilter skips", self.prefilter_skips)?;
+        writeln!(f, "{} NFA states touched", self.states_touched)?;
+        write!(f, "{} max backtrack depth", self.backtrack_depth)
+    }
+}
@@ -0,0 +1,112 @@
+/// Char starting at the byte offset, with its length in bytes, None at the end
+/// of the text. An invalid UTF-8 sequence is read as a single U+FFFD, as when
+/// decoding the whole text, so that an engine can start anywhere without
+/// decoding the text before
+pub(crate) fn char_at(bytes: &[u8], pos: usize) -> Option<(char, usize)> {
+    if let Some(&byte) = bytes.get(pos).filter(|byte| byte.is_ascii()) {
+        return Some((char::from(byte), 1));
+    }
+    // a char is at most 4 bytes long
+    let window = bytes.get(pos..bytes.len().min(pos.saturating_add(4)))?;
+    let chunk = window.utf8_chunks().next()?;
+    match chunk.valid().chars().next() {
+        Some(c) => Some((c, c.len_utf8())),
+        None => Some((char::REPLACEMENT_CHARACTER, chunk.invalid().len())),
+    }
+}
+
+/// Char ending at the byte offset, with its length in bytes, None at the start
+/// of the text
+pub(crate) fn char_before(bytes: &[u8], pos: usize) -> Option<(char, usize)> {
+    let byte = *bytes.get(pos.checked_sub(1)?)?;
+    if byte.is_ascii() {
+        return Some((char::from(byte), 1));
+    }
+    // the first byte of the char is the closest one that is not a
+    // continuation byte, unless the sequence it starts ends elsewhere
+    for len in 1..=pos.min(4) {
+        if bytes[pos - len] & 0xc0 != 0x80 {
+            match char_at(bytes, pos - len) {
+                Some((c, char_len)) if char_len == len => return Some((c, len)),
+                _ => break,
+            }
+        }
+    }
+    Some((char::REPLACEMENT_CHARACTER, 1))
+}
+
+/// First char boundary at or after the byte offset, which moves an offset in
+/// the middle of a char to its end
+pub(crate) fn boundary_from(bytes: &[u8], pos: usize) -> usize {
+    for back in 1..=pos.min(3) {
+        if bytes
+            .get(pos - back)
+            .is_some_and(|byte| byte & 0xc0 != 0x80)
+        {
+            return match char_at(bytes, pos - back) {
+                Some((_, len)) if len > back => pos - back + len,
+                _ => pos,
+            };
+        }
+    }
+    pos
+}
+
+/// Byte offsets of the chars from pos, and the end of the text
+pub(crate) fn positions(bytes: &[u8], pos: usize) -> impl Iterator<Item = usize> + '_ {
+    std::iter::successors(Some(pos), |&pos| {
+        char_at(bytes, pos).map(|(_, len)| pos + len)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(b"a\xc3\xa9", 1, Some(('é', 2)))]
+    #[case(b"a\xc3\xa9", 3, None)]
+    #[case(b"\xe2\x82a", 0, Some(('\u{fffd}', 2)))]
+    #[case(b"\xffa", 0, Some(('\u{fffd}', 1)))]
+    #[case(b"\xf0\x9f\x98\x80", 0, Some(('😀', 4)))]
+    fn test_char_at(
+        #[case] bytes: &[u8],
+        #[case] pos: usize,
+        #[case] expected: Option<(char, usize)>,
+    ) {
+        assert_eq!(char_at(bytes, pos), expected);
+    }
+
+    #[rstest]
+    #[case(b"a\xc3\xa9", 3, Some(('é', 2)))]
+    #[case(b"a\xc3\xa9", 1, Some(('a', 1)))]
+    #[case(b"a\xc3\xa9", 0, None)]
+    #[case(b"\xe2\x82", 2, Some(('\u{fffd}', 2)))]
+    #[case(b"a\x82", 2, Some(('\u{fffd}', 1)))]
+    #[case(b"\xf0\x9f\x98\x80", 4, Some(('😀', 4)))]
+    fn test_char_before(
+        #[case] bytes: &[u8],
+        #[case] pos: usize,
+        #[case] expected: Option<(char, usize)>,
+    ) {
+        assert_eq!(char_before(bytes, pos), expected);
+    }
+
+    #[rstest]
+    #[case(b"a\xc3\xa9b", 2, 3)]
+    #[case(b"a\xc3\xa9b", 3, 3)]
+    #[case(b"a\xc3\xa9b", 1, 1)]
+    #[case(b"\xf0\x9f\x98\x80", 3, 4)]
+    #[case(b"a\x82\x82", 2, 2)]
+    fn test_boundary_from(#[case] bytes: &[u8], #[case] pos: usize, #[case] expected: usize) {
+        assert_eq!(boundary_from(bytes, pos), expected);
+    }
+
+    #[test]
+    fn test_positions() {
+        let positions = positions("aé😀".as_bytes(), 1).collect::<Vec<_>>();
+        assert_eq!(positions, [1, 3, 7]);
+    }
+}
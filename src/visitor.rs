@@ -0,0 +1,105 @@
+use crate::regex_parser::Node;
+
+/// Analysis of a pattern, called by walk on each node of its AST
+pub trait Visitor {
+    /// Called before the children of the node, which are skipped if it
+    /// returns false
+    fn visit_pre(&mut self, _node: &Node) -> bool {
+        true
+    }
+
+    /// Called after the children of the node
+    fn visit_post(&mut self, _node: &Node) {}
+}
+
+/// Visits the nodes depth first, in the order of the pattern. The stack is
+/// explicit so that deeply nested patterns do not overflow
+pub fn walk(node: &Node, visitor: &mut impl Visitor) {
+    // a node is popped a first time to visit it, then after its children
+    let mut stack = vec![(node, false)];
+    while let Some((node, children_visited)) = stack.pop() {
+        if children_visited {
+            visitor.visit_post(node);
+            continue;
+        }
+        stack.push((node, true));
+        if visitor.visit_pre(node) {
+            let children = node.children();
+            stack.extend(children.into_iter().rev().map(|child| (child, false)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::regex_lexer::{Dialect, RegexLexer};
+    use crate::regex_parser::RegexParser;
+
+    use super::*;
+
+    /// Literals of the pattern, outside the lookarounds
+    #[derive(Default)]
+    struct Literals(String);
+
+    impl Visitor for Literals {
+        fn visit_pre(&mut self, node: &Node) -> bool {
+            if let Node::Literal(c) = node {
+                self.0.push(*c);
+            }
+            !matches!(node, Node::LookAround { .. })
+        }
+    }
+
+    /// Deepest nesting of the quantifiers
+    #[derive(Default)]
+    struct QuantifierDepth {
+        depth: usize,
+        max_depth: usize,
+    }
+
+    impl Visitor for QuantifierDepth {
+        fn visit_pre(&mut self, node: &Node) -> bool {
+            if let Node::Quantifier { .. } = node {
+                self.depth += 1;
+                self.max_depth = self.max_depth.max(self.depth);
+            }
+            true
+        }
+
+        fn visit_post(&mut self, node: &Node) {
+            if let Node::Quantifier { .. } = node {
+                self.depth -= 1;
+            }
+        }
+    }
+
+    fn parse(pat: &str) -> anyhow::Result<Node> {
+        let lexer = RegexLexer::with_dialect(pat, Dialect::Perl);
+        Ok(RegexParser::new(lexer)?.build_ast(0)?)
+    }
+
+    #[rstest]
+    #[case("ab|c(d)", "abcd")]
+    #[case("a(?=bc)d", "ad")]
+    #[case("[xy]\\d+z", "z")]
+    fn test_walk_literals(#[case] pat: &str, #[case] expected: &str) -> anyhow::Result<()> {
+        let mut literals = Literals::default();
+        walk(&parse(pat)?, &mut literals);
+        assert_eq!(literals.0, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("abc", 0)]
+    #[case("a+b*", 1)]
+    #[case("(a(b+)*)+", 3)]
+    fn test_walk_depth(#[case] pat: &str, #[case] expected: usize) -> anyhow::Result<()> {
+        let mut depth = QuantifierDepth::default();
+        walk(&parse(pat)?, &mut depth);
+        assert_eq!(depth.max_depth, expected);
+        assert_eq!(depth.depth, 0);
+        Ok(())
+    }
+}
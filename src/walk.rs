@@ -0,0 +1,552 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::UNIX_EPOCH;
+
+use crate::glob::Glob;
+use crate::ignore::Ignore;
+
+/// Decides which files are searched, from the --include and --exclude globs
+/// in the order of the command line, and which directories are walked
+#[derive(Debug, Default)]
+pub struct FileFilter {
+    /// each glob, with true for --include
+    rules: Vec<(Glob, bool)>,
+    /// --exclude-dir globs
+    excluded_dirs: Vec<Glob>,
+    /// globs of the types selected with -t, any file if there is none
+    types: Vec<Glob>,
+    /// globs of the types excluded with -T
+    excluded_types: Vec<Glob>,
+}
+
+/// Base name of the path, or the whole path if it has none (such as ..)
+fn base_name(path: &Path) -> std::borrow::Cow<'_, str> {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+}
+
+impl FileFilter {
+    pub fn add(&mut self, glob: Glob, include: bool) {
+        self.rules.push((glob, include));
+    }
+
+    pub fn exclude_dir(&mut self, glob: Glob) {
+        self.excluded_dirs.push(glob);
+    }
+
+    /// Adds the globs of a file type, selected with -t or excluded with -T
+    pub fn add_type(&mut self, globs: Vec<Glob>, include: bool) {
+        if include {
+            self.types.extend(globs);
+        } else {
+            self.excluded_types.extend(globs);
+        }
+    }
+
+    /// The directories whose base name matches an --exclude-dir glob are not
+    /// walked at all
+    pub fn is_dir_included(&self, path: &Path) -> bool {
+        let name = base_name(path);
+        !self.excluded_dirs.iter().any(|glob| glob.is_match(&name))
+    }
+
+    /// As in GNU grep, the last glob matching the base name of the file wins.
+    /// When none matches, the file is searched unless the first glob is an
+    /// --include. The file must also be of a type of -t, and of none of -T
+    pub fn is_included(&self, path: &Path) -> bool {
+        let name = base_name(path);
+        let is_match = |globs: &[Glob]| globs.iter().any(|glob| glob.is_match(&name));
+        if is_match(&self.excluded_types) || (!self.types.is_empty() && !is_match(&self.types)) {
+            return false;
+        }
+        match self
+            .rules
+            .iter()
+            .rev()
+            .find(|(glob, _)| glob.is_match(&name))
+        {
+            Some((_, include)) => *include,
+            None => self.rules.first().is_none_or(|(_, include)| !include),
+        }
+    }
+}
+
+/// Order of the files found in a directory tree (--sort)
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum SortBy {
+    /// by path, the order of the walk
+    #[default]
+    Path,
+    /// oldest modification first
+    Modified,
+    /// smallest first
+    Size,
+}
+
+impl SortBy {
+    /// Key of the file, None when its metadata can not be read
+    fn key(self, path: &Path) -> Option<u128> {
+        let metadata = fs::metadata(path).ok()?;
+        match self {
+            SortBy::Path => Some(0),
+            SortBy::Modified => {
+                let modified = metadata.modified().ok()?;
+                Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+            }
+            SortBy::Size => Some(metadata.len().into()),
+        }
+    }
+}
+
+/// What a recursive search walks
+#[derive(Debug, Default)]
+pub struct WalkOptions {
+    pub filter: FileFilter,
+    /// skip the paths ignored by the .gitignore and .ignore files, by the
+    /// global git excludes and by ignore_rules
+    pub use_ignore_files: bool,
+    /// content of the --ignore-file files, with the syntax of .gitignore. They
+    /// are applied from the root of the walk, after the global git excludes
+    pub ignore_rules: String,
+    /// follow the symbolic links found in the tree
+    pub follow_links: bool,
+    /// deepest level of the tree searched, 1 for the files of the root only
+    pub max_depth: Option<usize>,
+    /// search the files and directories whose name starts with a dot, which
+    /// are skipped otherwise
+    pub hidden: bool,
+    /// skip the files larger than this number of bytes
+    pub max_filesize: Option<u64>,
+    pub sort: SortBy,
+}
+
+/// Parses a size in bytes, with an optional K, M or G suffix for powers of 1024
+/// (--max-filesize)
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let (digits, multiplier) = match size.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&size[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&size[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&size[..i], 1 << 30),
+        _ => (size, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| {
+            format!("invalid size '{size}', expected a number with an optional K, M or G suffix")
+        })
+}
+
+/// Identifies a directory whatever the path leading to it
+#[cfg(unix)]
+type DirId = (u64, u64);
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+/// Device and inode of the directory
+#[cfg(unix)]
+fn dir_id(path: &Path) -> io::Result<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_id(path: &Path) -> io::Result<DirId> {
+    fs::canonicalize(path)
+}
+
+/// Directories from the root to a directory of the walk, to detect the links
+/// to one of them that would make the walk loop forever
+#[derive(Debug)]
+struct Ancestors {
+    id: DirId,
+    parent: Option<Rc<Ancestors>>,
+}
+
+impl Ancestors {
+    fn contains(&self, id: &DirId) -> bool {
+        self.id == *id
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.contains(id))
+    }
+}
+
+/// Path left to visit
+struct Pending {
+    path: PathBuf,
+    is_dir: bool,
+    /// ignore rules of the parent directory
+    ignore: Option<Rc<Ignore>>,
+    /// directories above the path, when following links
+    ancestors: Option<Rc<Ancestors>>,
+    /// 0 for the root
+    depth: usize,
+}
+
+/// Regular files of a directory tree, sorted by name, depth first.
+/// As with GNU grep -r, the symbolic links found in the tree are not followed
+/// unless asked
+pub struct Walk<'a> {
+    options: &'a WalkOptions,
+    /// the next one last
+    stack: Vec<Pending>,
+}
+
+impl<'a> Walk<'a> {
+    pub fn new(root: &Path, options: &'a WalkOptions) -> Self {
+        let ignore = options
+            .use_ignore_files
+            .then(|| Ignore::global(root, &options.ignore_rules));
+        Self {
+            options,
+            stack: vec![Pending {
+                path: root.to_path_buf(),
+                is_dir: true,
+                ignore,
+                ancestors: None,
+                depth: 0,
+            }],
+        }
+    }
+
+    /// Adds the entries of the directory to visit
+    fn read_dir(&mut self, dir: Pending) -> anyhow::Result<()> {
+        if self.options.max_depth.is_some_and(|max| dir.depth >= max) {
+            return Ok(());
+        }
+        let ancestors = if self.options.follow_links {
+            let id = dir_id(&dir.path)?;
+            if dir
+                .ancestors
+                .as_ref()
+                .is_some_and(|ancestors| ancestors.contains(&id))
+            {
+                anyhow::bail!("recursive directory loop");
+            }
+            Some(Rc::new(Ancestors {
+                id,
+                parent: dir.ancestors,
+            }))
+        } else {
+            None
+        };
+        let ignore = dir
+            .ignore
+            .map(|ignore| ignore.child(&dir.path))
+            .transpose()?;
+
+        let mut entries = fs::read_dir(&dir.path)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries.into_iter().rev() {
+            if !self.options.hidden && entry.file_name().as_encoded_bytes().starts_with(b".") {
+                continue;
+            }
+            let path = entry.path();
+            let (is_dir, is_file) = match entry.file_type()? {
+                file_type if file_type.is_symlink() && self.options.follow_links => {
+                    match fs::metadata(&path) {
+                        Ok(metadata) => (metadata.is_dir(), metadata.is_file()),
+                        // kept as a file, so that opening it reports the error
+                        Err(_) => (false, true),
+                    }
+                }
+                file_type => (file_type.is_dir(), file_type.is_file()),
+            };
+            let is_included = if is_dir {
+                self.options.filter.is_dir_included(&path)
+            } else {
+                is_file && self.options.filter.is_included(&path)
+            };
+            if !is_included
+                || (is_file && self.is_too_large(&path))
+                || ignore
+                    .as_ref()
+                    .is_some_and(|ignore| ignore.is_ignored(&path, is_dir))
+            {
+                continue;
+            }
+            self.stack.push(Pending {
+                path,
+                is_dir,
+                ignore: ignore.clone(),
+                ancestors: ancestors.clone(),
+                depth: dir.depth + 1,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Walk<'_> {
+    /// Files of the whole tree in the order of --sort. Unlike the walk, nothing
+    /// is returned before the whole tree is read. The errors come first, and
+    /// the files with the same key stay in path order
+    pub fn sorted(self) -> Vec<anyhow::Result<PathBuf>> {
+        let sort = self.options.sort;
+        let mut entries = self.collect::<Vec<_>>();
+        if sort != SortBy::Path {
+            entries.sort_by_cached_key(|entry| {
+                entry.as_ref().ok().map(|path| sort.key(path).unwrap_or(0))
+            });
+        }
+        entries
+    }
+
+    /// A file whose size is unknown is kept, so that opening it reports the error
+    fn is_too_large(&self, path: &Path) -> bool {
+        self.options
+            .max_filesize
+            .is_some_and(|max| fs::metadata(path).is_ok_and(|metadata| metadata.len() > max))
+    }
+}
+
+impl Iterator for Walk<'_> {
+    type Item = anyhow::Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(pending) = self.stack.pop() {
+            if !pending.is_dir {
+                return Some(Ok(pending.path));
+            }
+            let path = pending.path.clone();
+            // an unreadable directory does not stop the walk
+            if let Err(err) = self.read_dir(pending) {
+                return Some(Err(anyhow::anyhow!("{}: {err}", path.display())));
+            }
+        }
+        None
+    }
+}
+
+/// Name of a file found in the tree, relative to the root
+pub fn relative_name<'p>(path: &'p Path, root: &Path) -> &'p OsStr {
+    path.strip_prefix(root).unwrap_or(path).as_os_str()
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case(&[], "main.rs", true)]
+    #[case(&[("*.rs", true)], "main.rs", true)]
+    #[case(&[("*.rs", true)], "main.js", false)]
+    #[case(&[("*.min.js", false)], "app.min.js", false)]
+    #[case(&[("*.min.js", false)], "app.js", true)]
+    #[case(&[("*.js", true), ("*.min.js", false)], "app.min.js", false)]
+    #[case(&[("*.min.js", false), ("*.js", true)], "app.min.js", true)]
+    #[case(&[("*.min.js", false), ("*.rs", true)], "main.c", true)]
+    #[case(&[("*.rs", true)], "src/main.rs", true)]
+    fn test_file_filter(
+        #[case] rules: &[(&str, bool)],
+        #[case] path: &str,
+        #[case] expected: bool,
+    ) {
+        let mut filter = FileFilter::default();
+        for (glob, include) in rules {
+            filter.add(Glob::new(glob), *include);
+        }
+        assert_eq!(filter.is_included(Path::new(path)), expected);
+    }
+
+    #[rstest]
+    #[case(&[], &[], "main.rs", true)]
+    #[case(&["*.rs"], &[], "main.rs", true)]
+    #[case(&["*.rs"], &[], "main.c", false)]
+    #[case(&["*.rs", "*.c"], &[], "main.c", true)]
+    #[case(&[], &["*.rs"], "main.rs", false)]
+    #[case(&["*.rs"], &["main.*"], "main.rs", false)]
+    fn test_file_filter_types(
+        #[case] types: &[&str],
+        #[case] excluded_types: &[&str],
+        #[case] path: &str,
+        #[case] expected: bool,
+    ) {
+        let mut filter = FileFilter::default();
+        filter.add_type(types.iter().map(|glob| Glob::new(glob)).collect(), true);
+        filter.add_type(
+            excluded_types.iter().map(|glob| Glob::new(glob)).collect(),
+            false,
+        );
+        assert_eq!(filter.is_included(Path::new(path)), expected);
+    }
+
+    #[test]
+    fn test_walk() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(format!("grep-walk-{}", std::process::id()));
+        fs::create_dir_all(root.join("b/c"))?;
+        fs::create_dir_all(root.join("a"))?;
+        for file in ["z.rs", "a/x.rs", "a/y.js", "b/c/w.rs", "b/v.rs", "b/u.rs"] {
+            fs::write(root.join(file), "")?;
+        }
+        fs::write(root.join("b/.gitignore"), "u.rs\n")?;
+
+        let mut options = WalkOptions {
+            use_ignore_files: true,
+            ignore_rules: "/z.rs\n".to_string(),
+            ..Default::default()
+        };
+        options.filter.add(Glob::new("*.rs"), true);
+        options.filter.exclude_dir(Glob::new("c"));
+        let files = Walk::new(&root, &options)
+            .map(|path| Ok(relative_name(&path?, &root).to_owned()))
+            .collect::<anyhow::Result<Vec<_>>>();
+        fs::remove_dir_all(&root)?;
+
+        assert_eq!(files?, ["a/x.rs", "b/v.rs"]);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(false, &["a/x"])]
+    #[case(true, &[".git/config", ".z", "a/.y", "a/x"])]
+    fn test_walk_hidden(#[case] hidden: bool, #[case] expected: &[&str]) -> anyhow::Result<()> {
+        let root =
+            std::env::temp_dir().join(format!("grep-hidden-{}-{hidden}", std::process::id()));
+        fs::create_dir_all(root.join(".git"))?;
+        fs::create_dir_all(root.join("a"))?;
+        for file in [".z", ".git/config", "a/x", "a/.y"] {
+            fs::write(root.join(file), "")?;
+        }
+
+        let options = WalkOptions {
+            hidden,
+            ..Default::default()
+        };
+        let files = Walk::new(&root, &options)
+            .map(|path| Ok(relative_name(&path?, &root).to_owned()))
+            .collect::<anyhow::Result<Vec<_>>>();
+        fs::remove_dir_all(&root)?;
+
+        assert_eq!(files?, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(None, &["a/b/y", "a/x", "z"])]
+    #[case(Some(2), &["a/x", "z"])]
+    #[case(Some(1), &["z"])]
+    #[case(Some(0), &[])]
+    fn test_walk_max_depth(
+        #[case] max_depth: Option<usize>,
+        #[case] expected: &[&str],
+    ) -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(format!(
+            "grep-depth-{}-{}",
+            std::process::id(),
+            max_depth.map_or(-1, |max| max as i64)
+        ));
+        fs::create_dir_all(root.join("a/b"))?;
+        for file in ["z", "a/x", "a/b/y"] {
+            fs::write(root.join(file), "")?;
+        }
+
+        let options = WalkOptions {
+            max_depth,
+            ..Default::default()
+        };
+        let files = Walk::new(&root, &options)
+            .map(|path| Ok(relative_name(&path?, &root).to_owned()))
+            .collect::<anyhow::Result<Vec<_>>>();
+        fs::remove_dir_all(&root)?;
+
+        assert_eq!(files?, expected);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("10", Ok(10))]
+    #[case("2K", Ok(2048))]
+    #[case("10M", Ok(10 << 20))]
+    #[case("1g", Ok(1 << 30))]
+    #[case("M", Err(()))]
+    #[case("1.5M", Err(()))]
+    #[case("-1", Err(()))]
+    fn test_parse_size(#[case] size: &str, #[case] expected: Result<u64, ()>) {
+        assert_eq!(parse_size(size).map_err(|_| ()), expected);
+    }
+
+    #[test]
+    fn test_walk_max_filesize() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(format!("grep-filesize-{}", std::process::id()));
+        fs::create_dir_all(&root)?;
+        fs::write(root.join("big"), "abcd")?;
+        fs::write(root.join("small"), "abc")?;
+
+        let options = WalkOptions {
+            max_filesize: Some(3),
+            ..Default::default()
+        };
+        let files = Walk::new(&root, &options)
+            .map(|path| Ok(relative_name(&path?, &root).to_owned()))
+            .collect::<anyhow::Result<Vec<_>>>();
+        fs::remove_dir_all(&root)?;
+
+        assert_eq!(files?, ["small"]);
+        Ok(())
+    }
+
+    #[rstest]
+    #[case(SortBy::Path, &["a", "b", "c"])]
+    #[case(SortBy::Size, &["b", "c", "a"])]
+    fn test_walk_sort(#[case] sort: SortBy, #[case] expected: &[&str]) -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(format!("grep-sort-{}-{sort:?}", std::process::id()));
+        fs::create_dir_all(&root)?;
+        for (file, content) in [("a", "abc"), ("b", "a"), ("c", "ab")] {
+            fs::write(root.join(file), content)?;
+        }
+
+        let options = WalkOptions {
+            sort,
+            ..Default::default()
+        };
+        let files = Walk::new(&root, &options)
+            .sorted()
+            .into_iter()
+            .map(|path| Ok(relative_name(&path?, &root).to_owned()))
+            .collect::<anyhow::Result<Vec<_>>>();
+        fs::remove_dir_all(&root)?;
+
+        assert_eq!(files?, expected);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walk_follow_links() -> anyhow::Result<()> {
+        let root = std::env::temp_dir().join(format!("grep-follow-{}", std::process::id()));
+        fs::create_dir_all(root.join("a"))?;
+        fs::create_dir_all(root.join("b"))?;
+        fs::write(root.join("b/x"), "")?;
+        std::os::unix::fs::symlink("../b", root.join("a/link"))?;
+        std::os::unix::fs::symlink("..", root.join("b/loop"))?;
+
+        let mut options = WalkOptions::default();
+        let files = Walk::new(&root, &options)
+            .map(|path| Ok(relative_name(&path?, &root).to_owned()))
+            .collect::<anyhow::Result<Vec<_>>>();
+        options.follow_links = true;
+        let followed = Walk::new(&root, &options)
+            .map(|path| path.map(|path| relative_name(&path, &root).to_owned()))
+            .collect::<Vec<_>>();
+        fs::remove_dir_all(&root)?;
+
+        assert_eq!(files?, ["b/x"]);
+        // the loops are reported, and do not stop the walk
+        let errors = followed.iter().filter(|path| path.is_err()).count();
+        let followed = followed.into_iter().flatten().collect::<Vec<_>>();
+        assert_eq!(followed, ["a/link/x", "b/x"]);
+        assert_eq!(errors, 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// State of a file when it was last searched
+#[derive(Debug)]
+struct WatchedFile {
+    /// length of the part searched
+    searched_len: u64,
+    modified: Option<SystemTime>,
+    /// number of lines of the part searched, counted when lines are appended
+    nb_lines: Option<usize>,
+}
+
+/// How a file changed since it was last searched
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    /// a new file, or a file whose content was replaced, to search whole
+    Rewritten,
+    /// complete lines appended to the part already searched, which has
+    /// nb_lines lines and offset bytes
+    Appended {
+        content: Vec<u8>,
+        nb_lines: usize,
+        offset: usize,
+    },
+}
+
+/// Files searched again when they change (--watch). As with tail -f, the
+/// files are polled, and the lines appended to a file are searched alone
+#[derive(Debug, Default)]
+pub struct Watch {
+    files: HashMap<PathBuf, WatchedFile>,
+}
+
+impl Watch {
+    /// Records a file searched whole, which is searched again once it changes
+    pub fn add(&mut self, path: &Path) -> io::Result<()> {
+        let metadata = fs::metadata(path)?;
+        let file = WatchedFile {
+            searched_len: metadata.len(),
+            modified: metadata.modified().ok(),
+            nb_lines: None,
+        };
+        self.files.insert(path.to_path_buf(), file);
+        Ok(())
+    }
+
+    /// Returns how the file changed since it was last searched, if it did,
+    /// and records it as searched. An incomplete last line is left for later
+    pub fn poll(&mut self, path: &Path) -> io::Result<Option<Change>> {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                // a file created again is new
+                self.files.remove(path);
+                return Err(err);
+            }
+        };
+        let (len, modified) = (metadata.len(), metadata.modified().ok());
+        let Some(file) = self.files.get_mut(path) else {
+            self.add(path)?;
+            return Ok(Some(Change::Rewritten));
+        };
+        if len == file.searched_len && modified == file.modified {
+            return Ok(None);
+        }
+        if len <= file.searched_len {
+            // truncated, or rewritten in place
+            *file = WatchedFile {
+                searched_len: len,
+                modified,
+                nb_lines: None,
+            };
+            return Ok(Some(Change::Rewritten));
+        }
+
+        let mut reader = File::open(path)?;
+        let nb_lines = match file.nb_lines {
+            Some(nb_lines) => nb_lines,
+            None => {
+                let mut searched = Vec::new();
+                (&mut reader)
+                    .take(file.searched_len)
+                    .read_to_end(&mut searched)?;
+                count_lines(&searched)
+            }
+        };
+        let offset = file.searched_len;
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut content = Vec::new();
+        reader.take(len - offset).read_to_end(&mut content)?;
+        // the incomplete last line is searched once complete
+        let complete = content
+            .iter()
+            .rposition(|&byte| byte == b'\n')
+            .map_or(0, |end| end + 1);
+        content.truncate(complete);
+
+        file.modified = modified;
+        file.nb_lines = Some(nb_lines);
+        if content.is_empty() {
+            return Ok(None);
+        }
+        file.searched_len += content.len() as u64;
+        file.nb_lines = Some(nb_lines + count_lines(&content));
+        Ok(Some(Change::Appended {
+            content,
+            nb_lines,
+            offset: offset as usize,
+        }))
+    }
+}
+
+/// Number of line terminators
+fn count_lines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&byte| byte == b'\n').count()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_watch() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("grep-watch-{}", std::process::id()));
+        fs::write(&path, "a\nb\n")?;
+        let mut watch = Watch::default();
+        watch.add(&path)?;
+        let unchanged = watch.poll(&path);
+
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        file.write_all(b"c\nd")?;
+        let appended = watch.poll(&path);
+        file.write_all(b"\n")?;
+        let completed = watch.poll(&path);
+        fs::write(&path, "e\n")?;
+        let rewritten = watch.poll(&path);
+        fs::remove_file(&path)?;
+
+        assert_eq!(unchanged?, None);
+        let expected = Change::Appended {
+            content: b"c\n".to_vec(),
+            nb_lines: 2,
+            offset: 4,
+        };
+        assert_eq!(appended?, Some(expected));
+        let expected = Change::Appended {
+            content: b"d\n".to_vec(),
+            nb_lines: 3,
+            offset: 6,
+        };
+        assert_eq!(completed?, Some(expected));
+        assert_eq!(rewritten?, Some(Change::Rewritten));
+        Ok(())
+    }
+}